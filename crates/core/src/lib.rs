@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy, Default, PartialOrd, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Point {
     pub line: usize,
     pub column: usize,
@@ -0,0 +1,253 @@
+use std::ops::Range;
+
+use crate::{Buffer, Editor, Point};
+
+/// The brackets that pair in any text. Others, as the `<` and `>` of generics, only pair where
+/// the syntax tree says they do.
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+const QUOTES: [char; 3] = ['"', '\'', '`'];
+
+impl Buffer {
+    /// The char offset of the bracket that pairs with the one at a char offset. The syntax tree
+    /// pairs the brackets of the code, skipping those in strings and comments. Without one,
+    /// brackets are paired by counting them, those in strings only with each other.
+    pub fn matching_bracket(&self, offset: usize) -> Option<usize> {
+        self.matching_bracket_within(offset, 0..self.contents.len_chars())
+    }
+
+    /// The bracket that pairs with the one at a char offset, as [`Buffer::matching_bracket`], but
+    /// only counting brackets in a range of chars, for when it is looked for on every frame.
+    pub(crate) fn matching_bracket_within(
+        &self,
+        offset: usize,
+        within: Range<usize>,
+    ) -> Option<usize> {
+        let contents = &self.contents;
+        let c = contents.get_char(offset)?;
+        if let Some(syntax) = self.syntax.as_deref() {
+            let matching = syntax.matching_bracket(contents.char_to_byte(offset))?;
+            return Some(contents.byte_to_char(matching));
+        }
+        let (forward, open, close) = BRACKETS.iter().find_map(|&(open, close)| match c {
+            _ if c == open => Some((true, open, close)),
+            _ if c == close => Some((false, open, close)),
+            _ => None,
+        })?;
+        self.scan_brackets(offset, within, forward, (open, close))
+    }
+
+    /// Whether a char offset is inside a string or a comment, where brackets and quotes are
+    /// text. Without a syntax tree, only strings that end on their line are told apart.
+    fn is_literal(&self, offset: usize) -> bool {
+        let contents = &self.contents;
+        if let Some(syntax) = self.syntax.as_deref() {
+            return syntax.is_literal(contents.char_to_byte(offset));
+        }
+        let line = contents.char_to_line(offset);
+        let before = contents
+            .slice(contents.line_to_char(line)..offset)
+            .to_string();
+        let mut quote = None;
+        let mut chars = before.chars();
+        while let Some(c) = chars.next() {
+            match quote {
+                Some(_) if c == '\\' => _ = chars.next(),
+                Some(open) if c == open => quote = None,
+                None if QUOTES.contains(&c) => quote = Some(c),
+                _ => (),
+            }
+        }
+        quote.is_some()
+    }
+
+    /// The offset of the bracket that closes, or opens, the one at an offset, counting the pairs
+    /// of the same brackets in between, up to the ends of `within`. Only the brackets that are in
+    /// a string as much as the one at the offset count.
+    fn scan_brackets(
+        &self,
+        offset: usize,
+        within: Range<usize>,
+        forward: bool,
+        (open, close): (char, char),
+    ) -> Option<usize> {
+        let contents = &self.contents;
+        let literal = self.is_literal(offset);
+        let mut depth = 0usize;
+        let check = |(offset, c): (usize, char)| {
+            let is_bracket = (c == open || c == close) && self.is_literal(offset) == literal;
+            match c {
+                _ if !is_bracket => (),
+                _ if c == open && forward || c == close && !forward => depth += 1,
+                _ => depth -= 1,
+            }
+            (depth == 0).then_some(offset)
+        };
+        match forward {
+            true => (offset..within.end)
+                .zip(contents.chars_at(offset))
+                .find_map(check),
+            false => (within.start..=offset)
+                .rev()
+                .zip(contents.chars_at(offset + 1).reversed())
+                .find_map(check),
+        }
+    }
+}
+
+impl Editor {
+    /// The bracket under the cursor and the one it pairs with, to show them together. Brackets
+    /// are only counted in the given lines, as those on the screen.
+    pub fn matching_brackets(
+        &self,
+        buffer: &Buffer,
+        lines: Range<usize>,
+    ) -> Option<(Point, Point)> {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(self.cursor);
+        let line_to_char = |line: usize| contents.line_to_char(line.min(contents.len_lines()));
+        let within = line_to_char(lines.start)..line_to_char(lines.end);
+        let matching = buffer.matching_bracket_within(offset, within)?;
+        Some((self.cursor, contents.char_offset_to_point(matching)))
+    }
+
+    /// Goes to the bracket that pairs with the first bracket from the cursor on, on its line, as
+    /// `%` does.
+    pub(crate) fn cursor_jump_matching_bracket(&mut self, buffer: &Buffer) {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(self.cursor);
+        let line_end = offset - self.cursor.column + contents.line_len(self.cursor.line);
+        let matching = (offset..line_end).find_map(|offset| buffer.matching_bracket(offset));
+        if let Some(matching) = matching {
+            self.cursor = contents.char_offset_to_point(matching);
+        }
+    }
+
+    /// Types a char in insert mode where it may open or close a pair. An opening bracket or a
+    /// quote brings its closing one along, unless it is typed in a string or a comment or right
+    /// before a word. A closing one that is already there is typed over. Returns whether the char
+    /// was typed.
+    pub(crate) fn insert_pair(&mut self, buffer: &mut Buffer, c: char) -> bool {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(self.cursor);
+        let line_end = offset - self.cursor.column + contents.line_len(self.cursor.line);
+        let before = offset
+            .checked_sub(1)
+            .and_then(|offset| contents.get_char(offset));
+        let after = contents.get_char(offset).filter(|_| offset < line_end);
+
+        let is_closing = BRACKETS.iter().any(|(_, close)| *close == c) || QUOTES.contains(&c);
+        if is_closing && after == Some(c) {
+            self.cursor.move_next_column();
+            return true;
+        }
+
+        let close = match BRACKETS.iter().find(|(open, _)| *open == c) {
+            Some((_, close)) => *close,
+            // a quote right after a word is an apostrophe, or a suffix such as a lifetime's.
+            None if QUOTES.contains(&c)
+                && before.map_or(true, |c| c.is_whitespace() || "([{,=:;".contains(c)) =>
+            {
+                c
+            }
+            None => return false,
+        };
+        let closes = |c: char| c.is_whitespace() || BRACKETS.iter().any(|(_, close)| *close == c);
+        if !after.map_or(true, closes) || buffer.is_literal(offset) {
+            return false;
+        }
+        buffer.insert(offset, &format!("{}{}", c, close));
+        self.cursor.move_next_column();
+        true
+    }
+
+    /// Deletes an empty pair around the cursor in insert mode, as if the closing half had never
+    /// been there. Returns whether there was one.
+    pub(crate) fn delete_pair(&mut self, buffer: &mut Buffer) -> bool {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(self.cursor);
+        if self.cursor.column == 0 || self.cursor.column >= contents.line_len(self.cursor.line) {
+            return false;
+        }
+        let (before, after) = (contents.char(offset - 1), contents.char(offset));
+        let is_pair =
+            BRACKETS.contains(&(before, after)) || QUOTES.contains(&before) && before == after;
+        if !is_pair {
+            return false;
+        }
+        buffer.remove(offset - 1..offset + 1);
+        self.cursor.move_prev_column();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferId, EditorCommand, EditorId, Mode, Motion, Operator, Target};
+
+    fn setup(text: &str, line: usize, column: usize) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.cursor = Point { line, column };
+        (editor, buffer)
+    }
+
+    #[test]
+    fn jumps_between_brackets() {
+        let (mut editor, mut buffer) = setup("f(a, [b]) {\n    g(c)\n}\n", 0, 0);
+        let jump = EditorCommand::Motion(Motion::MatchingBracket, 1);
+        editor.command(&mut buffer, jump.clone());
+        assert_eq!(editor.cursor, Point { line: 0, column: 8 });
+        editor.command(&mut buffer, jump.clone());
+        assert_eq!(editor.cursor, Point { line: 0, column: 1 });
+        editor.cursor = Point { line: 0, column: 10 };
+        editor.command(&mut buffer, jump);
+        assert_eq!(editor.cursor, Point { line: 2, column: 0 });
+
+        editor.cursor = Point { line: 0, column: 5 };
+        let target = Target::Motion(Motion::MatchingBracket);
+        editor.operate(&mut buffer, Operator::Delete, 1, &target);
+        assert_eq!(buffer.contents.to_string(), "f(a, ) {\n    g(c)\n}\n");
+    }
+
+    #[test]
+    fn pairs_brackets_in_strings_apart() {
+        let (editor, buffer) = setup("f(\")\", g)\n", 0, 1);
+        assert_eq!(
+            editor.matching_brackets(&buffer, 0..1),
+            Some((editor.cursor, Point { line: 0, column: 8 }))
+        );
+        assert_eq!(buffer.matching_bracket(3), None);
+        // only the lines on the screen are looked at.
+        let (editor, buffer) = setup("(\n)\n", 0, 0);
+        assert_eq!(editor.matching_brackets(&buffer, 0..1), None);
+        assert!(editor.matching_brackets(&buffer, 0..2).is_some());
+    }
+
+    #[test]
+    fn pairs_brackets_and_quotes() {
+        let (mut editor, mut buffer) = setup("x\n", 0, 1);
+        editor.set_mode(&mut buffer, Mode::Insert);
+        for c in "f(\"a".chars() {
+            editor.command(&mut buffer, EditorCommand::InsertChar(c));
+        }
+        assert_eq!(buffer.contents.to_string(), "xf(\"a\")\n");
+        // typing a quote in the string closes it rather than opening another.
+        editor.command(&mut buffer, EditorCommand::InsertChar('"'));
+        editor.command(&mut buffer, EditorCommand::InsertChar(')'));
+        assert_eq!(buffer.contents.to_string(), "xf(\"a\")\n");
+        assert_eq!(editor.cursor.column, 7);
+
+        editor.command(&mut buffer, EditorCommand::InsertChar('['));
+        editor.command(&mut buffer, EditorCommand::DeleteBackward);
+        assert_eq!(buffer.contents.to_string(), "xf(\"a\")\n");
+        // no pair before a word, nor for an apostrophe.
+        editor.cursor.column = 0;
+        editor.command(&mut buffer, EditorCommand::InsertChar('{'));
+        editor.command(&mut buffer, EditorCommand::InsertChar('n'));
+        editor.command(&mut buffer, EditorCommand::InsertChar('\''));
+        assert_eq!(buffer.contents.to_string(), "{n'xf(\"a\")\n");
+    }
+}
@@ -1,11 +1,13 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rope::{Rope, RopeBuilder};
 use slotmap::new_key_type;
-use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::ops::{Deref, DerefMut, Range};
+use std::path::{Path, PathBuf};
 use tore::Point;
 
-pub type Highlights = iset::IntervalMap<usize, String>;
+use crate::highlights::{HighlightSpans, Highlights};
+use crate::history::{Change, History};
+use crate::SyntaxTree;
 
 new_key_type! {
     pub struct Id;
@@ -13,23 +15,230 @@ new_key_type! {
 
 #[derive(Debug, Clone)]
 pub enum Command {
-    Highlight(Highlights),
+    /// Replaces the highlights in the given byte ranges.
+    Highlight(Vec<Range<usize>>, HighlightSpans),
+}
+
+/// What pressing tab inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Indent::Spaces(4)
+    }
+}
+
+impl Indent {
+    /// The text of a single level of indentation.
+    pub fn unit(&self) -> String {
+        match self {
+            Indent::Tabs => "\t".into(),
+            Indent::Spaces(width) => " ".repeat(*width),
+        }
+    }
+}
+
+impl std::str::FromStr for Indent {
+    type Err = anyhow::Error;
+
+    /// Parses `tabs`, or a number of spaces.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "tabs" => Ok(Indent::Tabs),
+            width => match width.parse() {
+                Ok(width) if (1..=16).contains(&width) => Ok(Indent::Spaces(width)),
+                _ => anyhow::bail!("indent is `tabs` or 1 to 16 spaces, not `{}`", width),
+            },
+        }
+    }
+}
+
+/// An edit to the contents, in the byte offsets and points tree-sitter works with: the columns of
+/// its points are byte offsets into their lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub start_byte: usize,
+    pub old_end_byte: usize,
+    pub new_end_byte: usize,
+    pub start_point: Point,
+    pub old_end_point: Point,
+    pub new_end_point: Point,
+}
+
+impl Edit {
+    /// The edit replacing the chars in `char_range` with `inserted`, computed before it is made.
+    pub(crate) fn new(rope: &Rope, char_range: Range<usize>, inserted: &str) -> Self {
+        let byte_point = |byte_idx: usize| {
+            let line = rope.byte_to_line(byte_idx);
+            Point { line, column: byte_idx - rope.line_to_byte(line) }
+        };
+        let start_byte = rope.char_to_byte(char_range.start);
+        let old_end_byte = rope.char_to_byte(char_range.end);
+        let start_point = byte_point(start_byte);
+        let new_end_point = match inserted.rfind('\n') {
+            Some(idx) => Point {
+                line: start_point.line + inserted.matches('\n').count(),
+                column: inserted.len() - idx - 1,
+            },
+            None => Point { line: start_point.line, column: start_point.column + inserted.len() },
+        };
+        Self {
+            start_byte,
+            old_end_byte,
+            new_end_byte: start_byte + inserted.len(),
+            start_point,
+            old_end_point: byte_point(old_end_byte),
+            new_end_point,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Buffer {
     pub id: Id,
+    pub path: Option<PathBuf>,
     pub contents: Contents,
     pub highlights: Highlights,
+    /// The syntax tree of the contents, edited along with them until they are parsed again.
+    pub syntax: Option<Box<dyn SyntaxTree>>,
+    pub indent: Indent,
+    revision: usize,
+    saved_revision: usize,
+    history: History,
+    /// The edits made since they were last taken, for the syntax tree to catch up with.
+    edits: Vec<Edit>,
 }
 
 impl Buffer {
     pub fn empty(id: Id) -> Self {
-        Self::new(id, Contents(Rope::new()))
+        Self::new(id, None, Contents(Rope::new()))
+    }
+
+    pub fn new(id: Id, path: Option<PathBuf>, contents: Contents) -> Self {
+        let highlights = Default::default();
+        Self {
+            id,
+            path,
+            contents,
+            highlights,
+            syntax: None,
+            indent: Indent::default(),
+            revision: 0,
+            saved_revision: 0,
+            history: History::default(),
+            edits: vec![],
+        }
+    }
+
+    /// The name to show for the buffer: its path, or a placeholder if it has none.
+    pub fn name(&self) -> std::borrow::Cow<'_, str> {
+        self.path
+            .as_ref()
+            .map_or("[scratch]".into(), |p| p.to_string_lossy())
+    }
+
+    /// A counter bumped on every edit to the contents, identifying the state they are in.
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Whether the contents have been edited since they were last read or written.
+    pub fn is_dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    pub fn insert_char(&mut self, char_idx: usize, c: char) {
+        self.insert(char_idx, c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        self.record_edit(Edit::new(&self.contents, char_idx..char_idx, text));
+        self.contents.insert(char_idx, text);
+        let change = Change { char_idx, removed: String::new(), inserted: text.into() };
+        self.history.record(change);
+        self.revision += 1;
+    }
+
+    pub fn remove(&mut self, char_range: Range<usize>) {
+        let removed = self.contents.slice(char_range.clone()).to_string();
+        self.record_edit(Edit::new(&self.contents, char_range.clone(), ""));
+        self.contents.remove(char_range.clone());
+        let change = Change { char_idx: char_range.start, removed, inserted: String::new() };
+        self.history.record(change);
+        self.revision += 1;
+    }
+
+    /// Groups the edits made until [`Buffer::commit_transaction`] into a single undo step.
+    pub fn begin_transaction(&mut self) {
+        self.history.begin();
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.history.commit();
+    }
+
+    /// Begins a transaction that commits leave open until [`Buffer::release_transaction`], so
+    /// that a command run at several cursors undoes as one step.
+    pub(crate) fn hold_transaction(&mut self) {
+        self.history.hold();
+    }
+
+    pub(crate) fn release_transaction(&mut self) {
+        self.history.release();
+    }
+
+    /// Reverts the last transaction, returning the char offset it was made at.
+    pub fn undo(&mut self) -> Option<usize> {
+        let mut edits = vec![];
+        let char_idx = self.history.undo(&mut self.contents, &mut edits)?;
+        edits.into_iter().for_each(|edit| self.record_edit(edit));
+        self.revision += 1;
+        self.mark_saved_if_unchanged();
+        Some(char_idx)
+    }
+
+    /// Re-applies the last undone transaction, returning the char offset it was made at.
+    pub fn redo(&mut self) -> Option<usize> {
+        let mut edits = vec![];
+        let char_idx = self.history.redo(&mut self.contents, &mut edits)?;
+        edits.into_iter().for_each(|edit| self.record_edit(edit));
+        self.revision += 1;
+        self.mark_saved_if_unchanged();
+        Some(char_idx)
+    }
+
+    fn record_edit(&mut self, edit: Edit) {
+        self.highlights.edit(&edit);
+        if let Some(syntax) = self.syntax.as_mut() {
+            syntax.edit(&edit);
+        }
+        self.edits.push(edit);
     }
 
-    pub fn new(id: Id, contents: Contents) -> Self {
-        Self { id, contents, highlights: Default::default() }
+    /// The edits made since they were last taken, oldest first.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Takes the edits made since they were last taken, oldest first.
+    pub fn take_edits(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.edits)
+    }
+
+    fn mark_saved(&mut self) {
+        self.saved_revision = self.revision;
+        self.history.mark_saved();
+    }
+
+    /// Undoing or redoing back to the contents that were last saved makes the buffer clean again.
+    fn mark_saved_if_unchanged(&mut self) {
+        if self.history.is_saved() {
+            self.saved_revision = self.revision;
+        }
     }
 
     pub async fn read(filename: &PathBuf) -> Result<Contents> {
@@ -94,27 +303,142 @@ impl Buffer {
         }
     }
 
+    /// Writes the contents back to the path the buffer was read from.
+    pub async fn write(&mut self) -> Result<()> {
+        let path = self.path.clone().context("buffer has no file name")?;
+        Self::write_contents(&self.contents, &path).await?;
+        self.mark_saved();
+        Ok(())
+    }
+
+    /// Writes the contents to `path`, and makes it the buffer's path on success.
+    pub async fn save_as(&mut self, path: PathBuf) -> Result<()> {
+        Self::write_contents(&self.contents, &path).await?;
+        self.path = Some(path);
+        self.mark_saved();
+        Ok(())
+    }
+
+    /// Streams `contents` into a temporary file next to `path`, and renames it over `path` once
+    /// everything has been flushed to disk, so that a failed write never leaves a truncated file
+    /// behind. The permissions of an existing file are carried over to the new one.
+    async fn write_contents(contents: &Contents, path: &Path) -> Result<()> {
+        use tokio::fs;
+
+        // write through symlinks rather than replacing them.
+        let path = match fs::canonicalize(path).await {
+            Ok(path) => path,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+            Err(e) => return Err(e.into()),
+        };
+        let permissions = match fs::metadata(&path).await {
+            Ok(metadata) => Some(metadata.permissions()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let file_name = path.file_name().context("not a file")?.to_string_lossy();
+        let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+        let res = Self::write_chunks(contents, &tmp_path).await;
+        let res = match (res, permissions) {
+            (Ok(()), Some(permissions)) => fs::set_permissions(&tmp_path, permissions)
+                .await
+                .map_err(Into::into),
+            (res, _) => res,
+        };
+        let res = match res {
+            Ok(()) => fs::rename(&tmp_path, &path).await.map_err(Into::into),
+            res => res,
+        };
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp_path).await;
+        }
+        res
+    }
+
+    async fn write_chunks(contents: &Contents, path: &Path) -> Result<()> {
+        use tokio::fs::OpenOptions;
+        use tokio::io::{AsyncWriteExt, BufWriter};
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await?;
+        let mut writer = BufWriter::new(file);
+        for chunk in contents.chunks() {
+            writer.write_all(chunk.as_bytes()).await?;
+        }
+        writer.flush().await?;
+        writer.get_ref().sync_all().await?;
+        Ok(())
+    }
+
     pub fn command(&mut self, command: Command) {
         match command {
-            Command::Highlight(hls) => self.highlights = hls,
+            Command::Highlight(ranges, spans) => self.highlights.merge(&ranges, spans),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Contents(Rope);
 
 impl Contents {
-    pub(crate) fn point_to_char_offset(&self, cursor: Point) -> usize {
+    pub fn point_to_char_offset(&self, cursor: Point) -> usize {
         let line_offset = self.0.line_to_char(cursor.line);
         line_offset + cursor.column
     }
 
-    pub(crate) fn char_offset_to_point(&self, offset: usize) -> Point {
+    pub fn char_offset_to_point(&self, offset: usize) -> Point {
         let line = self.0.char_to_line(offset);
         let column = offset - self.0.line_to_char(line);
         Point { line, column }
     }
+
+    /// The number of chars in a line, not counting its line ending.
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        let line = self.0.line(line_idx);
+        line.len_chars() - line_ending(line).map_or(0, |ending| ending.chars().count())
+    }
+
+    /// The index of the last line, not counting the empty line after a trailing line ending.
+    pub fn last_line(&self) -> usize {
+        let last = self.0.len_lines() - 1;
+        if last > 0 && self.0.line(last).len_chars() == 0 {
+            last - 1
+        } else {
+            last
+        }
+    }
+
+    /// The column of the first character in a line that is not a space or tab.
+    pub fn first_non_blank(&self, line_idx: usize) -> usize {
+        let line = self.0.line(line_idx);
+        let blank = line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        std::cmp::min(blank, self.line_len(line_idx))
+    }
+
+    /// The line ending to use for new lines: the one the given line ends with, else the one the
+    /// first line ends with.
+    pub(crate) fn line_ending_at(&self, line_idx: usize) -> &'static str {
+        line_ending(self.0.line(line_idx))
+            .or_else(|| line_ending(self.0.line(0)))
+            .unwrap_or("\n")
+    }
+}
+
+fn line_ending(line: rope::RopeSlice) -> Option<&'static str> {
+    let len = line.len_chars();
+    match (
+        len.checked_sub(2).map(|i| line.char(i)),
+        len.checked_sub(1).map(|i| line.char(i)),
+    ) {
+        (Some('\r'), Some('\n')) => Some("\r\n"),
+        (_, Some('\n')) => Some("\n"),
+        (_, Some('\r')) => Some("\r"),
+        _ => None,
+    }
 }
 
 impl Deref for Contents {
@@ -130,3 +454,95 @@ impl DerefMut for Contents {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_bump_revision() {
+        let mut buffer = Buffer::empty(Id::default());
+        assert_eq!(buffer.revision(), 0);
+        assert!(!buffer.is_dirty());
+
+        buffer.insert(0, "hello");
+        buffer.insert_char(5, '!');
+        buffer.remove(0..1);
+        assert_eq!(buffer.revision(), 3);
+        assert!(buffer.is_dirty());
+        assert_eq!(buffer.contents.to_string(), "ello!");
+
+        assert_eq!(buffer.undo(), Some(0));
+        assert_eq!(buffer.undo(), Some(5));
+        assert_eq!(buffer.undo(), Some(0));
+        assert_eq!(buffer.undo(), None);
+        assert_eq!(buffer.contents.to_string(), "");
+        assert!(!buffer.is_dirty());
+        assert_eq!(buffer.revision(), 6);
+    }
+
+    #[test]
+    fn edits_are_in_bytes() {
+        let point = |line, column| Point { line, column };
+        let mut buffer = Buffer::empty(Id::default());
+        buffer.insert(0, "é\nab");
+        buffer.take_edits();
+
+        buffer.insert(1, "x\ny");
+        buffer.remove(3..5);
+        let edits = buffer.take_edits();
+        assert_eq!(
+            edits[0],
+            Edit {
+                start_byte: 2,
+                old_end_byte: 2,
+                new_end_byte: 5,
+                start_point: point(0, 2),
+                old_end_point: point(0, 2),
+                new_end_point: point(1, 1),
+            }
+        );
+        assert_eq!((edits[1].start_byte, edits[1].old_end_byte), (4, 6));
+        assert_eq!((edits[1].start_point, edits[1].old_end_point), (point(1, 0), point(2, 0)));
+
+        buffer.undo();
+        assert_eq!(buffer.take_edits().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn save_as_replaces_file_and_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("toku-buffer-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create directory");
+        let path = dir.join("save_as.txt");
+        std::fs::write(&path, "old contents\n").expect("write file");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640))
+            .expect("set permissions");
+
+        let mut buffer = Buffer::empty(Id::default());
+        buffer.insert(0, "new contents\n");
+        assert!(buffer.is_dirty());
+        buffer.save_as(path.clone()).await.expect("save buffer");
+        assert!(!buffer.is_dirty());
+        assert_eq!(buffer.path.as_ref(), Some(&path));
+        assert_eq!(std::fs::read_to_string(&path).expect("read file"), "new contents\n");
+        let mode = std::fs::metadata(&path)
+            .expect("metadata")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        buffer.insert(0, "more ");
+        assert!(buffer.is_dirty());
+        buffer.write().await.expect("write buffer");
+        assert!(!buffer.is_dirty());
+        let contents = Buffer::read(&path).await.expect("read buffer");
+        assert_eq!(contents.to_string(), "more new contents\n");
+        assert_eq!(std::fs::read_dir(&dir).expect("read directory").count(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
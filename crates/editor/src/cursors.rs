@@ -0,0 +1,297 @@
+use std::cmp::{max, min};
+
+use crate::{
+    Buffer, BufferContents, Editor, EditorCommand, Mode, Point, Selection, TextObject, Yank,
+};
+
+impl Editor {
+    /// Every selection in document order, and the index of the primary one among them. Outside
+    /// the visual modes each selection is just a cursor, with its anchor on its head.
+    pub fn selections(&self) -> (Vec<Selection>, usize) {
+        let visual = self.mode.is_visual();
+        let collapse = |selection: &Selection| match visual {
+            true => *selection,
+            false => Selection { anchor: selection.head, head: selection.head },
+        };
+        let mut selections: Vec<_> = self.secondaries.iter().map(collapse).collect();
+        let primary = collapse(&Selection { anchor: self.anchor, head: self.cursor });
+        let idx = selections.partition_point(|selection| selection.start() < primary.start());
+        selections.insert(idx, primary);
+        (selections, idx)
+    }
+
+    /// Replaces the selections, merging any that overlap. The primary one keeps its place when
+    /// merged into another.
+    pub fn set_selections(&mut self, mut selections: Vec<Selection>, primary: usize) {
+        let visual = self.mode.is_visual();
+        let primary_selection = selections[primary];
+        selections.sort_by_key(|selection| selection.start());
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+        let mut primary = 0;
+        for selection in selections {
+            let overlaps = merged.last().is_some_and(|last| match visual {
+                true => selection.start() <= last.end(),
+                false => selection.head == last.head,
+            });
+            match merged.last_mut() {
+                Some(last) if overlaps => {
+                    let end = max(last.end(), selection.end());
+                    *last = Selection { anchor: last.start(), head: end };
+                }
+                _ => merged.push(selection),
+            }
+            if selection == primary_selection {
+                primary = merged.len() - 1;
+            }
+        }
+
+        let primary = merged.remove(primary);
+        self.anchor = primary.anchor;
+        self.cursor = primary.head;
+        self.secondaries = merged;
+    }
+
+    /// Runs a command at each selection in turn, last first, so that its edits never move the
+    /// selections still to come; the ones already done shift by the length the edit added. All the
+    /// edits undo as one step.
+    pub(crate) fn command_at_each_cursor(
+        &mut self,
+        buffer: &mut Buffer,
+        command: EditorCommand,
+    ) -> Option<Yank> {
+        let (selections, primary) = self.selections();
+        let contents = &buffer.contents;
+        let mut offsets: Vec<_> = selections
+            .iter()
+            .map(|selection| (offset(contents, selection.anchor), offset(contents, selection.head)))
+            .collect();
+
+        let mode = self.mode.clone();
+        let mut primary_mode = mode.clone();
+        let mut yanks = vec![None; offsets.len()];
+        buffer.hold_transaction();
+        for idx in (0..offsets.len()).rev() {
+            let (anchor, head) = offsets[idx];
+            self.mode = mode.clone();
+            self.anchor = buffer.contents.char_offset_to_point(anchor);
+            self.cursor = buffer.contents.char_offset_to_point(head);
+
+            let len = buffer.contents.len_chars();
+            yanks[idx] = self.command_at_cursor(buffer, command.clone());
+            self.cursor_clamp(buffer);
+            let delta = buffer.contents.len_chars() as isize - len as isize;
+
+            let contents = &buffer.contents;
+            offsets[idx] = (offset(contents, self.anchor), offset(contents, self.cursor));
+            for (anchor, head) in offsets[idx + 1..].iter_mut() {
+                *anchor = anchor.saturating_add_signed(delta);
+                *head = head.saturating_add_signed(delta);
+            }
+            if idx == primary {
+                primary_mode = self.mode.clone();
+            }
+        }
+        buffer.release_transaction();
+        if primary_mode != Mode::Insert {
+            buffer.commit_transaction();
+        }
+
+        self.mode = primary_mode;
+        let contents = &buffer.contents;
+        let selections = offsets
+            .into_iter()
+            .map(|(anchor, head)| Selection {
+                anchor: contents.char_offset_to_point(min(anchor, contents.len_chars())),
+                head: contents.char_offset_to_point(min(head, contents.len_chars())),
+            })
+            .collect();
+        self.set_selections(selections, primary);
+
+        // the text taken at each cursor, joined in document order.
+        let yanks: Vec<Yank> = yanks.into_iter().flatten().collect();
+        let linewise = !yanks.is_empty() && yanks.iter().all(|yank| yank.linewise);
+        let separator = if linewise { "" } else { "\n" };
+        let text = yanks
+            .iter()
+            .map(|yank| yank.text.as_str())
+            .collect::<Vec<_>>();
+        (!yanks.is_empty()).then(|| Yank { text: text.join(separator), linewise })
+    }
+
+    /// Adds a cursor on the next match of the word under the primary cursor, or of the text of
+    /// the primary selection in visual mode, wrapping around the end of the buffer. The new cursor
+    /// becomes the primary one so that repeating the command keeps going.
+    pub fn add_cursor_at_next_match(&mut self, buffer: &Buffer) {
+        let contents = &buffer.contents;
+        let (needle, whole_word) = match self.selection() {
+            Some(selection) if self.mode == Mode::Visual => {
+                let start = offset(contents, selection.start());
+                let end = min(offset(contents, selection.end()) + 1, contents.len_chars());
+                (contents.slice(start..end).to_string(), false)
+            }
+            _ => match self.text_object_span(buffer, &TextObject::Word, true, 1) {
+                Some(span) => (contents.slice(span.start..span.end).to_string(), true),
+                None => return,
+            },
+        };
+        if needle.trim().is_empty() {
+            return;
+        }
+
+        let (selections, primary) = self.selections();
+        let from = min(offset(contents, selections[primary].end()) + 1, contents.len_chars());
+        let len = needle.chars().count();
+        let first = needle.chars().next();
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let is_match = |start: &usize| {
+            contents.chars_at(*start).take(len).eq(needle.chars())
+                && !(whole_word
+                    && (is_word(start.checked_sub(1).map(|before| contents.char(before)))
+                        || is_word(contents.get_char(start + len))))
+        };
+        let taken = |start: &usize| {
+            let start = contents.char_offset_to_point(*start);
+            selections
+                .iter()
+                .any(|selection| selection.start() == start)
+        };
+        // from the end of the primary selection on, then around from the start of the buffer.
+        let after = (from..).zip(contents.chars_at(from));
+        let before = (0..from).zip(contents.chars_at(0));
+        let Some(start) = after
+            .chain(before)
+            .filter(|(_, c)| Some(*c) == first)
+            .map(|(start, _)| start)
+            .find(|start| is_match(start) && !taken(start))
+        else {
+            return;
+        };
+
+        let head = match self.mode {
+            Mode::Visual => start + len - 1,
+            _ => start,
+        };
+        let selection = Selection {
+            anchor: contents.char_offset_to_point(start),
+            head: contents.char_offset_to_point(head),
+        };
+        let mut selections = selections;
+        selections.push(selection);
+        let primary = selections.len() - 1;
+        self.set_selections(selections, primary);
+    }
+
+    /// Splits each selection into one per line it covers. A block selection becomes a charwise
+    /// selection on each of its rows.
+    pub fn split_selection_lines(&mut self, buffer: &Buffer) {
+        if !self.mode.is_visual() {
+            return;
+        }
+        let contents = &buffer.contents;
+        let (selections, _) = self.selections();
+        let mut split = vec![];
+        for selection in selections {
+            let (start, end) = (selection.start(), selection.end());
+            for line in start.line..=end.line {
+                let last = contents.line_len(line).saturating_sub(1);
+                let (anchor, head) = match self.mode {
+                    Mode::VisualBlock => {
+                        let columns = selection.columns();
+                        (columns.start, columns.end - 1)
+                    }
+                    Mode::VisualLine => (0, last),
+                    _ => {
+                        let anchor = if line == start.line { start.column } else { 0 };
+                        let head = if line == end.line { end.column } else { last };
+                        (anchor, head)
+                    }
+                };
+                split.push(Selection {
+                    anchor: Point { line, column: anchor },
+                    head: Point { line, column: head },
+                });
+            }
+        }
+        if self.mode != Mode::Visual {
+            self.mode = Mode::Visual;
+        }
+        let primary = split.len() - 1;
+        self.set_selections(split, primary);
+    }
+}
+
+/// The char offset of a point, clamped to the buffer.
+fn offset(contents: &BufferContents, point: Point) -> usize {
+    let line = min(point.line, contents.len_lines() - 1);
+    let column = min(point.column, contents.line_len(line));
+    contents.line_to_char(line) + column
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Buffer, BufferId, Editor, EditorCommand, EditorId, Mode, Point};
+
+    fn setup(text: &str) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let editor = Editor::new(EditorId::default(), buffer.id);
+        (editor, buffer)
+    }
+
+    fn heads(editor: &Editor) -> Vec<(usize, usize)> {
+        let (selections, _) = editor.selections();
+        selections
+            .iter()
+            .map(|s| (s.head.line, s.head.column))
+            .collect()
+    }
+
+    #[test]
+    fn insert_at_each_cursor_undoes_as_one() {
+        let (mut editor, mut buffer) = setup("foo bar\nfoo baz\nfoo");
+        editor.command(&mut buffer, EditorCommand::AddCursorAtNextMatch);
+        editor.command(&mut buffer, EditorCommand::AddCursorAtNextMatch);
+        assert_eq!(heads(&editor), vec![(0, 0), (1, 0), (2, 0)]);
+
+        editor.command(&mut buffer, EditorCommand::SetMode(Mode::Insert));
+        for c in "ab".chars() {
+            editor.command(&mut buffer, EditorCommand::InsertChar(c));
+        }
+        editor.command(&mut buffer, EditorCommand::SetMode(Mode::Normal));
+        assert_eq!(buffer.contents.to_string(), "abfoo bar\nabfoo baz\nabfoo");
+        assert_eq!(heads(&editor), vec![(0, 1), (1, 1), (2, 1)]);
+
+        editor.command(&mut buffer, EditorCommand::Undo);
+        assert_eq!(buffer.contents.to_string(), "foo bar\nfoo baz\nfoo");
+        assert!(editor.secondaries.is_empty());
+    }
+
+    #[test]
+    fn adds_cursors_at_whole_words() {
+        let (mut editor, mut buffer) = setup("foo foobar\nfoo_x foo");
+        editor.cursor = Point { line: 1, column: 6 };
+        editor.command(&mut buffer, EditorCommand::AddCursorAtNextMatch);
+        assert_eq!(heads(&editor), vec![(0, 0), (1, 6)]);
+        editor.command(&mut buffer, EditorCommand::AddCursorAtNextMatch);
+        assert_eq!(heads(&editor), vec![(0, 0), (1, 6)]);
+    }
+
+    #[test]
+    fn deletes_shift_later_cursors() {
+        let (mut editor, mut buffer) = setup("ab\ncd\nef");
+        editor.cursor = Point { line: 2, column: 1 };
+        editor.command(&mut buffer, EditorCommand::SetMode(Mode::VisualLine));
+        editor.cursor = Point { line: 0, column: 0 };
+        editor.command(&mut buffer, EditorCommand::SplitSelectionLines);
+        assert_eq!(editor.secondaries.len(), 2);
+
+        editor.command(&mut buffer, EditorCommand::SetMode(Mode::Normal));
+        editor.command(&mut buffer, EditorCommand::DeleteForward);
+        assert_eq!(buffer.contents.to_string(), "a\nc\ne");
+        assert_eq!(heads(&editor), vec![(0, 0), (1, 0), (2, 0)]);
+
+        editor.command(&mut buffer, EditorCommand::CollapseSelections);
+        assert_eq!(heads(&editor), vec![(2, 0)]);
+    }
+}
@@ -0,0 +1,396 @@
+use bstr::ByteSlice;
+
+use crate::{Buffer, Editor, Indent, Mode, Operator, Point, Yank};
+
+/// Where put text goes relative to the cursor: its character, or its line for linewise text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Before,
+    After,
+}
+
+impl Editor {
+    /// Breaks the line at the cursor, indenting the new line for what comes before it. The blanks
+    /// after the cursor give way to the indentation.
+    pub fn insert_newline(&mut self, buffer: &mut Buffer) {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(self.cursor);
+        let line_end =
+            contents.line_to_char(self.cursor.line) + contents.line_len(self.cursor.line);
+        let blanks = contents
+            .slice(offset..line_end)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+        let indent = buffer.split_indent(self.cursor.line, offset, offset + blanks);
+        let line_ending = buffer.contents.line_ending_at(self.cursor.line);
+
+        if blanks > 0 {
+            buffer.remove(offset..offset + blanks);
+        }
+        buffer.insert(offset, &format!("{}{}", line_ending, indent));
+        self.cursor.move_next_line();
+        self.cursor.column = indent.chars().count();
+    }
+
+    /// Opens a new, indented line below or above the cursor's line to insert text into, as `o`
+    /// and `O` do.
+    pub fn open_line(&mut self, buffer: &mut Buffer, placement: Placement) {
+        self.set_mode(buffer, Mode::Insert);
+        let line = match placement {
+            Placement::After => self.cursor.line,
+            Placement::Before if self.cursor.line > 0 => self.cursor.line - 1,
+            Placement::Before => {
+                let line_ending = buffer.contents.line_ending_at(0);
+                buffer.insert(0, line_ending);
+                self.cursor = Point { line: 0, column: 0 };
+                return;
+            }
+        };
+        let column = buffer.contents.line_len(line);
+        self.cursor = Point { line, column };
+        self.insert_newline(buffer);
+    }
+
+    /// Inserts a tab, or spaces up to the next tab stop when the buffer indents with spaces.
+    pub fn insert_tab(&mut self, buffer: &mut Buffer) {
+        let offset = buffer.contents.point_to_char_offset(self.cursor);
+        let indent = match buffer.indent {
+            crate::Indent::Tabs => "\t".to_string(),
+            crate::Indent::Spaces(width) => " ".repeat(width - self.cursor.column % width),
+        };
+        buffer.insert(offset, &indent);
+        self.cursor.column += indent.chars().count();
+    }
+
+    /// Puts yanked text `count` times, as lines above or below the cursor's line if it was taken
+    /// linewise, or next to the cursor otherwise. In visual mode the text replaces the selection,
+    /// whose text is returned.
+    pub fn put(
+        &mut self,
+        buffer: &mut Buffer,
+        yank: &Yank,
+        placement: Placement,
+        count: usize,
+    ) -> Option<Yank> {
+        if self.mode.is_visual() {
+            return self.put_over_selection(buffer, yank, count);
+        }
+        let text = yank.text.repeat(count);
+        if text.is_empty() {
+            return None;
+        }
+
+        buffer.begin_transaction();
+        if yank.linewise {
+            let line = match placement {
+                Placement::Before => self.cursor.line,
+                Placement::After => self.cursor.line + 1,
+            };
+            if line < buffer.contents.len_lines() {
+                buffer.insert(buffer.contents.line_to_char(line), &text);
+            } else {
+                // the last line has no line ending to put the lines after.
+                let line_ending = buffer.contents.line_ending_at(self.cursor.line).to_string();
+                let text = text.strip_suffix(&line_ending).unwrap_or(&text);
+                let end = buffer.contents.len_chars();
+                buffer.insert(end, &format!("{}{}", line_ending, text));
+            }
+            self.cursor_jump_line(buffer, line);
+        } else {
+            let mut offset = buffer.contents.point_to_char_offset(self.cursor);
+            if placement == Placement::After && buffer.contents.line_len(self.cursor.line) > 0 {
+                offset += 1;
+            }
+            buffer.insert(offset, &text);
+            let end = offset + text.chars().count() - 1;
+            self.cursor = buffer.contents.char_offset_to_point(end);
+        }
+        buffer.commit_transaction();
+        None
+    }
+
+    /// Deletes the selection and puts yanked text where it was, as one change. Text put over
+    /// lines goes on lines of its own, and lines put over part of a line split it around them.
+    fn put_over_selection(
+        &mut self,
+        buffer: &mut Buffer,
+        yank: &Yank,
+        count: usize,
+    ) -> Option<Yank> {
+        let linewise = self.mode == Mode::VisualLine;
+        let start = self.selection()?.start();
+        let start_offset = buffer.contents.point_to_char_offset(start);
+        buffer.hold_transaction();
+        let replaced = self.operate_selection(buffer, Operator::Delete);
+        if linewise {
+            let mut text = yank.text.clone();
+            if !text.ends_with('\n') {
+                text.push_str(buffer.contents.line_ending_at(self.cursor.line));
+            }
+            // deleting the last lines leaves the cursor on the line above them.
+            let placement = match self.cursor.line < start.line {
+                true => Placement::After,
+                false => Placement::Before,
+            };
+            self.put(buffer, &Yank { text, linewise: true }, placement, count);
+        } else if yank.linewise {
+            let line_ending = buffer.contents.line_ending_at(start.line).to_string();
+            buffer.insert(start_offset, &line_ending);
+            self.cursor = Point { line: start.line + 1, column: 0 };
+            self.put(buffer, yank, Placement::Before, count);
+        } else {
+            // deleting up to the end of a line leaves the cursor before where the text was.
+            let placement = match buffer.contents.point_to_char_offset(self.cursor) < start_offset {
+                true => Placement::After,
+                false => Placement::Before,
+            };
+            self.put(buffer, yank, placement, count);
+        }
+        buffer.release_transaction();
+        buffer.commit_transaction();
+        replaced
+    }
+
+    /// Deletes the grapheme before the cursor, joining the line with the previous one when at its
+    /// start.
+    pub fn delete_backward(&mut self, buffer: &mut Buffer) {
+        if self.delete_pair(buffer) {
+            return;
+        }
+        let offset = buffer.contents.point_to_char_offset(self.cursor);
+        if self.cursor.column == 0 {
+            if self.cursor.line == 0 {
+                return;
+            }
+            let prev_line = self.cursor.line - 1;
+            let prev_len = buffer.contents.line_len(prev_line);
+            let line_start = buffer.contents.line_to_char(prev_line);
+            buffer.remove(line_start + prev_len..offset);
+            self.cursor.line = prev_line;
+            self.cursor.column = prev_len;
+            return;
+        }
+
+        let line_start = offset - self.cursor.column;
+        let before = buffer.contents.slice(line_start..offset).to_string();
+        let len = before
+            .as_bytes()
+            .graphemes()
+            .next_back()
+            .map_or(1, |g| g.chars().count());
+        buffer.remove(offset - len..offset);
+        self.cursor.column -= len;
+    }
+
+    /// Deletes the grapheme under the cursor, joining the next line onto this one when at its end.
+    pub fn delete_forward(&mut self, buffer: &mut Buffer) {
+        let offset = buffer.contents.point_to_char_offset(self.cursor);
+        let line_len = buffer.contents.line_len(self.cursor.line);
+        if self.cursor.column >= line_len {
+            let line_end = buffer.contents.line_to_char(self.cursor.line + 1);
+            if line_end > offset {
+                buffer.remove(offset..line_end);
+            }
+            return;
+        }
+
+        let line_end = offset - self.cursor.column + line_len;
+        let after = buffer.contents.slice(offset..line_end).to_string();
+        let len = after
+            .as_bytes()
+            .graphemes()
+            .next()
+            .map_or(1, |g| g.chars().count());
+        buffer.remove(offset..offset + len);
+    }
+}
+
+impl Buffer {
+    /// The indentation for the text from char `content` on, when its line is split at char `split`.
+    /// It is worked out from the syntax tree against the last line with text before the split,
+    /// or else copied from that line.
+    fn split_indent(&self, line: usize, split: usize, content: usize) -> String {
+        let contents = &self.contents;
+        let has_text = |line: usize| {
+            let start = contents.line_to_char(line) + contents.first_non_blank(line);
+            let end = contents.line_to_char(line) + contents.line_len(line);
+            start < end.min(split)
+        };
+        let Some(base) = (0..=line).rev().find(|line| has_text(*line)) else {
+            return String::new();
+        };
+        let base_start = contents.line_to_char(base);
+        let base_content = base_start + contents.first_non_blank(base);
+        let prefix = contents.slice(base_start..base_content).to_string();
+
+        let Some(syntax) = self.syntax.as_deref() else {
+            return prefix;
+        };
+        let byte = |char_idx| contents.char_to_byte(char_idx);
+        let base_level = syntax.indent_level(contents, byte(base_start), byte(base_content));
+        let level = syntax.indent_level(contents, byte(split), byte(content));
+        match base_level.zip(level) {
+            Some((base_level, level)) => reindent(prefix, level - base_level, self.indent),
+            None => prefix,
+        }
+    }
+}
+
+/// Indentation `levels` deeper than `prefix`, or shallower if negative. A level is a tab or up
+/// to a unit of spaces, as outdenting takes away.
+fn reindent(mut prefix: String, levels: isize, indent: Indent) -> String {
+    let width = match indent {
+        Indent::Tabs => 1,
+        Indent::Spaces(width) => width,
+    };
+    for _ in 0..levels {
+        prefix.push_str(&indent.unit());
+    }
+    for _ in levels..0 {
+        match prefix.pop() {
+            Some(' ') => {
+                let spaces = prefix.len() - prefix.trim_end_matches(' ').len();
+                prefix.truncate(prefix.len() - spaces.min(width - 1));
+            }
+            Some(_) | None => (),
+        }
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Buffer, BufferId, Editor, EditorId, Mode, Placement, Point, Yank};
+
+    fn setup(text: &str, line: usize, column: usize) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.set_mode(&mut buffer, Mode::Insert);
+        editor.cursor = Point { line, column };
+        (editor, buffer)
+    }
+
+    #[test]
+    fn newline_keeps_line_endings() {
+        let (mut editor, mut buffer) = setup("ab\r\ncd", 0, 1);
+        editor.insert_newline(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "a\r\nb\r\ncd");
+        assert_eq!(editor.cursor, Point { line: 1, column: 0 });
+    }
+
+    #[test]
+    fn newline_copies_indentation() {
+        let (mut editor, mut buffer) = setup("\tif a {\n\n}", 0, 7);
+        editor.insert_newline(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "\tif a {\n\t\n\n}");
+        assert_eq!(editor.cursor, Point { line: 1, column: 1 });
+        // from the last line with text, taking the place of the blanks after the cursor.
+        editor.cursor = Point { line: 2, column: 0 };
+        buffer.insert(buffer.contents.line_to_char(2), "  ");
+        editor.insert_newline(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "\tif a {\n\t\n\n\t\n}");
+    }
+
+    #[test]
+    fn open_lines() {
+        let (mut editor, mut buffer) = setup("  a\nb", 0, 2);
+        editor.set_mode(&mut buffer, Mode::Normal);
+        editor.open_line(&mut buffer, Placement::After);
+        assert_eq!(buffer.contents.to_string(), "  a\n  \nb");
+        assert_eq!(
+            (editor.mode.clone(), editor.cursor),
+            (Mode::Insert, Point { line: 1, column: 2 })
+        );
+
+        editor.set_mode(&mut buffer, Mode::Normal);
+        editor.cursor = Point { line: 0, column: 0 };
+        editor.open_line(&mut buffer, Placement::Before);
+        assert_eq!(buffer.contents.to_string(), "\n  a\n  \nb");
+        assert_eq!(editor.cursor, Point { line: 0, column: 0 });
+    }
+
+    #[test]
+    fn delete_joins_lines_across_crlf() {
+        let (mut editor, mut buffer) = setup("ab\r\ncd", 1, 0);
+        editor.delete_backward(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "abcd");
+        assert_eq!(editor.cursor, Point { line: 0, column: 2 });
+
+        let (mut editor, mut buffer) = setup("ab\r\ncd", 0, 2);
+        editor.delete_forward(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "abcd");
+        assert_eq!(editor.cursor, Point { line: 0, column: 2 });
+
+        let (mut editor, mut buffer) = setup("ab", 0, 2);
+        editor.delete_forward(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "ab");
+    }
+
+    #[test]
+    fn delete_whole_graphemes() {
+        let (mut editor, mut buffer) = setup("ae\u{301}z", 0, 3);
+        editor.delete_backward(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "az");
+        assert_eq!(editor.cursor, Point { line: 0, column: 1 });
+
+        editor.cursor.column = 0;
+        editor.delete_forward(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "z");
+    }
+
+    #[test]
+    fn tab_indents_to_next_stop() {
+        let (mut editor, mut buffer) = setup("ab", 0, 1);
+        editor.insert_tab(&mut buffer);
+        assert_eq!(buffer.contents.to_string(), "a   b");
+        assert_eq!(editor.cursor, Point { line: 0, column: 4 });
+    }
+
+    #[test]
+    fn put_linewise_and_charwise() {
+        let lines = Yank { text: "x\n".into(), linewise: true };
+        let (mut editor, mut buffer) = setup("a\nb", 1, 0);
+        editor.put(&mut buffer, &lines, Placement::After, 2);
+        assert_eq!(buffer.contents.to_string(), "a\nb\nx\nx");
+        assert_eq!(editor.cursor, Point { line: 2, column: 0 });
+        editor.put(&mut buffer, &lines, Placement::Before, 1);
+        assert_eq!(buffer.contents.to_string(), "a\nb\nx\nx\nx");
+
+        let chars = Yank { text: "yz".into(), linewise: false };
+        let (mut editor, mut buffer) = setup("ab", 0, 0);
+        editor.put(&mut buffer, &chars, Placement::After, 1);
+        assert_eq!(buffer.contents.to_string(), "ayzb");
+        assert_eq!(editor.cursor, Point { line: 0, column: 2 });
+        editor.put(&mut buffer, &chars, Placement::Before, 1);
+        assert_eq!(buffer.contents.to_string(), "ayyzzb");
+    }
+
+    #[test]
+    fn put_replaces_the_selection() {
+        let chars = Yank { text: "yz".into(), linewise: false };
+        let (mut editor, mut buffer) = setup("abc\nd", 0, 1);
+        editor.set_mode(&mut buffer, Mode::Visual);
+        editor.cursor.column = 2;
+        let replaced = editor.put(&mut buffer, &chars, Placement::After, 1);
+        assert_eq!(buffer.contents.to_string(), "ayz\nd");
+        assert_eq!(replaced.map(|yank| yank.text), Some("bc".into()));
+        assert_eq!(editor.mode, Mode::Normal);
+        buffer.undo();
+        assert_eq!(buffer.contents.to_string(), "abc\nd");
+
+        let lines = Yank { text: "x\n".into(), linewise: true };
+        editor.cursor = Point { line: 0, column: 1 };
+        editor.set_mode(&mut buffer, Mode::Visual);
+        editor.put(&mut buffer, &lines, Placement::After, 1);
+        assert_eq!(buffer.contents.to_string(), "a\nx\nc\nd");
+
+        let (mut editor, mut buffer) = setup("a\nb\nc", 1, 0);
+        editor.set_mode(&mut buffer, Mode::VisualLine);
+        editor.cursor.line = 2;
+        let replaced = editor.put(&mut buffer, &chars, Placement::Before, 1);
+        assert_eq!(buffer.contents.to_string(), "a\nyz");
+        assert_eq!(replaced.map(|yank| (yank.text, yank.linewise)), Some(("b\nc\n".into(), true)));
+    }
+}
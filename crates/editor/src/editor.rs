@@ -1,6 +1,6 @@
-use std::path::PathBuf;
-
-use crate::{Buffer, BufferId};
+use crate::operator::{Operator, Target, Yank};
+use crate::pending::Pending;
+use crate::{Buffer, BufferId, FoldCommand, Folds, Placement, Selection, SyntaxMotion};
 use slotmap::new_key_type;
 use tore::Point;
 
@@ -13,6 +13,15 @@ pub enum Mode {
     #[default]
     Normal,
     Insert,
+    Visual,
+    VisualLine,
+    VisualBlock,
+}
+
+impl Mode {
+    pub fn is_visual(&self) -> bool {
+        matches!(self, Mode::Visual | Mode::VisualLine | Mode::VisualBlock)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,13 +40,74 @@ pub enum CursorJump {
     StartOfNearestWord,
 }
 
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Move(Direction),
+    Jump(CursorJump),
+    StartOfLine,
+    FirstNonBlank,
+    EndOfLine,
+    StartOfFile,
+    EndOfFile,
+    /// Goes to a line, counting from 1.
+    Line(usize),
+    /// Moves through the syntax tree, if the buffer has one.
+    Syntax(SyntaxMotion),
+    /// Goes to the bracket that pairs with the one at or after the cursor on its line.
+    MatchingBracket,
+}
+
 #[derive(Debug, Clone)]
 pub enum Command {
     SetMode(Mode),
     SwapBuffer(BufferId),
     CursorMove(Direction),
     CursorJump(CursorJump),
+    Motion(Motion, usize),
+    /// Goes to a point picked from outside the buffer, such as a symbol, leaving visual mode and
+    /// every cursor but the primary one.
+    JumpTo(Point),
+    Operate(Operator, usize, Target),
+    Put(Yank, Placement, usize),
+    /// Applies an operator to the visual selection and returns to normal mode.
+    OperateSelection(Operator),
+    /// Selects a text object, as `viw` or `va(` do.
+    SelectTextObject(Target),
+    /// Moves the cursor to the other end of the visual selection.
+    SwapAnchor,
+    /// Adds a cursor on the next match of the word under the cursor, or of the selected text.
+    AddCursorAtNextMatch,
+    /// Splits each visual selection into one per line it covers.
+    SplitSelectionLines,
+    /// Drops every cursor but the primary one.
+    CollapseSelections,
+    Fold(FoldCommand),
     InsertChar(char),
+    InsertNewline,
+    /// Opens a line below or above the cursor's and inserts there.
+    OpenLine(Placement),
+    InsertTab,
+    DeleteBackward,
+    DeleteForward,
+    Undo,
+    Redo,
+}
+
+impl Command {
+    /// Whether the command acts at a cursor, and so at each cursor when there are several.
+    fn is_per_cursor(&self) -> bool {
+        !matches!(
+            self,
+            Command::SwapBuffer(_)
+                | Command::JumpTo(_)
+                | Command::Undo
+                | Command::Redo
+                | Command::AddCursorAtNextMatch
+                | Command::SplitSelectionLines
+                | Command::CollapseSelections
+                | Command::Fold(_)
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -46,23 +116,69 @@ pub struct Editor {
     pub id: Id,
     pub buffer_id: BufferId,
     pub cursor: Point,
+    /// The end of the visual selection that stays put while the cursor moves.
+    pub anchor: Point,
+    /// Selections besides the primary one made of `anchor` and `cursor`, in document order and
+    /// not overlapping it or each other.
+    pub secondaries: Vec<Selection>,
+    pub pending: Pending,
+    pub folds: Folds,
 }
 
 impl Editor {
     pub fn new(id: Id, buffer_id: BufferId) -> Self {
-        Self { id, mode: Mode::default(), buffer_id, cursor: Default::default() }
+        let pending = Pending::default();
+        let (cursor, anchor) = Default::default();
+        let (secondaries, folds) = Default::default();
+        let mode = Mode::default();
+        Self { id, mode, buffer_id, cursor, anchor, secondaries, pending, folds }
     }
 
     pub fn swap_buffer(&mut self, buffer_id: BufferId) {
+        self.secondaries.clear();
+        self.folds = Folds::default();
         self.buffer_id = buffer_id;
     }
 
-    pub fn command(&mut self, buffer: &mut Buffer, command: Command) {
+    /// Applies a command, returning the text it yanked or deleted, if any. Commands that act at
+    /// the cursor act at every cursor.
+    pub fn command(&mut self, buffer: &mut Buffer, command: Command) -> Option<Yank> {
         debug_assert!(buffer.id == self.buffer_id);
+        let edits = buffer.edits().len();
+        let yank = match command.is_per_cursor() && !self.secondaries.is_empty() {
+            true => self.command_at_each_cursor(buffer, command),
+            false => self.command_at_cursor(buffer, command),
+        };
+        self.follow_edits(&buffer.edits()[edits..]);
+        yank
+    }
+
+    pub(crate) fn command_at_cursor(
+        &mut self,
+        buffer: &mut Buffer,
+        command: Command,
+    ) -> Option<Yank> {
         match command {
+            Command::Motion(motion, count) => self.cursor_motion(buffer, &motion, count),
+            Command::JumpTo(point) => self.jump_to(buffer, point),
+            Command::Operate(operator, count, target) => {
+                return self.operate(buffer, operator, count, &target)
+            }
+            Command::OperateSelection(operator) => return self.operate_selection(buffer, operator),
+            Command::SelectTextObject(target) => self.select_text_object(buffer, &target),
+            Command::SwapAnchor => std::mem::swap(&mut self.cursor, &mut self.anchor),
+            Command::AddCursorAtNextMatch => self.add_cursor_at_next_match(buffer),
+            Command::SplitSelectionLines => self.split_selection_lines(buffer),
+            Command::CollapseSelections => self.secondaries.clear(),
+            Command::Fold(command) => self.fold(buffer, command),
             Command::SwapBuffer(buffer_id) => self.swap_buffer(buffer_id),
             Command::InsertChar(c) => self.insert_char(buffer, c),
-            Command::SetMode(mode) => self.mode = mode,
+            Command::InsertNewline => self.insert_newline(buffer),
+            Command::OpenLine(placement) => self.open_line(buffer, placement),
+            Command::InsertTab => self.insert_tab(buffer),
+            Command::DeleteBackward => self.delete_backward(buffer),
+            Command::DeleteForward => self.delete_forward(buffer),
+            Command::SetMode(mode) => self.set_mode(buffer, mode),
             Command::CursorMove(direction) => match direction {
                 Direction::Up => self.cursor_move_up(buffer),
                 Direction::Down => self.cursor_move_down(buffer),
@@ -75,12 +191,53 @@ impl Editor {
                 CursorJump::EndOfNearestWord => self.cursor_jump_end_of_nearest_word(buffer),
                 CursorJump::StartOfNearestWord => self.cursor_jump_start_of_nearest_word(buffer),
             },
+            Command::Put(yank, placement, count) => {
+                return self.put(buffer, &yank, placement, count)
+            }
+            Command::Undo => self.undo(buffer),
+            Command::Redo => self.redo(buffer),
         };
+        None
+    }
+
+    /// Switches modes. Everything typed in a single visit to insert mode is undone as one.
+    pub fn set_mode(&mut self, buffer: &mut Buffer, mode: Mode) {
+        match (&self.mode, &mode) {
+            (Mode::Insert, Mode::Insert) => (),
+            (_, Mode::Insert) => buffer.begin_transaction(),
+            (Mode::Insert, Mode::Normal) => {
+                buffer.commit_transaction();
+                self.cursor.move_prev_column();
+            }
+            (from, to) if to.is_visual() && !from.is_visual() => self.anchor = self.cursor,
+            _ => (),
+        }
+        self.mode = mode;
+        self.cursor_clamp(buffer);
+    }
+
+    pub fn undo(&mut self, buffer: &mut Buffer) {
+        self.secondaries.clear();
+        if let Some(offset) = buffer.undo() {
+            self.cursor = buffer.contents.char_offset_to_point(offset);
+            self.cursor_clamp(buffer);
+        }
+    }
+
+    pub fn redo(&mut self, buffer: &mut Buffer) {
+        self.secondaries.clear();
+        if let Some(offset) = buffer.redo() {
+            self.cursor = buffer.contents.char_offset_to_point(offset);
+            self.cursor_clamp(buffer);
+        }
     }
 
     pub fn insert_char(&mut self, buffer: &mut Buffer, c: char) {
+        if self.insert_pair(buffer, c) {
+            return;
+        }
         let offset = buffer.contents.point_to_char_offset(self.cursor);
         self.cursor.move_next_column();
-        buffer.contents.insert_char(offset, c);
+        buffer.insert_char(offset, c);
     }
 }
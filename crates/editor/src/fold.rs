@@ -0,0 +1,268 @@
+use std::ops::Range;
+
+use crate::{Buffer, BufferContents, Edit, Editor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldCommand {
+    /// Opens the fold at the cursor if it is closed, or else closes it, as `za` does.
+    Toggle,
+    Open,
+    /// Closes the innermost open fold around the cursor.
+    Close,
+    OpenAll,
+    CloseAll,
+}
+
+/// The folds an editor has closed, as ranges of lines. The first line of a closed fold stays in
+/// view, standing for the rest, which are hidden. Folds may nest.
+#[derive(Debug, Clone, Default)]
+pub struct Folds {
+    closed: Vec<Range<usize>>,
+}
+
+impl Folds {
+    pub fn is_empty(&self) -> bool {
+        self.closed.is_empty()
+    }
+
+    /// The outermost closed fold that a line is in, if any.
+    pub fn closed_at(&self, line: usize) -> Option<Range<usize>> {
+        self.closed
+            .iter()
+            .filter(|fold| fold.contains(&line))
+            .max_by_key(|fold| fold.len())
+            .cloned()
+    }
+
+    /// Whether a line is hidden inside a closed fold.
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.closed_at(line).is_some_and(|fold| fold.start != line)
+    }
+
+    /// The line in view that shows a line: its own, or the first of the fold that hides it.
+    pub fn shown_line(&self, line: usize) -> usize {
+        self.closed_at(line).map_or(line, |fold| fold.start)
+    }
+
+    /// The first line in view after a line, which may be past the end of the buffer.
+    pub fn next_shown(&self, line: usize) -> usize {
+        self.closed_at(line).map_or(line + 1, |fold| fold.end)
+    }
+
+    /// The last line in view before a line, if there is one.
+    pub fn prev_shown(&self, line: usize) -> Option<usize> {
+        let prev = self.shown_line(line).checked_sub(1)?;
+        Some(self.shown_line(prev))
+    }
+
+    /// Keeps the folds on the lines they were on before an edit. Folds the edit changed are opened,
+    /// unless it stayed within their first line.
+    pub(crate) fn edited(&mut self, edit: &Edit) {
+        let start = edit.start_point.line;
+        let old_end = edit.old_end_point.line;
+        let new_end = edit.new_end_point.line;
+        self.closed.retain_mut(|fold| {
+            if fold.end <= start || (fold.start == start && old_end == start && new_end == start) {
+                return true;
+            }
+            if fold.start > old_end {
+                fold.start = fold.start + new_end - old_end;
+                fold.end = fold.end + new_end - old_end;
+                return true;
+            }
+            false
+        });
+    }
+
+    /// Opens the closed folds that a line is in.
+    fn open_at(&mut self, line: usize) {
+        self.closed.retain(|fold| !fold.contains(&line));
+    }
+}
+
+impl Buffer {
+    /// The ranges of lines that can fold, from the syntax tree if the language says what folds, or
+    /// else from the indentation of the lines.
+    pub fn foldable(&self) -> Vec<Range<usize>> {
+        let syntax = self
+            .syntax
+            .as_deref()
+            .and_then(|syntax| syntax.folds(&self.contents));
+        syntax.unwrap_or_else(|| indent_folds(&self.contents))
+    }
+}
+
+/// The ranges of lines indented deeper than the line before them, with that line. Blank lines
+/// belong to the deeper lines around them.
+fn indent_folds(contents: &BufferContents) -> Vec<Range<usize>> {
+    let mut folds = vec![];
+    let mut open: Vec<(usize, usize)> = vec![];
+    let mut last = 0;
+    for line in 0..=contents.last_line() {
+        let indent = contents.first_non_blank(line);
+        if indent == contents.line_len(line) {
+            continue;
+        }
+        while let Some(&(start, level)) = open.last() {
+            if level < indent {
+                break;
+            }
+            open.pop();
+            if last > start {
+                folds.push(start..last + 1);
+            }
+        }
+        open.push((line, indent));
+        last = line;
+    }
+    for (start, _) in open.into_iter().rev() {
+        if last > start {
+            folds.push(start..last + 1);
+        }
+    }
+    folds.sort_by_key(|fold| fold.start);
+    folds
+}
+
+impl Editor {
+    /// Keeps the folds on the lines they were on before edits to the buffer, whether this editor
+    /// or another one on the buffer made them.
+    pub fn follow_edits(&mut self, edits: &[Edit]) {
+        for edit in edits {
+            self.folds.edited(edit);
+        }
+    }
+
+    /// Opens the folds around the cursor, after it jumped to a line they hid.
+    pub fn open_folds_at_cursor(&mut self) {
+        self.folds.open_at(self.cursor.line);
+    }
+
+    pub fn fold(&mut self, buffer: &Buffer, command: FoldCommand) {
+        let line = self.cursor.line;
+        match command {
+            FoldCommand::Toggle if self.folds.closed_at(line).is_some() => {
+                return self.fold(buffer, FoldCommand::Open);
+            }
+            FoldCommand::Toggle => return self.fold(buffer, FoldCommand::Close),
+            FoldCommand::Open => {
+                if let Some(fold) = self.folds.closed_at(line) {
+                    self.folds.closed.retain(|closed| *closed != fold);
+                }
+            }
+            FoldCommand::Close => {
+                let foldable = buffer.foldable();
+                let fold = foldable
+                    .into_iter()
+                    .filter(|fold| fold.contains(&line) && !self.folds.closed.contains(fold))
+                    .min_by_key(|fold| fold.len());
+                self.folds.closed.extend(fold);
+            }
+            FoldCommand::OpenAll => self.folds.closed.clear(),
+            FoldCommand::CloseAll => self.folds.closed = buffer.foldable(),
+        }
+        // the cursor stays in view, on the line that stands for its fold.
+        self.cursor.line = self.folds.shown_line(self.cursor.line);
+        self.cursor_clamp(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferId, EditorCommand, EditorId, Point};
+
+    const TEXT: &str = "fn a() {\n    if b {\n        c;\n\n    }\n}\nd\n";
+
+    fn setup(line: usize) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, TEXT);
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.cursor = Point { line, column: 0 };
+        (editor, buffer)
+    }
+
+    #[test]
+    fn folds_by_indentation() {
+        let (_, buffer) = setup(0);
+        let lines = [0..5, 1..3];
+        assert_eq!(buffer.foldable(), lines);
+    }
+
+    #[test]
+    fn closes_innermost_first() {
+        let (mut editor, buffer) = setup(2);
+        editor.fold(&buffer, FoldCommand::Close);
+        assert_eq!(editor.cursor.line, 1);
+        assert!(editor.folds.is_hidden(2) && !editor.folds.is_hidden(3));
+        editor.fold(&buffer, FoldCommand::Close);
+        assert_eq!(editor.cursor.line, 0);
+        assert!(editor.folds.is_hidden(4) && !editor.folds.is_hidden(5));
+
+        editor.fold(&buffer, FoldCommand::Toggle);
+        assert_eq!(editor.folds.closed_at(1), Some(1..3));
+        editor.fold(&buffer, FoldCommand::OpenAll);
+        assert!(editor.folds.is_empty());
+    }
+
+    #[test]
+    fn moves_over_closed_folds() {
+        let (mut editor, mut buffer) = setup(0);
+        editor.fold(&buffer, FoldCommand::CloseAll);
+        let down = crate::Motion::Move(crate::Direction::Down);
+        editor.command(&mut buffer, EditorCommand::Motion(down, 1));
+        assert_eq!(editor.cursor.line, 5);
+        let up = crate::Motion::Move(crate::Direction::Up);
+        editor.command(&mut buffer, EditorCommand::Motion(up, 1));
+        assert_eq!(editor.cursor.line, 0);
+    }
+
+    #[test]
+    fn edits_move_and_open_folds() {
+        let (mut editor, mut buffer) = setup(1);
+        editor.fold(&buffer, FoldCommand::Close);
+        editor.cursor = Point { line: 0, column: 0 };
+        editor.command(&mut buffer, EditorCommand::SetMode(crate::Mode::Insert));
+        editor.command(&mut buffer, EditorCommand::InsertNewline);
+        assert_eq!(editor.folds.closed_at(2), Some(2..4));
+
+        editor.cursor = Point { line: 3, column: 0 };
+        editor.command(&mut buffer, EditorCommand::InsertChar('x'));
+        assert!(editor.folds.is_empty());
+    }
+
+    #[test]
+    fn edits_move_the_folds_of_other_editors() {
+        let (mut editor, mut buffer) = setup(1);
+        editor.fold(&buffer, FoldCommand::Close);
+        let mut other = Editor::new(EditorId::default(), buffer.id);
+        let edits = buffer.edits().len();
+        other.command(&mut buffer, EditorCommand::OpenLine(crate::Placement::Before));
+        editor.follow_edits(&buffer.edits()[edits..]);
+        assert_eq!(editor.folds.closed_at(2), Some(2..4));
+    }
+
+    #[test]
+    fn jumps_open_folds() {
+        let (mut editor, mut buffer) = setup(0);
+        editor.fold(&buffer, FoldCommand::CloseAll);
+        let jump = EditorCommand::Motion(crate::Motion::Line(3), 1);
+        editor.command(&mut buffer, jump);
+        assert_eq!(editor.cursor.line, 2);
+        assert_eq!(editor.folds.closed_at(2), None);
+        assert_eq!(editor.folds.closed_at(0), None);
+
+        editor.fold(&buffer, FoldCommand::CloseAll);
+        let search = crate::Search::new("c;", false).unwrap();
+        editor.search(&buffer, &search, Point::default(), false);
+        assert_eq!(editor.cursor, Point { line: 2, column: 8 });
+        assert!(editor.folds.is_empty());
+
+        editor.fold(&buffer, FoldCommand::CloseAll);
+        editor.set_mode(&mut buffer, crate::Mode::Visual);
+        let point = Point { line: 2, column: 0 };
+        editor.command(&mut buffer, EditorCommand::JumpTo(point));
+        assert_eq!((editor.cursor, editor.mode.clone()), (point, crate::Mode::Normal));
+        assert!(editor.folds.is_empty());
+    }
+}
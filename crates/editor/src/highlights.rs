@@ -0,0 +1,278 @@
+use std::ops::Range;
+
+use crate::Edit;
+
+/// Highlight capture names by byte range, as the syntax worker produces them. The spans do not
+/// overlap, so a byte has at most one name; build them up with [`paint`].
+pub type HighlightSpans = iset::IntervalMap<usize, String>;
+
+/// Names a byte range, over whatever names its bytes had before.
+pub fn paint(spans: &mut HighlightSpans, range: Range<usize>, name: String) {
+    if range.is_empty() {
+        return;
+    }
+    clear(spans, range.clone());
+    spans.insert(range, name);
+}
+
+/// Removes the names of a byte range, cutting the spans that straddle its ends.
+fn clear(spans: &mut HighlightSpans, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    let overlapping: Vec<_> = spans
+        .iter(range.clone())
+        .map(|(overlapping, name)| (overlapping, name.clone()))
+        .collect();
+    for (overlapping, name) in overlapping {
+        spans.remove(overlapping.clone());
+        if overlapping.start < range.start {
+            spans.insert(overlapping.start..range.start, name.clone());
+        }
+        if overlapping.end > range.end {
+            spans.insert(range.end..overlapping.end, name);
+        }
+    }
+}
+
+/// The syntax highlights of a buffer. They are computed lazily, a range at a time, so they also
+/// track which byte ranges are up to date.
+#[derive(Debug, Clone, Default)]
+pub struct Highlights {
+    spans: HighlightSpans,
+    /// The highlighted byte ranges, sorted and disjoint.
+    highlighted: Vec<Range<usize>>,
+}
+
+impl Highlights {
+    /// The highlights overlapping a byte range.
+    pub fn iter(&self, range: Range<usize>) -> impl Iterator<Item = (Range<usize>, &String)> {
+        self.spans.iter(range)
+    }
+
+    /// The parts of a byte range that have not been highlighted.
+    pub fn missing(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut missing = vec![];
+        let mut start = range.start;
+        for highlighted in self.highlighted.iter() {
+            if highlighted.end <= start {
+                continue;
+            }
+            if highlighted.start >= range.end {
+                break;
+            }
+            if highlighted.start > start {
+                missing.push(start..highlighted.start);
+            }
+            start = start.max(highlighted.end);
+        }
+        if start < range.end {
+            missing.push(start..range.end);
+        }
+        missing
+    }
+
+    /// Replaces the highlights in the given ranges with freshly computed ones, which must lie
+    /// within them.
+    pub fn merge(&mut self, ranges: &[Range<usize>], spans: HighlightSpans) {
+        for range in ranges {
+            clear(&mut self.spans, range.clone());
+        }
+        for (range, name) in spans.iter(..) {
+            self.spans.insert(range, name.clone());
+        }
+        for range in ranges {
+            self.add_highlighted(range.clone());
+        }
+    }
+
+    /// Forgets the highlights in the given ranges, to be highlighted again when next needed.
+    pub fn invalidate(&mut self, ranges: &[Range<usize>]) {
+        for range in ranges {
+            let removed = self.remove_spans(range.clone());
+            for range in std::iter::once(range.clone()).chain(removed) {
+                self.remove_highlighted(range);
+            }
+        }
+    }
+
+    /// Moves the highlights after an edit along with the text, and forgets those it touched.
+    pub(crate) fn edit(&mut self, edit: &Edit) {
+        let delta = edit.new_end_byte as isize - edit.old_end_byte as isize;
+        let shift = |offset: usize| match offset >= edit.old_end_byte {
+            true => offset.saturating_add_signed(delta),
+            false => offset,
+        };
+        let touched = edit.start_byte..edit.old_end_byte.max(edit.start_byte + 1);
+        self.invalidate(&[touched]);
+
+        if delta != 0 {
+            // nothing is left across the edit, so only the spans after it move.
+            let after: Vec<_> = self.spans.intervals(edit.old_end_byte..).collect();
+            let moved: Vec<_> = after
+                .into_iter()
+                .filter_map(|range| Some((range.clone(), self.spans.remove(range)?)))
+                .collect();
+            for (range, name) in moved {
+                self.spans
+                    .insert(shift(range.start)..shift(range.end), name);
+            }
+            let first = self
+                .highlighted
+                .partition_point(|range| range.end < edit.old_end_byte);
+            for range in self.highlighted[first..].iter_mut() {
+                *range = shift(range.start)..shift(range.end);
+            }
+        }
+        // the inserted text itself is not highlighted yet.
+        self.remove_highlighted(edit.start_byte..edit.new_end_byte.max(edit.start_byte + 1));
+    }
+
+    /// Removes the spans overlapping a range, returning their ranges.
+    fn remove_spans(&mut self, range: Range<usize>) -> Vec<Range<usize>> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let overlapping: Vec<_> = self.spans.intervals(range).collect();
+        for interval in overlapping.iter() {
+            self.spans.remove(interval.clone());
+        }
+        overlapping
+    }
+
+    fn add_highlighted(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        self.highlighted.retain(|highlighted| {
+            let touches = highlighted.start <= end && start <= highlighted.end;
+            if touches {
+                start = start.min(highlighted.start);
+                end = end.max(highlighted.end);
+            }
+            !touches
+        });
+        let idx = self
+            .highlighted
+            .partition_point(|highlighted| highlighted.start < start);
+        self.highlighted.insert(idx, start..end);
+    }
+
+    fn remove_highlighted(&mut self, range: Range<usize>) {
+        let mut kept = Vec::with_capacity(self.highlighted.len() + 1);
+        for highlighted in self.highlighted.drain(..) {
+            if highlighted.end <= range.start || highlighted.start >= range.end {
+                kept.push(highlighted);
+                continue;
+            }
+            if highlighted.start < range.start {
+                kept.push(highlighted.start..range.start);
+            }
+            if highlighted.end > range.end {
+                kept.push(range.end..highlighted.end);
+            }
+        }
+        self.highlighted = kept;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    fn spans(spans: &[(Range<usize>, &str)]) -> HighlightSpans {
+        let mut map = HighlightSpans::new();
+        for (range, name) in spans {
+            map.insert(range.clone(), name.to_string());
+        }
+        map
+    }
+
+    #[test]
+    fn tracks_missing_ranges() {
+        let mut highlights = Highlights::default();
+        highlights.merge(&[10..20, 30..40], spans(&[(12..15, "keyword")]));
+        assert_eq!(highlights.missing(0..50), vec![0..10, 20..30, 40..50]);
+        let gap = 20..30;
+        highlights.merge(&[gap], spans(&[]));
+        assert_eq!(highlights.missing(15..45), vec![40..45]);
+
+        let changed = 14..16;
+        highlights.invalidate(&[changed]);
+        assert_eq!(highlights.missing(0..40), vec![0..10, 12..16]);
+        assert_eq!(highlights.iter(0..50).count(), 0);
+    }
+
+    #[test]
+    fn merging_keeps_spans_apart() {
+        let mut highlights = Highlights::default();
+        let line = 0..20;
+        highlights.merge(&[line], spans(&[(0..20, "comment")]));
+        let mut inner = HighlightSpans::new();
+        paint(&mut inner, 5..15, "string".into());
+        paint(&mut inner, 8..10, "escape".into());
+        let middle = 5..15;
+        highlights.merge(&[middle], inner);
+        let names: Vec<_> = highlights
+            .iter(0..20)
+            .map(|(range, name)| (range, name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (0..5, "comment"),
+                (5..8, "string"),
+                (8..10, "escape"),
+                (10..15, "string"),
+                (15..20, "comment")
+            ]
+        );
+    }
+
+    #[test]
+    fn edits_shift_later_highlights() {
+        let mut highlights = Highlights::default();
+        let names = spans(&[(0..2, "keyword"), (3..8, "function"), (10..12, "string")]);
+        let line = 0..12;
+        highlights.merge(&[line], names);
+
+        // inserting two bytes at 5, inside the function name.
+        let point = Point { line: 0, column: 5 };
+        let new_end_point = Point { line: 0, column: 7 };
+        let edit = Edit {
+            start_byte: 5,
+            old_end_byte: 5,
+            new_end_byte: 7,
+            start_point: point,
+            old_end_point: point,
+            new_end_point,
+        };
+        highlights.edit(&edit);
+        let names: Vec<_> = highlights
+            .iter(0..20)
+            .map(|(range, name)| (range, name.as_str()))
+            .collect();
+        assert_eq!(names, vec![(0..2, "keyword"), (12..14, "string")]);
+        assert_eq!(highlights.missing(0..14), vec![3..10]);
+
+        // deleting the keyword.
+        let end = Point { line: 0, column: 2 };
+        let edit = Edit {
+            start_byte: 0,
+            old_end_byte: 2,
+            new_end_byte: 0,
+            start_point: Point::default(),
+            old_end_point: end,
+            new_end_point: Point::default(),
+        };
+        highlights.edit(&edit);
+        let names: Vec<_> = highlights
+            .iter(0..20)
+            .map(|(range, name)| (range, name.as_str()))
+            .collect();
+        assert_eq!(names, vec![(10..12, "string")]);
+        assert_eq!(highlights.missing(0..12), vec![0..8]);
+    }
+}
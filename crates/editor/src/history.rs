@@ -0,0 +1,283 @@
+use rope::Rope;
+
+use crate::buffer::Edit;
+
+/// A single edit, stored as the text it replaced rather than a snapshot of the whole buffer so
+/// that history stays small on large files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub char_idx: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    fn apply(&self, rope: &mut Rope) -> Edit {
+        let range = self.char_idx..self.char_idx + self.removed.chars().count();
+        let edit = Edit::new(rope, range.clone(), &self.inserted);
+        rope.remove(range);
+        rope.insert(self.char_idx, &self.inserted);
+        edit
+    }
+
+    fn revert(&self, rope: &mut Rope) -> Edit {
+        let range = self.char_idx..self.char_idx + self.inserted.chars().count();
+        let edit = Edit::new(rope, range.clone(), &self.removed);
+        rope.remove(range);
+        rope.insert(self.char_idx, &self.removed);
+        edit
+    }
+}
+
+#[derive(Debug, Default)]
+struct Transaction {
+    changes: Vec<Change>,
+}
+
+impl Transaction {
+    /// Adds a change, merging it into the previous one when it continues it, as consecutive
+    /// typed characters or backspaces do.
+    fn push(&mut self, change: Change) {
+        if let Some(last) = self.changes.last_mut() {
+            let last_end = last.char_idx + last.inserted.chars().count();
+            if change.removed.is_empty() && last.removed.is_empty() && change.char_idx == last_end {
+                last.inserted.push_str(&change.inserted);
+                return;
+            }
+
+            let change_end = change.char_idx + change.removed.chars().count();
+            if change.inserted.is_empty() && last.inserted.is_empty() {
+                if change_end == last.char_idx {
+                    last.char_idx = change.char_idx;
+                    last.removed.insert_str(0, &change.removed);
+                    return;
+                } else if change.char_idx == last.char_idx {
+                    last.removed.push_str(&change.removed);
+                    return;
+                }
+            }
+        }
+        self.changes.push(change);
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: usize,
+    /// The child most recently committed or undone from, which redo returns to.
+    last_child: Option<usize>,
+    transaction: Transaction,
+}
+
+/// The undo tree of a buffer. Committing after an undo starts a new branch instead of discarding
+/// the undone changes.
+#[derive(Debug)]
+pub(crate) struct History {
+    nodes: Vec<Node>,
+    current: usize,
+    pending: Option<Transaction>,
+    /// How many holds keep the pending transaction open.
+    holds: usize,
+    /// The node holding the contents that were last saved, if any does.
+    saved: Option<usize>,
+    /// Whether the contents were saved with the pending transaction's changes and none since, so
+    /// that its node holds them once it is committed.
+    pending_saved: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        let root = Node { parent: 0, last_child: None, transaction: Transaction::default() };
+        Self {
+            nodes: vec![root],
+            current: 0,
+            pending: None,
+            holds: 0,
+            saved: Some(0),
+            pending_saved: false,
+        }
+    }
+}
+
+impl History {
+    /// Groups the changes recorded from now on into a single transaction.
+    pub fn begin(&mut self) {
+        self.pending.get_or_insert_with(Transaction::default);
+    }
+
+    /// Keeps the pending transaction open across commits until [`History::release`]. Holds nest,
+    /// so the transaction stays open until each one is released.
+    pub fn hold(&mut self) {
+        self.begin();
+        self.holds += 1;
+    }
+
+    pub fn release(&mut self) {
+        self.holds = self.holds.saturating_sub(1);
+    }
+
+    pub fn commit(&mut self) {
+        if self.holds > 0 {
+            return;
+        }
+        if let Some(transaction) = self.pending.take() {
+            if !transaction.changes.is_empty() {
+                self.push(transaction);
+            }
+        }
+    }
+
+    /// Marks the contents as saved, without committing the pending transaction.
+    pub fn mark_saved(&mut self) {
+        match self.pending.as_ref().is_some_and(|t| !t.changes.is_empty()) {
+            true => (self.saved, self.pending_saved) = (None, true),
+            false => (self.saved, self.pending_saved) = (Some(self.current), false),
+        }
+    }
+
+    /// Whether the contents are the ones last saved, as of the last committed transaction.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+
+    /// Records a change, as a transaction of its own unless one has been begun.
+    pub fn record(&mut self, change: Change) {
+        match self.pending.as_mut() {
+            Some(transaction) => {
+                transaction.push(change);
+                self.pending_saved = false;
+            }
+            None => self.push(Transaction { changes: vec![change] }),
+        }
+    }
+
+    /// Reverts the current transaction, returning the offset of the first change it made. The
+    /// edits made to the rope are added to `edits`.
+    pub fn undo(&mut self, rope: &mut Rope, edits: &mut Vec<Edit>) -> Option<usize> {
+        self.commit();
+        if self.current == 0 {
+            return None;
+        }
+
+        let node = &self.nodes[self.current];
+        for change in node.transaction.changes.iter().rev() {
+            edits.push(change.revert(rope));
+        }
+        let char_idx = node.transaction.changes.first().map(|c| c.char_idx);
+        let (parent, child) = (node.parent, self.current);
+        self.nodes[parent].last_child = Some(child);
+        self.current = parent;
+        char_idx
+    }
+
+    /// Re-applies the most recently undone transaction, returning the offset of its first change.
+    pub fn redo(&mut self, rope: &mut Rope, edits: &mut Vec<Edit>) -> Option<usize> {
+        self.commit();
+        let child = self.nodes[self.current].last_child?;
+
+        let node = &self.nodes[child];
+        for change in node.transaction.changes.iter() {
+            edits.push(change.apply(rope));
+        }
+        self.current = child;
+        node.transaction.changes.first().map(|c| c.char_idx)
+    }
+
+    fn push(&mut self, transaction: Transaction) {
+        let idx = self.nodes.len();
+        self.nodes
+            .push(Node { parent: self.current, last_child: None, transaction });
+        self.nodes[self.current].last_child = Some(idx);
+        self.current = idx;
+        if std::mem::take(&mut self.pending_saved) {
+            self.saved = Some(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(char_idx: usize, text: &str) -> Change {
+        Change { char_idx, removed: String::new(), inserted: text.into() }
+    }
+
+    fn remove(char_idx: usize, text: &str) -> Change {
+        Change { char_idx, removed: text.into(), inserted: String::new() }
+    }
+
+    fn record(history: &mut History, rope: &mut Rope, change: Change) {
+        change.apply(rope);
+        history.record(change);
+    }
+
+    #[test]
+    fn transactions_coalesce_typing() {
+        let mut transaction = Transaction::default();
+        transaction.push(insert(3, "a"));
+        transaction.push(insert(4, "b"));
+        transaction.push(remove(4, "b"));
+        transaction.push(remove(3, "a"));
+        transaction.push(remove(3, "x"));
+        assert_eq!(transaction.changes, vec![insert(3, "ab"), remove(3, "abx")]);
+    }
+
+    #[test]
+    fn undo_redo_branches() {
+        let mut rope = Rope::from_str("hello");
+        let mut history = History::default();
+        let edits = &mut vec![];
+
+        history.begin();
+        record(&mut history, &mut rope, insert(5, " "));
+        record(&mut history, &mut rope, insert(6, "world"));
+        history.commit();
+        record(&mut history, &mut rope, remove(0, "h"));
+        assert_eq!(rope.to_string(), "ello world");
+
+        assert_eq!(history.undo(&mut rope, edits), Some(0));
+        assert_eq!(rope.to_string(), "hello world");
+        assert_eq!(history.undo(&mut rope, edits), Some(5));
+        assert_eq!(rope.to_string(), "hello");
+        assert_eq!(history.undo(&mut rope, edits), None);
+
+        assert_eq!(history.redo(&mut rope, edits), Some(5));
+        assert_eq!(rope.to_string(), "hello world");
+
+        // editing after an undo branches off, and redo follows the new branch.
+        record(&mut history, &mut rope, insert(0, ">"));
+        assert_eq!(history.undo(&mut rope, edits), Some(0));
+        assert_eq!(history.redo(&mut rope, edits), Some(0));
+        assert_eq!(rope.to_string(), ">hello world");
+        assert_eq!(history.redo(&mut rope, edits), None);
+    }
+
+    #[test]
+    fn saving_leaves_the_transaction_open() {
+        let mut rope = Rope::from_str("");
+        let mut history = History::default();
+        let edits = &mut vec![];
+
+        history.begin();
+        record(&mut history, &mut rope, insert(0, "a"));
+        history.mark_saved();
+        record(&mut history, &mut rope, insert(1, "b"));
+        history.commit();
+        // typing went on after saving, so no state holds what was saved.
+        assert_eq!(rope.to_string(), "ab");
+        assert!(!history.is_saved());
+        history.undo(&mut rope, edits);
+        assert!(!history.is_saved());
+
+        history.begin();
+        record(&mut history, &mut rope, insert(0, "c"));
+        history.mark_saved();
+        history.commit();
+        assert!(history.is_saved());
+        history.undo(&mut rope, edits);
+        assert!(!history.is_saved());
+        history.redo(&mut rope, edits);
+        assert!(history.is_saved());
+    }
+}
@@ -1,9 +1,32 @@
+mod brackets;
 mod buffer;
+mod cursors;
+mod edit;
 mod editor;
+mod fold;
+mod highlights;
+mod history;
 mod movement;
+mod operator;
+mod pending;
+mod search;
+mod selection;
+mod structure;
+mod substitute;
 
 pub use buffer::{
-    Buffer, Command as BufferCommand, Contents as BufferContents, Highlights, Id as BufferId,
+    Buffer, Command as BufferCommand, Contents as BufferContents, Edit, Id as BufferId, Indent,
 };
-pub use editor::{Command as EditorCommand, CursorJump, Direction, Editor, Id as EditorId, Mode};
+pub use edit::Placement;
+pub use editor::{
+    Command as EditorCommand, CursorJump, Direction, Editor, Id as EditorId, Mode, Motion,
+};
+pub use fold::{FoldCommand, Folds};
+pub use highlights::{paint, HighlightSpans, Highlights};
+pub use operator::{Operator, Target, TextObject, Yank};
+pub use pending::{Parsed, Pending};
+pub use search::Search;
+pub use selection::Selection;
+pub use structure::{ObjectRange, SyntaxMotion, SyntaxObject, SyntaxTree};
+pub use substitute::{Replacement, Substitute, Substitution};
 pub use tore::Point;
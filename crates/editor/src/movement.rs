@@ -1,14 +1,85 @@
 use tore::Point;
 
-use crate::{Buffer, Editor};
+use crate::{Buffer, CursorJump, Direction, Editor, Mode, Motion};
 
 impl Editor {
+    pub fn cursor_motion(&mut self, buffer: &Buffer, motion: &Motion, count: usize) {
+        match motion {
+            Motion::Move(direction) => {
+                for _ in 0..count {
+                    let from = self.cursor;
+                    match direction {
+                        Direction::Up => self.cursor_move_up(buffer),
+                        Direction::Down => self.cursor_move_down(buffer),
+                        Direction::Left => self.cursor_move_left(buffer),
+                        Direction::Right => self.cursor_move_right(buffer),
+                    }
+                    // the rest of a large count would not move it further.
+                    if self.cursor == from {
+                        break;
+                    }
+                }
+            }
+            Motion::Jump(jump) => {
+                for _ in 0..count {
+                    let from = self.cursor;
+                    match jump {
+                        CursorJump::StartOfNextWord => self.cursor_jump_start_of_next_word(buffer),
+                        CursorJump::StartOfLastWord => self.cursor_jump_start_of_last_word(buffer),
+                        CursorJump::EndOfNearestWord => {
+                            self.cursor_jump_end_of_nearest_word(buffer)
+                        }
+                        CursorJump::StartOfNearestWord => {
+                            self.cursor_jump_start_of_nearest_word(buffer)
+                        }
+                    }
+                    if self.cursor == from {
+                        break;
+                    }
+                }
+            }
+            Motion::StartOfLine => self.cursor_jump_line_zero(buffer),
+            Motion::FirstNonBlank => self.cursor_jump_first_non_blank(buffer),
+            Motion::EndOfLine => {
+                let line = std::cmp::min(
+                    self.cursor.line.saturating_add(count - 1),
+                    buffer.contents.last_line(),
+                );
+                self.cursor.line = line;
+                self.cursor.column = usize::MAX;
+                self.cursor_clamp(buffer);
+            }
+            Motion::StartOfFile | Motion::EndOfFile | Motion::Line(_) => {
+                let line = match motion {
+                    Motion::Line(line) => line.saturating_sub(1),
+                    Motion::EndOfFile => buffer.contents.last_line(),
+                    _ => 0,
+                };
+                self.cursor_jump_line(buffer, line);
+                self.open_folds_at_cursor();
+            }
+            Motion::Syntax(motion) => {
+                for _ in 0..count {
+                    let from = self.cursor;
+                    self.cursor_syntax_motion(buffer, *motion);
+                    if self.cursor == from {
+                        break;
+                    }
+                }
+            }
+            Motion::MatchingBracket => self.cursor_jump_matching_bracket(buffer),
+        }
+    }
+
     pub fn cursor_move_left(&mut self, _buffer: &Buffer) {
         self.cursor.move_prev_column();
     }
 
     pub fn cursor_move_up(&mut self, buffer: &Buffer) {
-        self.cursor.move_prev_line();
+        // over the lines of a closed fold at once.
+        if let Some(line) = self.folds.prev_shown(self.cursor.line) {
+            self.cursor.line = line;
+        }
         // match buffer.contents.line(self.cursor.line) {
         //     None => (),
         //     Some(line) => {
@@ -36,7 +107,11 @@ impl Editor {
     }
 
     pub fn cursor_move_down(&mut self, buffer: &Buffer) {
-        self.cursor.move_next_line();
+        let line = self.folds.next_shown(self.cursor.line);
+        if line > buffer.contents.last_line() {
+            return;
+        }
+        self.cursor.line = line;
         // match buffer.contents.line(self.cursor.line) {
         //     None => self.cursor.move_prev_line(),
         //     Some(line) => {
@@ -50,10 +125,42 @@ impl Editor {
         self.cursor.column = std::cmp::min(len, self.cursor.column);
     }
 
+    /// Moves the cursor back onto the last character of its line if it is past it, or just past the
+    /// last character in insert mode.
+    pub fn cursor_clamp(&mut self, buffer: &Buffer) {
+        let len = buffer.contents.line_len(self.cursor.line);
+        let len = match self.mode {
+            Mode::Insert => len,
+            _ if len == 0 => 0,
+            _ => len - 1,
+        };
+        self.cursor.column = std::cmp::min(len, self.cursor.column);
+    }
+
     pub fn cursor_jump_line_zero(&mut self, _buffer: &Buffer) {
         self.cursor.column = 0;
     }
 
+    pub fn cursor_jump_first_non_blank(&mut self, buffer: &Buffer) {
+        self.cursor.column = buffer.contents.first_non_blank(self.cursor.line);
+        self.cursor_clamp(buffer);
+    }
+
+    /// Jumps to the first non-blank character of a line, or the last line if it is past the end.
+    pub fn cursor_jump_line(&mut self, buffer: &Buffer, line: usize) {
+        self.cursor.line = std::cmp::min(line, buffer.contents.last_line());
+        self.cursor_jump_first_non_blank(buffer);
+    }
+
+    /// Jumps to a point in normal mode with a single cursor, opening the folds it is in.
+    pub fn jump_to(&mut self, buffer: &mut Buffer, point: Point) {
+        self.secondaries.clear();
+        self.set_mode(buffer, Mode::Normal);
+        self.cursor = point;
+        self.cursor_clamp(buffer);
+        self.open_folds_at_cursor();
+    }
+
     pub fn cursor_jump_start_of_nearest_word(&mut self, buffer: &Buffer) {
         let line_offset = buffer.contents.line_to_char(self.cursor.line);
         let mut offset = line_offset + self.cursor.column;
@@ -0,0 +1,597 @@
+use std::cmp::{max, min};
+
+use crate::{
+    Buffer, BufferContents, CursorJump, Direction, Editor, Mode, Motion, Point, SyntaxObject,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    Lowercase,
+    Uppercase,
+    ToggleCase,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextObject {
+    Word,
+    /// Text delimited by an opening and closing bracket, which may nest.
+    Pair(char, char),
+    /// Text delimited by a quote character, within a line.
+    Quote(char),
+    /// A node of the syntax tree, such as a function.
+    Syntax(SyntaxObject),
+}
+
+#[derive(Debug, Clone)]
+pub enum Target {
+    Motion(Motion),
+    Inner(TextObject),
+    Around(TextObject),
+    /// Whole lines starting at the cursor's, as doubled operators (`dd`, `yy`) act on.
+    Lines,
+}
+
+/// Text taken by a yank or delete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Yank {
+    pub text: String,
+    /// Whether whole lines were taken, rather than a run of characters.
+    pub linewise: bool,
+}
+
+/// The chars an operator acts on. Linewise spans cover whole lines, including their line endings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub linewise: bool,
+}
+
+impl Span {
+    pub(crate) fn chars(start: usize, end: usize) -> Self {
+        Self { start: min(start, end), end: max(start, end), linewise: false }
+    }
+
+    pub(crate) fn lines(contents: &BufferContents, first: usize, last: usize) -> Self {
+        let (first, last) = (min(first, last), max(first, last));
+        let start = contents.line_to_char(first);
+        let end = contents.line_to_char(last + 1);
+        Self { start, end, linewise: true }
+    }
+}
+
+impl Editor {
+    /// Applies an operator to the text a motion or text object covers, returning the text it took.
+    pub fn operate(
+        &mut self,
+        buffer: &mut Buffer,
+        operator: Operator,
+        count: usize,
+        target: &Target,
+    ) -> Option<Yank> {
+        let span = self.target_span(buffer, operator, count, target)?;
+        self.apply_operator(buffer, operator, span)
+    }
+
+    pub(crate) fn apply_operator(
+        &mut self,
+        buffer: &mut Buffer,
+        operator: Operator,
+        span: Span,
+    ) -> Option<Yank> {
+        let contents = &buffer.contents;
+        let start = contents.char_offset_to_point(span.start);
+        let last_line = contents.char_to_line(max(span.start, span.end.saturating_sub(1)));
+        let mut text = contents.slice(span.start..span.end).to_string();
+        if span.linewise && !text.ends_with('\n') {
+            text.push_str(contents.line_ending_at(start.line));
+        }
+        let yank = Yank { text, linewise: span.linewise };
+
+        match operator {
+            Operator::Yank => {
+                self.cursor.line = start.line;
+                if !span.linewise {
+                    self.cursor = start;
+                }
+                self.cursor_clamp(buffer);
+                Some(yank)
+            }
+            Operator::Delete => {
+                if span.start == span.end {
+                    return None;
+                }
+                buffer.remove(removal_range(&buffer.contents, span));
+                if span.linewise {
+                    self.cursor_jump_line(buffer, start.line);
+                } else {
+                    self.cursor = start;
+                    self.cursor_clamp(buffer);
+                }
+                Some(yank)
+            }
+            Operator::Change => {
+                buffer.begin_transaction();
+                if span.linewise {
+                    // keep the lines' indentation and a single line to type into.
+                    let indent = buffer.contents.first_non_blank(start.line);
+                    let last_start = buffer.contents.line_to_char(last_line);
+                    let end = last_start + buffer.contents.line_len(last_line);
+                    buffer.remove(span.start + indent..max(span.start + indent, end));
+                    self.cursor = Point { line: start.line, column: indent };
+                } else {
+                    buffer.remove(span.start..span.end);
+                    self.cursor = start;
+                }
+                self.set_mode(buffer, Mode::Insert);
+                Some(yank)
+            }
+            Operator::Indent | Operator::Outdent => {
+                buffer.begin_transaction();
+                for line in start.line..=last_line {
+                    match operator {
+                        Operator::Indent => indent_line(buffer, line),
+                        _ => outdent_line(buffer, line),
+                    }
+                }
+                buffer.commit_transaction();
+                self.cursor_jump_line(buffer, start.line);
+                None
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                let text = buffer.contents.slice(span.start..span.end).to_string();
+                let converted: String = text
+                    .chars()
+                    .flat_map(|c| convert_case(operator, c))
+                    .collect();
+                if converted != text {
+                    buffer.begin_transaction();
+                    buffer.remove(span.start..span.end);
+                    buffer.insert(span.start, &converted);
+                    buffer.commit_transaction();
+                }
+                self.cursor = start;
+                self.cursor_clamp(buffer);
+                None
+            }
+        }
+    }
+
+    fn target_span(
+        &mut self,
+        buffer: &Buffer,
+        operator: Operator,
+        count: usize,
+        target: &Target,
+    ) -> Option<Span> {
+        let contents = &buffer.contents;
+        match target {
+            Target::Lines => {
+                let last = min(self.cursor.line.saturating_add(count - 1), contents.last_line());
+                Some(Span::lines(contents, self.cursor.line, last))
+            }
+            Target::Inner(object) => self.text_object_span(buffer, object, true, count),
+            Target::Around(object) => self.text_object_span(buffer, object, false, count),
+            Target::Motion(motion) => Some(self.motion_span(buffer, operator, motion, count)),
+        }
+    }
+
+    fn motion_span(
+        &mut self,
+        buffer: &Buffer,
+        operator: Operator,
+        motion: &Motion,
+        count: usize,
+    ) -> Span {
+        let contents = &buffer.contents;
+        let start = self.cursor;
+        let offset = contents.point_to_char_offset(start);
+        let line_start = offset - start.column;
+        let on_blank = matches!(contents.get_char(offset), None | Some(' ' | '\t' | '\r' | '\n'));
+
+        match motion {
+            Motion::Move(Direction::Left) => Span::chars(offset, offset - min(start.column, count)),
+            Motion::Move(Direction::Right) => {
+                let end = min(start.column.saturating_add(count), contents.line_len(start.line));
+                Span::chars(offset, line_start + max(end, start.column))
+            }
+            Motion::EndOfLine => {
+                let line = min(start.line.saturating_add(count - 1), contents.last_line());
+                let end = contents.line_to_char(line) + contents.line_len(line);
+                Span::chars(offset, max(offset, end))
+            }
+            // `cw` changes to the end of the word, like `ce`.
+            Motion::Jump(CursorJump::StartOfNextWord)
+                if operator == Operator::Change && !on_blank =>
+            {
+                let end =
+                    self.motion_target(buffer, &Motion::Jump(CursorJump::EndOfNearestWord), count);
+                let end = contents.point_to_char_offset(end);
+                Span::chars(offset, max(offset, end) + 1)
+            }
+            // the last word moved over at the end of a line ends the span there, rather than
+            // taking the line break and the next line's indentation with it.
+            Motion::Jump(CursorJump::StartOfNextWord) => {
+                let target = self.motion_target(buffer, motion, count);
+                let end = contents.point_to_char_offset(target);
+                if target.line > start.line {
+                    let line = target.line - 1;
+                    let line_end = contents.line_to_char(line) + contents.line_len(line);
+                    Span::chars(offset, max(offset, min(end, line_end)))
+                } else {
+                    Span::chars(offset, end)
+                }
+            }
+            Motion::Jump(CursorJump::EndOfNearestWord) => {
+                let end = self.motion_target(buffer, motion, count);
+                let end = contents.point_to_char_offset(end);
+                Span::chars(offset, max(offset, end) + 1)
+            }
+            // both brackets are taken, or nothing if there is no pair.
+            Motion::MatchingBracket => {
+                let end = self.motion_target(buffer, motion, count);
+                match contents.point_to_char_offset(end) {
+                    end if end == offset => Span::chars(offset, offset),
+                    end => Span::chars(min(offset, end), max(offset, end) + 1),
+                }
+            }
+            Motion::Jump(_) | Motion::StartOfLine | Motion::FirstNonBlank | Motion::Syntax(_) => {
+                let end = self.motion_target(buffer, motion, count);
+                Span::chars(offset, contents.point_to_char_offset(end))
+            }
+            Motion::Move(Direction::Up | Direction::Down)
+            | Motion::StartOfFile
+            | Motion::EndOfFile
+            | Motion::Line(_) => {
+                let end = self.motion_target(buffer, motion, count);
+                Span::lines(contents, start.line, end.line)
+            }
+        }
+    }
+
+    /// Where a motion would move the cursor to, leaving the cursor in place.
+    fn motion_target(&mut self, buffer: &Buffer, motion: &Motion, count: usize) -> Point {
+        let cursor = self.cursor;
+        self.cursor_motion(buffer, motion, count);
+        std::mem::replace(&mut self.cursor, cursor)
+    }
+
+    pub(crate) fn text_object_span(
+        &self,
+        buffer: &Buffer,
+        object: &TextObject,
+        inner: bool,
+        count: usize,
+    ) -> Option<Span> {
+        let contents = &buffer.contents;
+        match object {
+            TextObject::Word => word_span(contents, self.cursor, inner),
+            TextObject::Pair(open, close) => {
+                let offset = contents.point_to_char_offset(self.cursor);
+                pair_span(contents, offset, (*open, *close), inner, count)
+            }
+            TextObject::Quote(quote) => quote_span(contents, self.cursor, *quote, inner),
+            TextObject::Syntax(object) => self.syntax_object_span(buffer, *object, inner, count),
+        }
+    }
+}
+
+/// The chars to remove to delete a span. Deleting the last lines of a buffer takes the line
+/// ending before them, so that no empty line is left behind.
+fn removal_range(contents: &BufferContents, span: Span) -> std::ops::Range<usize> {
+    let ends_in_newline = span.end > 0 && contents.char(span.end - 1) == '\n';
+    if span.linewise && !ends_in_newline && span.start > 0 {
+        let prev_line = contents.char_to_line(span.start - 1);
+        let prev_end = contents.line_to_char(prev_line) + contents.line_len(prev_line);
+        prev_end..span.end
+    } else {
+        span.start..span.end
+    }
+}
+
+fn indent_line(buffer: &mut Buffer, line: usize) {
+    if buffer.contents.line_len(line) == 0 {
+        return;
+    }
+    let offset = buffer.contents.line_to_char(line);
+    buffer.insert(offset, &buffer.indent.unit());
+}
+
+fn outdent_line(buffer: &mut Buffer, line: usize) {
+    let offset = buffer.contents.line_to_char(line);
+    let width = match buffer.indent {
+        crate::Indent::Tabs => 1,
+        crate::Indent::Spaces(width) => width,
+    };
+    let mut chars = buffer.contents.line(line).chars();
+    let len = match chars.next() {
+        Some('\t') => 1,
+        Some(' ') => 1 + chars.take(width - 1).take_while(|c| *c == ' ').count(),
+        _ => 0,
+    };
+    if len > 0 {
+        buffer.remove(offset..offset + len);
+    }
+}
+
+pub(crate) fn convert_case(operator: Operator, c: char) -> Vec<char> {
+    match operator {
+        Operator::Lowercase => c.to_lowercase().collect(),
+        Operator::Uppercase => c.to_uppercase().collect(),
+        _ if c.is_uppercase() => c.to_lowercase().collect(),
+        _ => c.to_uppercase().collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c == ' ' || c == '\t' {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// The word or run of blanks under the cursor. Around a word also takes the blanks after it, or
+/// the ones before it if there are none after; around blanks also takes the word after them.
+fn word_span(contents: &BufferContents, cursor: Point, inner: bool) -> Option<Span> {
+    let line = contents.line(cursor.line);
+    let len = contents.line_len(cursor.line);
+    if cursor.column >= len {
+        return None;
+    }
+    let class_at = |column: usize| char_class(line.char(column));
+    let extend_back = |mut column: usize, class: CharClass| {
+        while column > 0 && class_at(column - 1) == class {
+            column -= 1;
+        }
+        column
+    };
+    let extend_forward = |mut column: usize, class: CharClass| {
+        while column < len && class_at(column) == class {
+            column += 1;
+        }
+        column
+    };
+
+    let class = class_at(cursor.column);
+    let mut start = extend_back(cursor.column, class);
+    let mut end = extend_forward(cursor.column, class);
+    if !inner {
+        if class == CharClass::Blank {
+            if end < len {
+                end = extend_forward(end, class_at(end));
+            }
+        } else if end < len && class_at(end) == CharClass::Blank {
+            end = extend_forward(end, CharClass::Blank);
+        } else if start > 0 && class_at(start - 1) == CharClass::Blank {
+            start = extend_back(start, CharClass::Blank);
+        }
+    }
+
+    let line_start = contents.line_to_char(cursor.line);
+    Some(Span::chars(line_start + start, line_start + end))
+}
+
+/// The innermost `count` levels of brackets around `offset`. Inside a block whose brackets sit on
+/// lines of their own, the span is the whole lines between them.
+fn pair_span(
+    contents: &BufferContents,
+    offset: usize,
+    (open, close): (char, char),
+    inner: bool,
+    count: usize,
+) -> Option<Span> {
+    let (mut open_idx, mut close_idx) = match contents.get_char(offset) {
+        Some(c) if c == open => (offset, find_close(contents, offset + 1, open, close)?),
+        Some(c) if c == close => (find_open(contents, offset, open, close)?, offset),
+        _ => {
+            let open_idx = find_open(contents, offset, open, close)?;
+            (open_idx, find_close(contents, open_idx + 1, open, close)?)
+        }
+    };
+    for _ in 1..count {
+        open_idx = find_open(contents, open_idx, open, close)?;
+        close_idx = find_close(contents, open_idx + 1, open, close)?;
+    }
+
+    if !inner {
+        return Some(Span::chars(open_idx, close_idx + 1));
+    }
+
+    let open_line = contents.char_to_line(open_idx);
+    let close_line = contents.char_to_line(close_idx);
+    let open_line_end = contents.line_to_char(open_line) + contents.line_len(open_line);
+    let close_line_start = contents.line_to_char(close_line);
+    let close_column = close_idx - close_line_start;
+    if close_line > open_line + 1
+        && open_line_end == open_idx + 1
+        && contents.first_non_blank(close_line) == close_column
+    {
+        return Some(Span::lines(contents, open_line + 1, close_line - 1));
+    }
+    Some(Span::chars(open_idx + 1, close_idx))
+}
+
+/// Finds the unmatched opening bracket before `offset`.
+fn find_open(contents: &BufferContents, offset: usize, open: char, close: char) -> Option<usize> {
+    let mut chars = contents.chars_at(offset);
+    let mut idx = offset;
+    let mut depth = 0;
+    while let Some(c) = chars.prev() {
+        idx -= 1;
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                return Some(idx);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Finds the unmatched closing bracket from `offset` onwards.
+fn find_close(contents: &BufferContents, offset: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, c) in contents.chars_at(offset).enumerate() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some(offset + idx);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// The quoted string on the cursor's line that the cursor is in, or the next one after it.
+fn quote_span(contents: &BufferContents, cursor: Point, quote: char, inner: bool) -> Option<Span> {
+    let line = contents.line(cursor.line);
+    let mut quotes = vec![];
+    let mut escaped = false;
+    for (column, c) in line.chars().enumerate() {
+        if c == quote && !escaped {
+            quotes.push(column);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+
+    let line_start = contents.line_to_char(cursor.line);
+    let (start, end) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, end)| *end >= cursor.column)?;
+    if inner {
+        Some(Span::chars(line_start + start + 1, line_start + end))
+    } else {
+        Some(Span::chars(line_start + start, line_start + end + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferId, EditorId};
+
+    fn setup(text: &str, line: usize, column: usize) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.cursor = Point { line, column };
+        (editor, buffer)
+    }
+
+    fn operate(text: &str, cursor: (usize, usize), operator: Operator, target: Target) -> String {
+        let (mut editor, mut buffer) = setup(text, cursor.0, cursor.1);
+        editor.operate(&mut buffer, operator, 1, &target);
+        buffer.contents.to_string()
+    }
+
+    #[test]
+    fn delete_motions() {
+        let word = Target::Motion(Motion::Jump(CursorJump::StartOfNextWord));
+        assert_eq!(operate("foo bar baz", (0, 4), Operator::Delete, word.clone()), "foo baz");
+        assert_eq!(operate("foo bar\n  baz", (0, 4), Operator::Delete, word), "foo \n  baz");
+
+        let end = Target::Motion(Motion::EndOfLine);
+        assert_eq!(operate("foo bar\nbaz", (0, 3), Operator::Delete, end), "foo\nbaz");
+
+        let right = Target::Motion(Motion::Move(Direction::Right));
+        assert_eq!(operate("ab", (0, 1), Operator::Delete, right), "a");
+    }
+
+    #[test]
+    fn oversized_counts_stop_at_the_end() {
+        let (mut editor, mut buffer) = setup("a\nb\nc\nd", 2, 0);
+        editor.operate(&mut buffer, Operator::Delete, usize::MAX, &Target::Lines);
+        assert_eq!(buffer.contents.to_string(), "a\nb");
+
+        let (mut editor, mut buffer) = setup("a b\nc d", 0, 0);
+        let end = Target::Motion(Motion::EndOfLine);
+        editor.operate(&mut buffer, Operator::Delete, usize::MAX, &end);
+        assert_eq!(buffer.contents.to_string(), "");
+        let (mut editor, buffer) = setup("a b\nc d", 0, 0);
+        let word = Motion::Jump(CursorJump::StartOfNextWord);
+        editor.cursor_motion(&buffer, &word, usize::MAX);
+        editor.cursor_motion(&buffer, &Motion::Move(Direction::Down), usize::MAX);
+        assert_eq!(editor.cursor.line, 1);
+    }
+
+    #[test]
+    fn delete_lines() {
+        let (mut editor, mut buffer) = setup("one\ntwo\nthree\nfour", 1, 0);
+        let yank = editor.operate(&mut buffer, Operator::Delete, 2, &Target::Lines);
+        assert_eq!(buffer.contents.to_string(), "one\nfour");
+        assert_eq!(yank, Some(Yank { text: "two\nthree\n".into(), linewise: true }));
+
+        let (mut editor, mut buffer) = setup("one\ntwo", 1, 0);
+        let yank = editor.operate(&mut buffer, Operator::Delete, 1, &Target::Lines);
+        assert_eq!(buffer.contents.to_string(), "one");
+        assert_eq!(yank, Some(Yank { text: "two\n".into(), linewise: true }));
+        assert_eq!(editor.cursor, Point { line: 0, column: 0 });
+    }
+
+    #[test]
+    fn change_word_keeps_trailing_blank() {
+        let word = Target::Motion(Motion::Jump(CursorJump::StartOfNextWord));
+        let (mut editor, mut buffer) = setup("foo bar baz", 0, 4);
+        editor.operate(&mut buffer, Operator::Change, 1, &word);
+        assert_eq!(buffer.contents.to_string(), "foo  baz");
+        assert_eq!(editor.mode, Mode::Insert);
+        assert_eq!(editor.cursor, Point { line: 0, column: 4 });
+    }
+
+    #[test]
+    fn text_objects() {
+        let inner_word = Target::Inner(TextObject::Word);
+        let around_word = Target::Around(TextObject::Word);
+        assert_eq!(operate("foo bar baz", (0, 5), Operator::Delete, inner_word), "foo  baz");
+        assert_eq!(operate("foo bar baz", (0, 5), Operator::Delete, around_word), "foo baz");
+
+        let parens = TextObject::Pair('(', ')');
+        let inner = Target::Inner(parens.clone());
+        assert_eq!(operate("f(a, (b), c)", (0, 3), Operator::Delete, inner.clone()), "f()");
+        assert_eq!(operate("f(a, (b), c)", (0, 6), Operator::Delete, inner), "f(a, (), c)");
+        let around = Target::Around(parens);
+        assert_eq!(operate("f(a, (b), c)", (0, 1), Operator::Delete, around), "f");
+
+        let block = Target::Inner(TextObject::Pair('{', '}'));
+        assert_eq!(
+            operate("fn f() {\n    a;\n}\n", (1, 4), Operator::Delete, block),
+            "fn f() {\n}\n"
+        );
+
+        let quote = Target::Inner(TextObject::Quote('"'));
+        assert_eq!(operate(r#"a("b\"c", "d")"#, (0, 0), Operator::Delete, quote), r#"a("", "d")"#);
+    }
+
+    #[test]
+    fn indent_and_case() {
+        let (mut editor, mut buffer) = setup("a\n\nb", 0, 0);
+        editor.operate(&mut buffer, Operator::Indent, 3, &Target::Lines);
+        assert_eq!(buffer.contents.to_string(), "    a\n\n    b");
+        editor.operate(&mut buffer, Operator::Outdent, 1, &Target::Lines);
+        assert_eq!(buffer.contents.to_string(), "a\n\n    b");
+
+        let word = Target::Motion(Motion::Jump(CursorJump::EndOfNearestWord));
+        assert_eq!(operate("foo bar", (0, 0), Operator::Uppercase, word.clone()), "FOO bar");
+        assert_eq!(operate("FoO bar", (0, 0), Operator::ToggleCase, word), "fOo bar");
+    }
+}
@@ -0,0 +1,482 @@
+use crate::editor::Command;
+use crate::operator::{Operator, Target, TextObject};
+use crate::{CursorJump, Direction, FoldCommand, Motion, Placement, SyntaxMotion, SyntaxObject};
+
+/// The outcome of feeding a key to [`Pending`].
+#[derive(Debug, Clone)]
+pub enum Parsed {
+    /// The key started or continued a command that needs more keys.
+    Pending,
+    /// A complete command, and the register it was prefixed with (`"a`), which takes any text it
+    /// yanks or deletes.
+    Complete(Command, Option<char>),
+    /// `p` or `P`: puts the contents of a register, or the unnamed one, `count` times.
+    Put(Option<char>, Placement, usize),
+    /// The key did not fit the command typed so far, which was dropped.
+    Cancelled,
+    /// A key that enters insert mode came after a count or register, which inserts do not take;
+    /// holds the keys typed, which were dropped.
+    Refused(String),
+    /// The key does not start a command this parser knows; the caller may map it itself.
+    Unhandled,
+}
+
+/// The largest count, as in vim, so that the counts typed stay clear of overflowing.
+const MAX_COUNT: usize = 999_999_999;
+
+/// The normal mode command being typed: `[count] operator [count] motion`, or a shortcut such as
+/// `x` or `D`.
+#[derive(Debug, Default)]
+pub struct Pending {
+    keys: String,
+    count: Option<usize>,
+    register: Option<char>,
+    operator: Option<(Operator, Option<usize>)>,
+    prefix: Option<char>,
+}
+
+impl Pending {
+    /// The keys typed so far for the pending command.
+    pub fn keys(&self) -> &str {
+        &self.keys
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn feed(&mut self, c: char) -> Parsed {
+        let parsed = self.parse(c);
+        match parsed {
+            Parsed::Pending => self.keys.push(c),
+            Parsed::Unhandled => {}
+            Parsed::Complete(..) | Parsed::Put(..) | Parsed::Cancelled | Parsed::Refused(_) => {
+                self.reset()
+            }
+        }
+        parsed
+    }
+
+    /// Feeds a key typed in one of the visual modes, where operators act on the selection at once
+    /// and `i` or `a` start a text object to select.
+    pub fn feed_visual(&mut self, c: char) -> Parsed {
+        if self.prefix.is_some() || self.operator.is_some() {
+            return self.feed(c);
+        }
+        let operator = match c {
+            'd' | 'x' => Operator::Delete,
+            'c' | 's' => Operator::Change,
+            'y' => Operator::Yank,
+            '>' => Operator::Indent,
+            '<' => Operator::Outdent,
+            'u' => Operator::Lowercase,
+            'U' => Operator::Uppercase,
+            '~' => Operator::ToggleCase,
+            'i' | 'a' => {
+                self.prefix = Some(c);
+                self.keys.push(c);
+                return Parsed::Pending;
+            }
+            // `o` goes to the other end of the selection, which no count changes.
+            'o' | 'O' => {
+                self.reset();
+                return Parsed::Unhandled;
+            }
+            _ => return self.feed(c),
+        };
+        let parsed = Parsed::Complete(Command::OperateSelection(operator), self.register);
+        self.reset();
+        parsed
+    }
+
+    fn is_idle(&self) -> bool {
+        self.count.is_none()
+            && self.register.is_none()
+            && self.operator.is_none()
+            && self.prefix.is_none()
+    }
+
+    fn parse(&mut self, c: char) -> Parsed {
+        if let Some(prefix) = self.prefix.take() {
+            return match prefix {
+                '"' => self.parse_register(c),
+                'g' => self.parse_g(c),
+                ']' | '[' => self.parse_syntax_motion(prefix == ']', c),
+                'z' => self.parse_z(c),
+                _ => self.parse_text_object(prefix == 'i', c),
+            };
+        }
+
+        if let Some(digit) = c.to_digit(10) {
+            if digit != 0 || self.count.is_some() {
+                let count = self.count.unwrap_or(0).saturating_mul(10);
+                self.count = Some(count.saturating_add(digit as usize).min(MAX_COUNT));
+                return Parsed::Pending;
+            }
+        }
+
+        if let Some((operator, _)) = self.operator {
+            if doubled_key(operator) == c {
+                return self.complete(Target::Lines);
+            }
+        }
+
+        let operator = match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            '>' => Some(Operator::Indent),
+            '<' => Some(Operator::Outdent),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            return self.begin_operator(operator);
+        }
+
+        let motion = match c {
+            'h' => Some(Motion::Move(Direction::Left)),
+            'j' => Some(Motion::Move(Direction::Down)),
+            'k' => Some(Motion::Move(Direction::Up)),
+            'l' => Some(Motion::Move(Direction::Right)),
+            'w' => Some(Motion::Jump(CursorJump::StartOfNextWord)),
+            'b' => Some(Motion::Jump(CursorJump::StartOfNearestWord)),
+            'e' => Some(Motion::Jump(CursorJump::EndOfNearestWord)),
+            '0' => Some(Motion::StartOfLine),
+            '^' => Some(Motion::FirstNonBlank),
+            '$' => Some(Motion::EndOfLine),
+            'G' => Some(self.line_or(Motion::EndOfFile)),
+            '%' => Some(Motion::MatchingBracket),
+            _ => None,
+        };
+        if let Some(motion) = motion {
+            return self.complete(Target::Motion(motion));
+        }
+
+        match c {
+            'g' | ']' | '[' => {
+                self.prefix = Some(c);
+                Parsed::Pending
+            }
+            'z' if self.operator.is_none() => {
+                self.prefix = Some(c);
+                Parsed::Pending
+            }
+            '"' if self.operator.is_none() => {
+                self.prefix = Some(c);
+                Parsed::Pending
+            }
+            'p' | 'P' if self.operator.is_none() => {
+                let placement = if c == 'p' {
+                    Placement::After
+                } else {
+                    Placement::Before
+                };
+                let count = self.total_count().unwrap_or(1);
+                Parsed::Put(self.register, placement, count)
+            }
+            'i' | 'a' if self.operator.is_some() => {
+                self.prefix = Some(c);
+                Parsed::Pending
+            }
+            'x' | 'X' | 'D' | 'C' | 's' | 'S' | 'Y' if self.operator.is_none() => {
+                let (operator, target) = match c {
+                    'x' => (Operator::Delete, Target::Motion(Motion::Move(Direction::Right))),
+                    'X' => (Operator::Delete, Target::Motion(Motion::Move(Direction::Left))),
+                    'D' => (Operator::Delete, Target::Motion(Motion::EndOfLine)),
+                    'C' => (Operator::Change, Target::Motion(Motion::EndOfLine)),
+                    's' => (Operator::Change, Target::Motion(Motion::Move(Direction::Right))),
+                    'S' => (Operator::Change, Target::Lines),
+                    _ => (Operator::Yank, Target::Lines),
+                };
+                self.operator = Some((operator, None));
+                self.complete(target)
+            }
+            _ if self.is_idle() => Parsed::Unhandled,
+            'i' | 'o' | 'O' if self.operator.is_none() => {
+                Parsed::Refused(format!("{}{}", self.keys, c))
+            }
+            _ => Parsed::Cancelled,
+        }
+    }
+
+    fn parse_register(&mut self, c: char) -> Parsed {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '"' | '+' | '*' | '_' => {
+                self.register = Some(c);
+                Parsed::Pending
+            }
+            _ => Parsed::Cancelled,
+        }
+    }
+
+    fn parse_g(&mut self, c: char) -> Parsed {
+        let operator = match c {
+            'g' => return self.complete(Target::Motion(self.line_or(Motion::StartOfFile))),
+            'u' => Operator::Lowercase,
+            'U' => Operator::Uppercase,
+            '~' => Operator::ToggleCase,
+            _ => return Parsed::Cancelled,
+        };
+        match self.operator {
+            // `gugu` is the same as `guu`.
+            Some((pending, _)) if pending == operator => self.complete(Target::Lines),
+            _ => self.begin_operator(operator),
+        }
+    }
+
+    /// `za`, `zo` and `zc` toggle, open and close the fold at the cursor; `zR` and `zM` open and
+    /// close every fold.
+    fn parse_z(&mut self, c: char) -> Parsed {
+        let command = match c {
+            'a' => FoldCommand::Toggle,
+            'o' => FoldCommand::Open,
+            'c' => FoldCommand::Close,
+            'R' => FoldCommand::OpenAll,
+            'M' => FoldCommand::CloseAll,
+            _ => return Parsed::Cancelled,
+        };
+        Parsed::Complete(Command::Fold(command), None)
+    }
+
+    fn parse_text_object(&mut self, inner: bool, c: char) -> Parsed {
+        let object = match c {
+            'w' => TextObject::Word,
+            '(' | ')' | 'b' => TextObject::Pair('(', ')'),
+            '[' | ']' => TextObject::Pair('[', ']'),
+            '{' | '}' | 'B' => TextObject::Pair('{', '}'),
+            '<' | '>' => TextObject::Pair('<', '>'),
+            '"' | '\'' | '`' => TextObject::Quote(c),
+            _ => match syntax_object(c) {
+                Some(object) => TextObject::Syntax(object),
+                None => return Parsed::Cancelled,
+            },
+        };
+        match inner {
+            true => self.complete(Target::Inner(object)),
+            false => self.complete(Target::Around(object)),
+        }
+    }
+
+    /// `]f` goes to the next function and `[f` to the previous one; `]n` and `[n` go to the next
+    /// and previous syntax node at the cursor's level.
+    fn parse_syntax_motion(&mut self, forward: bool, c: char) -> Parsed {
+        let motion = match (c, syntax_object(c)) {
+            ('n', _) if forward => SyntaxMotion::NextSibling,
+            ('n', _) => SyntaxMotion::PreviousSibling,
+            (_, Some(object)) if forward => SyntaxMotion::Next(object),
+            (_, Some(object)) => SyntaxMotion::Previous(object),
+            (_, None) => return Parsed::Cancelled,
+        };
+        self.complete(Target::Motion(Motion::Syntax(motion)))
+    }
+
+    fn begin_operator(&mut self, operator: Operator) -> Parsed {
+        if self.operator.is_some() {
+            return Parsed::Cancelled;
+        }
+        self.operator = Some((operator, self.count.take()));
+        Parsed::Pending
+    }
+
+    /// The count typed before the operator and before the motion multiply, as in `2d3w`.
+    fn total_count(&self) -> Option<usize> {
+        let operator_count = self.operator.and_then(|(_, count)| count);
+        match (operator_count, self.count) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1)).min(MAX_COUNT)),
+        }
+    }
+
+    /// With a count, `G` and `gg` go to that line instead of the end or start of the file.
+    fn line_or(&self, motion: Motion) -> Motion {
+        self.total_count().map(Motion::Line).unwrap_or(motion)
+    }
+
+    fn complete(&mut self, target: Target) -> Parsed {
+        let count = match target {
+            Target::Motion(Motion::Line(_)) => 1,
+            _ => self.total_count().unwrap_or(1),
+        };
+        let command = match (self.operator.take(), target) {
+            (Some((operator, _)), target) => Command::Operate(operator, count, target),
+            (None, Target::Motion(motion)) => Command::Motion(motion, count),
+            (None, target @ (Target::Inner(_) | Target::Around(_))) => {
+                Command::SelectTextObject(target)
+            }
+            (None, _) => return Parsed::Cancelled,
+        };
+        Parsed::Complete(command, self.register)
+    }
+}
+
+/// The syntax objects by the key that names them after `i`, `a`, `]` or `[`.
+fn syntax_object(c: char) -> Option<SyntaxObject> {
+    match c {
+        'f' => Some(SyntaxObject::Function),
+        'c' => Some(SyntaxObject::Class),
+        'a' => Some(SyntaxObject::Parameter),
+        '/' => Some(SyntaxObject::Comment),
+        'o' => Some(SyntaxObject::Block),
+        _ => None,
+    }
+}
+
+/// The key that, repeated after an operator, makes it act on whole lines.
+fn doubled_key(operator: Operator) -> char {
+    match operator {
+        Operator::Delete => 'd',
+        Operator::Change => 'c',
+        Operator::Yank => 'y',
+        Operator::Indent => '>',
+        Operator::Outdent => '<',
+        Operator::Lowercase => 'u',
+        Operator::Uppercase => 'U',
+        Operator::ToggleCase => '~',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(keys: &str) -> Vec<Parsed> {
+        let mut pending = Pending::default();
+        keys.chars().map(|c| pending.feed(c)).collect()
+    }
+
+    fn last(keys: &str) -> Parsed {
+        feed(keys).pop().unwrap()
+    }
+
+    #[test]
+    fn counts_multiply() {
+        let parsed = last("2d3w");
+        assert!(matches!(
+            parsed,
+            Parsed::Complete(
+                Command::Operate(
+                    Operator::Delete,
+                    6,
+                    Target::Motion(Motion::Jump(CursorJump::StartOfNextWord))
+                ),
+                _
+            )
+        ));
+        assert!(matches!(last("10j"), Parsed::Complete(Command::Motion(_, 10), _)));
+        assert!(matches!(
+            last("99999999999999999999d99999999999999999999d"),
+            Parsed::Complete(Command::Operate(Operator::Delete, MAX_COUNT, Target::Lines), _)
+        ));
+        assert!(matches!(
+            last("0"),
+            Parsed::Complete(Command::Motion(Motion::StartOfLine, 1), _)
+        ));
+    }
+
+    #[test]
+    fn doubled_operators_take_lines() {
+        assert!(matches!(
+            last("3dd"),
+            Parsed::Complete(Command::Operate(Operator::Delete, 3, Target::Lines), None)
+        ));
+        assert!(matches!(
+            last("guu"),
+            Parsed::Complete(Command::Operate(Operator::Lowercase, 1, Target::Lines), _)
+        ));
+        assert!(matches!(
+            last("gUgU"),
+            Parsed::Complete(Command::Operate(Operator::Uppercase, 1, Target::Lines), _)
+        ));
+    }
+
+    #[test]
+    fn text_objects_and_lines() {
+        assert!(matches!(
+            last("ci("),
+            Parsed::Complete(
+                Command::Operate(Operator::Change, 1, Target::Inner(TextObject::Pair('(', ')'))),
+                _
+            )
+        ));
+        assert!(matches!(last("5G"), Parsed::Complete(Command::Motion(Motion::Line(5), 1), _)));
+        assert!(matches!(
+            last("gg"),
+            Parsed::Complete(Command::Motion(Motion::StartOfFile, 1), _)
+        ));
+    }
+
+    #[test]
+    fn syntax_objects_and_motions() {
+        assert!(matches!(
+            last("daf"),
+            Parsed::Complete(
+                Command::Operate(
+                    Operator::Delete,
+                    1,
+                    Target::Around(TextObject::Syntax(SyntaxObject::Function))
+                ),
+                _
+            )
+        ));
+        assert!(matches!(
+            last("2]f"),
+            Parsed::Complete(
+                Command::Motion(Motion::Syntax(SyntaxMotion::Next(SyntaxObject::Function)), 2),
+                _
+            )
+        ));
+        assert!(matches!(
+            last("[n"),
+            Parsed::Complete(Command::Motion(Motion::Syntax(SyntaxMotion::PreviousSibling), 1), _)
+        ));
+        assert!(matches!(last("]z"), Parsed::Cancelled));
+    }
+
+    #[test]
+    fn fold_commands() {
+        assert!(matches!(
+            last("zM"),
+            Parsed::Complete(Command::Fold(FoldCommand::CloseAll), None)
+        ));
+        assert!(matches!(last("za"), Parsed::Complete(Command::Fold(FoldCommand::Toggle), _)));
+        assert!(matches!(last("dz"), Parsed::Cancelled));
+    }
+
+    #[test]
+    fn registers() {
+        assert!(matches!(
+            last("\"a2yy"),
+            Parsed::Complete(Command::Operate(Operator::Yank, 2, Target::Lines), Some('a'))
+        ));
+        assert!(matches!(last("\"+3P"), Parsed::Put(Some('+'), Placement::Before, 3)));
+        assert!(matches!(last("p"), Parsed::Put(None, Placement::After, 1)));
+        assert!(matches!(last("\"!"), Parsed::Cancelled));
+    }
+
+    #[test]
+    fn visual_keys() {
+        let mut pending = Pending::default();
+        assert!(matches!(
+            pending.feed_visual('y'),
+            Parsed::Complete(Command::OperateSelection(Operator::Yank), None)
+        ));
+        assert!(matches!(pending.feed_visual('i'), Parsed::Pending));
+        assert!(matches!(
+            pending.feed_visual('w'),
+            Parsed::Complete(Command::SelectTextObject(Target::Inner(TextObject::Word)), None)
+        ));
+        assert!(matches!(pending.feed_visual('o'), Parsed::Unhandled));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let mut pending = Pending::default();
+        assert!(matches!(pending.feed('i'), Parsed::Unhandled));
+        assert!(matches!(pending.feed('d'), Parsed::Pending));
+        assert_eq!(pending.keys(), "d");
+        assert!(matches!(pending.feed('z'), Parsed::Cancelled));
+        assert_eq!(pending.keys(), "");
+        assert!(matches!(last("3i"), Parsed::Refused(keys) if keys == "3i"));
+        assert!(matches!(last("\"ao"), Parsed::Refused(keys) if keys == "\"ao"));
+    }
+}
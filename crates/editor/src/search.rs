@@ -0,0 +1,222 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind, Literal, Look};
+use regex_syntax::ParserBuilder;
+
+use crate::{Buffer, BufferContents, Editor, Point};
+
+/// A compiled search pattern and the direction it was searched in.
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub pattern: String,
+    pub backward: bool,
+    regex: Regex,
+    /// Whether a match may go past the end of a line, so that the whole buffer has to be searched
+    /// at once rather than a line at a time.
+    spans_lines: bool,
+}
+
+impl Search {
+    /// Compiles a pattern, ignoring case unless it has an uppercase letter.
+    pub fn new(pattern: &str, backward: bool) -> Result<Self, regex::Error> {
+        let case_insensitive = !pattern.chars().any(char::is_uppercase);
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .crlf(true)
+            .build()?;
+        let hir = ParserBuilder::new()
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .crlf(true)
+            .build()
+            .parse(pattern);
+        let spans_lines = hir.map_or(true, |hir| spans_lines(&hir));
+        Ok(Self { pattern: pattern.into(), backward, regex, spans_lines })
+    }
+
+    /// The matches that start on the given lines, as char ranges.
+    pub fn matches_in_lines(
+        &self,
+        contents: &BufferContents,
+        lines: Range<usize>,
+    ) -> Vec<Range<usize>> {
+        let (text, byte_start) = text_of_lines(contents, lines);
+        self.regex
+            .find_iter(&text)
+            .map(|m| to_chars(contents, byte_start, m))
+            .collect()
+    }
+
+    /// The nearest match starting after `offset` in the search's direction, or the opposite one if
+    /// `reverse`, wrapping around the ends of the buffer. Also tells whether it wrapped.
+    pub fn find(
+        &self,
+        contents: &BufferContents,
+        offset: usize,
+        reverse: bool,
+    ) -> Option<(Range<usize>, bool)> {
+        let offset = offset.min(contents.len_chars());
+        // the lines searched at once, from the one with the offset.
+        let (blocks, block) = match self.spans_lines {
+            true => (1, 0),
+            false => (contents.len_lines(), contents.char_to_line(offset)),
+        };
+        let lines = |block: usize| match self.spans_lines {
+            true => 0..contents.len_lines(),
+            false => block..block + 1,
+        };
+        let last = |block| self.matches_in_lines(contents, lines(block)).pop();
+
+        if self.backward != reverse {
+            let before = self
+                .matches_in_lines(contents, lines(block))
+                .into_iter()
+                .take_while(|m| m.start < offset)
+                .last();
+            let before = before.or_else(|| (0..block).rev().find_map(last));
+            match before {
+                Some(m) => Some((m, false)),
+                None => (block..blocks).rev().find_map(last).map(|m| (m, true)),
+            }
+        } else {
+            let first = |block| {
+                let (text, byte_start) = text_of_lines(contents, lines(block));
+                let m = self.regex.find(&text);
+                m.map(|m| to_chars(contents, byte_start, m))
+            };
+            let (text, byte_start) = text_of_lines(contents, lines(block));
+            let after = contents
+                .get_char(offset)
+                .map(|c| contents.char_to_byte(offset) + c.len_utf8() - byte_start)
+                .filter(|next| *next <= text.len())
+                .and_then(|next| self.regex.find_at(&text, next))
+                .map(|m| to_chars(contents, byte_start, m));
+            let after = after.or_else(|| (block + 1..blocks).find_map(first));
+            match after {
+                Some(m) => Some((m, false)),
+                None => (0..=block).find_map(first).map(|m| (m, true)),
+            }
+        }
+    }
+}
+
+/// The text of a range of lines, borrowed from the buffer where it can be, and the byte offset
+/// that it starts at.
+fn text_of_lines(contents: &BufferContents, lines: Range<usize>) -> (Cow<'_, str>, usize) {
+    let first = lines.start.min(contents.len_lines());
+    let last = lines.end.min(contents.len_lines());
+    let start = contents.line_to_char(first);
+    let text = contents.slice(start..contents.line_to_char(last));
+    (text.into(), contents.char_to_byte(start))
+}
+
+fn to_chars(contents: &BufferContents, byte_start: usize, m: regex::Match) -> Range<usize> {
+    contents.byte_to_char(byte_start + m.start())..contents.byte_to_char(byte_start + m.end())
+}
+
+/// The chars that the buffer breaks lines at.
+const LINE_BREAKS: [char; 7] = [
+    '\n', '\u{0B}', '\u{0C}', '\r', '\u{85}', '\u{2028}', '\u{2029}',
+];
+
+/// Whether a pattern can match a line break, or looks at the ends of the whole text, so that
+/// searching one line at a time could give other matches than searching the buffer.
+fn spans_lines(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty => false,
+        HirKind::Literal(Literal(bytes)) => String::from_utf8_lossy(bytes).contains(LINE_BREAKS),
+        HirKind::Class(Class::Unicode(class)) => class.ranges().iter().any(|range| {
+            LINE_BREAKS
+                .iter()
+                .any(|c| (range.start()..=range.end()).contains(c))
+        }),
+        HirKind::Class(Class::Bytes(class)) => class.ranges().iter().any(|range| {
+            LINE_BREAKS
+                .iter()
+                .any(|c| c.is_ascii() && (range.start()..=range.end()).contains(&(*c as u8)))
+        }),
+        HirKind::Look(look) => matches!(look, Look::Start | Look::End),
+        HirKind::Repetition(repetition) => spans_lines(&repetition.sub),
+        HirKind::Capture(capture) => spans_lines(&capture.sub),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(spans_lines),
+    }
+}
+
+impl Editor {
+    /// Moves the cursor to the nearest match from `from`, opening the folds it is in. Returns
+    /// whether the search wrapped around the end of the buffer, or `None` if nothing matches.
+    pub fn search(
+        &mut self,
+        buffer: &Buffer,
+        search: &Search,
+        from: Point,
+        reverse: bool,
+    ) -> Option<bool> {
+        let contents = &buffer.contents;
+        let offset = contents.point_to_char_offset(from);
+        let (range, wrapped) = search.find(contents, offset, reverse)?;
+        self.cursor = contents.char_offset_to_point(range.start);
+        self.cursor_clamp(buffer);
+        self.open_folds_at_cursor();
+        Some(wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BufferId;
+
+    fn contents(text: &str) -> Buffer {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        buffer
+    }
+
+    #[test]
+    fn finds_and_wraps() {
+        let buffer = contents("foo bar\nbaz Foo\nfoo");
+        let contents = &buffer.contents;
+        let search = Search::new("foo", false).unwrap();
+        assert_eq!(search.find(contents, 0, false), Some((12..15, false)));
+        assert_eq!(search.find(contents, 12, false), Some((16..19, false)));
+        assert_eq!(search.find(contents, 16, false), Some((0..3, true)));
+        assert_eq!(search.find(contents, 12, true), Some((0..3, false)));
+        assert_eq!(search.find(contents, 0, true), Some((16..19, true)));
+
+        // an uppercase letter makes the search case sensitive.
+        let search = Search::new("Foo", true).unwrap();
+        assert_eq!(search.find(contents, 0, false), Some((12..15, true)));
+        assert_eq!(search.matches_in_lines(contents, 0..2), vec![12..15]);
+    }
+
+    #[test]
+    fn finds_matches_across_lines() {
+        let buffer = contents("foo\nbar\nfoo\nbaz\n");
+        let contents = &buffer.contents;
+        let search = Search::new("o\\nb", false).unwrap();
+        assert!(search.spans_lines);
+        assert_eq!(search.find(contents, 0, false), Some((2..5, false)));
+        assert_eq!(search.find(contents, 2, false), Some((10..13, false)));
+        assert_eq!(search.find(contents, 2, true), Some((10..13, true)));
+
+        let search = Search::new(r"\s\w", true).unwrap();
+        assert!(search.spans_lines);
+        assert_eq!(search.find(contents, 5, false), Some((3..5, false)));
+        let search = Search::new(r"\Abar", false).unwrap();
+        assert!(search.spans_lines);
+        assert_eq!(search.find(contents, 0, false), None);
+        assert!(Search::new("[^a]", false).unwrap().spans_lines);
+        assert!(!Search::new("^o+$|[ab]", false).unwrap().spans_lines);
+    }
+
+    #[test]
+    fn anchors_match_lines() {
+        let buffer = contents("ab\r\ncd\r\n");
+        let search = Search::new("^c|b$", false).unwrap();
+        assert_eq!(search.matches_in_lines(&buffer.contents, 0..3), vec![1..2, 4..5]);
+    }
+}
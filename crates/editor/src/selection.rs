@@ -0,0 +1,233 @@
+use std::cmp::{max, min};
+
+use crate::operator::{convert_case, Span};
+use crate::{Buffer, Editor, Mode, Operator, Point, Target, Yank};
+
+/// A visual selection: the anchor stays where it was started while the head follows the cursor.
+/// Both ends are included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Point,
+    pub head: Point,
+}
+
+impl Selection {
+    pub fn start(&self) -> Point {
+        min(self.anchor, self.head)
+    }
+
+    pub fn end(&self) -> Point {
+        max(self.anchor, self.head)
+    }
+
+    /// The columns a block selection spans, end exclusive.
+    pub fn columns(&self) -> std::ops::Range<usize> {
+        let left = min(self.anchor.column, self.head.column);
+        let right = max(self.anchor.column, self.head.column);
+        left..right + 1
+    }
+
+    /// Whether the char at `point` is selected in the given visual mode.
+    pub fn contains(&self, mode: &Mode, point: Point) -> bool {
+        let (start, end) = (self.start(), self.end());
+        match mode {
+            Mode::Visual => start <= point && point <= end,
+            Mode::VisualLine => (start.line..=end.line).contains(&point.line),
+            Mode::VisualBlock => {
+                (start.line..=end.line).contains(&point.line)
+                    && self.columns().contains(&point.column)
+            }
+            Mode::Normal | Mode::Insert => false,
+        }
+    }
+}
+
+impl Editor {
+    /// The selection, while in one of the visual modes.
+    pub fn selection(&self) -> Option<Selection> {
+        self.mode
+            .is_visual()
+            .then_some(Selection { anchor: self.anchor, head: self.cursor })
+    }
+
+    /// Applies an operator to the selection, leaving visual mode.
+    pub fn operate_selection(&mut self, buffer: &mut Buffer, operator: Operator) -> Option<Yank> {
+        let selection = self.selection()?;
+        let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+        let (start, end) = (selection.start(), selection.end());
+        let contents = &buffer.contents;
+        let span = match mode {
+            Mode::VisualLine => Span::lines(contents, start.line, end.line),
+            Mode::VisualBlock if !matches!(operator, Operator::Indent | Operator::Outdent) => {
+                return self.operate_block(buffer, operator, selection);
+            }
+            Mode::VisualBlock => Span::lines(contents, start.line, end.line),
+            _ => {
+                let end = contents.point_to_char_offset(end) + 1;
+                let start = contents.point_to_char_offset(start);
+                Span::chars(start, min(end, contents.len_chars()))
+            }
+        };
+        self.apply_operator(buffer, operator, span)
+    }
+
+    /// Applies an operator to the columns of a block selection on each of its lines. The text
+    /// taken is the block's rows, one per line.
+    fn operate_block(
+        &mut self,
+        buffer: &mut Buffer,
+        operator: Operator,
+        selection: Selection,
+    ) -> Option<Yank> {
+        let (start, end) = (selection.start(), selection.end());
+        let columns = selection.columns();
+        let contents = &buffer.contents;
+        let rows: Vec<_> = (start.line..=end.line)
+            .map(|line| {
+                let line_start = contents.line_to_char(line);
+                let len = contents.line_len(line);
+                line_start + min(columns.start, len)..line_start + min(columns.end, len)
+            })
+            .collect();
+        let text = rows
+            .iter()
+            .map(|row| contents.slice(row.clone()).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let yank = Yank { text, linewise: false };
+
+        self.cursor = Point { line: start.line, column: columns.start };
+        match operator {
+            Operator::Yank => (),
+            Operator::Delete | Operator::Change => {
+                buffer.begin_transaction();
+                for row in rows.into_iter().rev() {
+                    buffer.remove(row);
+                }
+                if operator == Operator::Change {
+                    // type into every row of the block at once.
+                    self.secondaries = (start.line + 1..=end.line)
+                        .filter(|line| buffer.contents.line_len(*line) >= columns.start)
+                        .map(|line| {
+                            let point = Point { line, column: columns.start };
+                            Selection { anchor: point, head: point }
+                        })
+                        .collect();
+                    self.set_mode(buffer, Mode::Insert);
+                } else {
+                    buffer.commit_transaction();
+                }
+            }
+            _ => {
+                buffer.begin_transaction();
+                for row in rows.into_iter().rev() {
+                    let text = buffer.contents.slice(row.clone()).to_string();
+                    let converted: String = text
+                        .chars()
+                        .flat_map(|c| convert_case(operator, c))
+                        .collect();
+                    if converted != text {
+                        buffer.remove(row.clone());
+                        buffer.insert(row.start, &converted);
+                    }
+                }
+                buffer.commit_transaction();
+            }
+        }
+        self.cursor_clamp(buffer);
+        matches!(operator, Operator::Yank | Operator::Delete | Operator::Change).then_some(yank)
+    }
+
+    /// Selects the text object around the cursor, switching to linewise selection for a block
+    /// whose brackets sit on lines of their own.
+    pub fn select_text_object(&mut self, buffer: &mut Buffer, target: &Target) {
+        let span = match target {
+            Target::Inner(object) => self.text_object_span(buffer, object, true, 1),
+            Target::Around(object) => self.text_object_span(buffer, object, false, 1),
+            Target::Motion(_) | Target::Lines => None,
+        };
+        let Some(span) = span.filter(|span| span.start < span.end) else {
+            return;
+        };
+
+        let contents = &buffer.contents;
+        self.anchor = contents.char_offset_to_point(span.start);
+        self.cursor = contents.char_offset_to_point(span.end - 1);
+        if span.linewise {
+            self.mode = Mode::VisualLine;
+        } else if self.mode == Mode::VisualLine {
+            self.mode = Mode::Visual;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Buffer, BufferId, Editor, EditorCommand, EditorId, Mode, Operator, Point, TextObject, Yank,
+    };
+
+    fn setup(text: &str, anchor: Point, head: Point, mode: Mode) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.cursor = anchor;
+        editor.set_mode(&mut buffer, mode);
+        editor.cursor = head;
+        (editor, buffer)
+    }
+
+    fn point(line: usize, column: usize) -> Point {
+        Point { line, column }
+    }
+
+    #[test]
+    fn charwise_and_linewise() {
+        let (mut editor, mut buffer) = setup("abc\ndef", point(1, 1), point(0, 1), Mode::Visual);
+        let yank = editor.operate_selection(&mut buffer, Operator::Delete);
+        assert_eq!(buffer.contents.to_string(), "af");
+        assert_eq!(yank, Some(Yank { text: "bc\nde".into(), linewise: false }));
+        assert_eq!(editor.mode, Mode::Normal);
+
+        let (mut editor, mut buffer) =
+            setup("abc\ndef\nghi", point(0, 2), point(1, 0), Mode::VisualLine);
+        let yank = editor.operate_selection(&mut buffer, Operator::Yank);
+        assert_eq!(yank, Some(Yank { text: "abc\ndef\n".into(), linewise: true }));
+        assert_eq!(editor.cursor, point(0, 0));
+    }
+
+    #[test]
+    fn blockwise() {
+        let (mut editor, mut buffer) =
+            setup("abcd\nef\nghij", point(0, 1), point(2, 2), Mode::VisualBlock);
+        let yank = editor.operate_selection(&mut buffer, Operator::Delete);
+        assert_eq!(buffer.contents.to_string(), "ad\ne\ngj");
+        assert_eq!(yank, Some(Yank { text: "bc\nf\nhi".into(), linewise: false }));
+        assert_eq!(editor.cursor, point(0, 1));
+
+        let (mut editor, mut buffer) = setup(
+            "abcd
+ef
+ghij",
+            point(0, 1),
+            point(2, 2),
+            Mode::VisualBlock,
+        );
+        editor.command(&mut buffer, EditorCommand::OperateSelection(Operator::Change));
+        editor.command(&mut buffer, EditorCommand::InsertChar('x'));
+        assert_eq!(
+            buffer.contents.to_string(),
+            "axd
+ex
+gxj"
+        );
+    }
+
+    #[test]
+    fn select_text_objects() {
+        let (mut editor, mut buffer) = setup("f(ab, c)", point(0, 3), point(0, 3), Mode::Visual);
+        let target = crate::Target::Inner(TextObject::Pair('(', ')'));
+        editor.select_text_object(&mut buffer, &target);
+        assert_eq!((editor.anchor, editor.cursor), (point(0, 2), point(0, 6)));
+    }
+}
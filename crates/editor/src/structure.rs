@@ -0,0 +1,303 @@
+use std::cmp::max;
+use std::ops::Range;
+
+use crate::operator::Span;
+use crate::{Buffer, BufferContents, Edit, Editor};
+
+/// The kinds of syntax nodes that text objects and motions act on, as `textobjects.scm` queries
+/// capture them: `@function.around` for a whole function, `@function.inside` for its body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxObject {
+    Function,
+    Class,
+    Parameter,
+    Comment,
+    Block,
+}
+
+impl SyntaxObject {
+    /// The name of the kind in captures.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyntaxObject::Function => "function",
+            SyntaxObject::Class => "class",
+            SyntaxObject::Parameter => "parameter",
+            SyntaxObject::Comment => "comment",
+            SyntaxObject::Block => "block",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxMotion {
+    /// To the start of the next object of a kind.
+    Next(SyntaxObject),
+    Previous(SyntaxObject),
+    /// To the start of the next syntax node at the level of the one at the cursor.
+    NextSibling,
+    PreviousSibling,
+}
+
+/// Where an object was found in the syntax tree, in byte offsets. A query may capture either part
+/// or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectRange {
+    pub around: Option<Range<usize>>,
+    /// The inside of the object, without the brackets around it, if any.
+    pub inside: Option<Range<usize>>,
+}
+
+impl ObjectRange {
+    /// The whole of the object.
+    fn hull(&self) -> Range<usize> {
+        let (around, inside) = (self.around.clone(), self.inside.clone());
+        around.or(inside).unwrap_or_default()
+    }
+}
+
+/// A buffer's syntax tree, as the editor consults it. It is implemented outside of this crate so
+/// that the editor does not depend on a parser.
+pub trait SyntaxTree: std::fmt::Debug + Send + Sync {
+    /// Moves the nodes after an edit to the contents along with the text, and stretches those
+    /// around it over it, so that the tree stays close to the contents until they are parsed
+    /// again.
+    fn edit(&mut self, edit: &Edit);
+
+    /// The objects of a kind overlapping a byte range.
+    fn objects(
+        &self,
+        contents: &BufferContents,
+        object: SyntaxObject,
+        range: Range<usize>,
+    ) -> Vec<ObjectRange>;
+
+    /// The start of the named node after or before the one at a byte offset, at the same level
+    /// of the tree. The node at an offset is the outermost one starting there, or else the
+    /// innermost one around it.
+    fn sibling(&self, offset: usize, next: bool) -> Option<usize>;
+
+    /// The ranges of lines that fold, end exclusive, or `None` if the language does not say.
+    fn folds(&self, contents: &BufferContents) -> Option<Vec<Range<usize>>>;
+
+    /// How many levels deeper than the start of the buffer a line starting at byte `split`, with
+    /// its text from byte `content`, is indented, or `None` if the language does not say. Only
+    /// the difference between the levels of two lines is meaningful.
+    fn indent_level(
+        &self,
+        contents: &BufferContents,
+        split: usize,
+        content: usize,
+    ) -> Option<isize>;
+
+    /// The ranges of lines of the definitions around a line, such as the functions and modules
+    /// it is in, outermost first and end exclusive.
+    fn scopes(&self, contents: &BufferContents, line: usize) -> Vec<Range<usize>>;
+
+    /// The start of the bracket that the grammar pairs with the bracket starting at a byte
+    /// offset, or `None` if the tree has no paired bracket there.
+    fn matching_bracket(&self, offset: usize) -> Option<usize>;
+
+    /// Whether a byte offset is inside a string or a comment.
+    fn is_literal(&self, offset: usize) -> bool;
+
+    /// The starts of the brackets that the grammar has in a byte range, leaving out those in
+    /// strings and comments, each with how many pairs of brackets are around it.
+    fn brackets(&self, range: Range<usize>) -> Vec<(usize, usize)>;
+}
+
+impl Editor {
+    /// The `count`th innermost object of a kind around the cursor, or the next one after it.
+    pub(crate) fn syntax_object_span(
+        &self,
+        buffer: &Buffer,
+        object: SyntaxObject,
+        inner: bool,
+        count: usize,
+    ) -> Option<Span> {
+        let syntax = buffer.syntax.as_deref()?;
+        let contents = &buffer.contents;
+        let offset = contents.char_to_byte(contents.point_to_char_offset(self.cursor));
+        let part = |object: &ObjectRange| match inner {
+            true => object.inside.clone(),
+            false => object.around.clone(),
+        };
+
+        let objects = syntax.objects(contents, object, offset..contents.len_bytes());
+        let mut around_cursor: Vec<_> = objects
+            .iter()
+            .filter(|object| part(object).is_some() && object.hull().contains(&offset))
+            .collect();
+        around_cursor.sort_by_key(|object| object.hull().len());
+        around_cursor.dedup_by_key(|object| object.hull());
+        let found = match around_cursor.get(count - 1) {
+            Some(object) => part(object)?,
+            None if around_cursor.is_empty() => objects
+                .iter()
+                .filter(|object| object.hull().start > offset)
+                .min_by_key(|object| object.hull().start)
+                .and_then(part)?,
+            None => return None,
+        };
+        Some(object_span(contents, found))
+    }
+
+    pub(crate) fn cursor_syntax_motion(&mut self, buffer: &Buffer, motion: SyntaxMotion) {
+        let Some(syntax) = buffer.syntax.as_deref() else {
+            return;
+        };
+        let contents = &buffer.contents;
+        let offset = contents.char_to_byte(contents.point_to_char_offset(self.cursor));
+        let starts = |range: Range<usize>, object| {
+            let objects = syntax.objects(contents, object, range);
+            objects.into_iter().map(|object| object.hull().start)
+        };
+        let target = match motion {
+            SyntaxMotion::Next(object) => starts(offset..contents.len_bytes(), object)
+                .filter(|start| *start > offset)
+                .min(),
+            SyntaxMotion::Previous(object) => starts(0..offset, object)
+                .filter(|start| *start < offset)
+                .max(),
+            SyntaxMotion::NextSibling => syntax.sibling(offset, true),
+            SyntaxMotion::PreviousSibling => syntax.sibling(offset, false),
+        };
+        if let Some(target) = target {
+            self.cursor = contents.char_offset_to_point(contents.byte_to_char(target));
+            self.cursor_clamp(buffer);
+        }
+    }
+}
+
+/// The chars of an object found in the syntax tree. An object that takes up whole lines takes
+/// them with their line endings, as does the inside of brackets that sit on lines of their own.
+fn object_span(contents: &BufferContents, bytes: Range<usize>) -> Span {
+    let start = contents.byte_to_char(bytes.start);
+    let end = contents.byte_to_char(bytes.end);
+    let first = contents.char_to_line(start);
+    let last = contents.char_to_line(max(start, end.saturating_sub(1)));
+    let is_blank = |range: Range<usize>| {
+        let text = contents.slice(range);
+        text.chars().all(char::is_whitespace)
+    };
+    let line_end = |line: usize| contents.line_to_char(line) + contents.line_len(line);
+
+    let opens_line = start - contents.line_to_char(first) == contents.first_non_blank(first);
+    if opens_line && is_blank(end..max(end, line_end(last))) {
+        return Span::lines(contents, first, last);
+    }
+    // the inside of `{` and `}` on lines of their own.
+    let closing_line = contents.char_to_line(end);
+    let after_open = is_blank(start..line_end(first));
+    let before_close = is_blank(contents.line_to_char(closing_line)..end);
+    if after_open && before_close && closing_line > first + 1 {
+        return Span::lines(contents, first + 1, closing_line - 1);
+    }
+    Span::chars(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferId, EditorId, Operator, Point, Target, TextObject};
+
+    /// Functions as `fn` up to the matching `}`, with their bodies as the inside.
+    #[derive(Debug)]
+    struct Functions(String);
+
+    impl SyntaxTree for Functions {
+        fn edit(&mut self, _edit: &Edit) {}
+
+        fn objects(
+            &self,
+            _contents: &BufferContents,
+            _object: SyntaxObject,
+            _range: Range<usize>,
+        ) -> Vec<ObjectRange> {
+            self.0
+                .match_indices("fn")
+                .map(|(start, _)| {
+                    let open = start + self.0[start..].find('{').unwrap();
+                    let close = open + self.0[open..].find('}').unwrap();
+                    let inside = Some(open + 1..close);
+                    ObjectRange { around: Some(start..close + 1), inside }
+                })
+                .collect()
+        }
+
+        fn sibling(&self, _offset: usize, _next: bool) -> Option<usize> {
+            None
+        }
+
+        fn folds(&self, _contents: &BufferContents) -> Option<Vec<Range<usize>>> {
+            None
+        }
+
+        fn indent_level(
+            &self,
+            _contents: &BufferContents,
+            _split: usize,
+            _content: usize,
+        ) -> Option<isize> {
+            None
+        }
+
+        fn scopes(&self, _contents: &BufferContents, _line: usize) -> Vec<Range<usize>> {
+            vec![]
+        }
+
+        fn matching_bracket(&self, _offset: usize) -> Option<usize> {
+            None
+        }
+
+        fn is_literal(&self, _offset: usize) -> bool {
+            false
+        }
+
+        fn brackets(&self, _range: Range<usize>) -> Vec<(usize, usize)> {
+            vec![]
+        }
+    }
+
+    fn setup(text: &str, line: usize, column: usize) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        buffer.syntax = Some(Box::new(Functions(text.into())));
+        let mut editor = Editor::new(EditorId::default(), buffer.id);
+        editor.cursor = Point { line, column };
+        (editor, buffer)
+    }
+
+    #[test]
+    fn function_objects() {
+        let text = "fn a() {\n    b;\n}\nfn c() { d }\n";
+        let function = TextObject::Syntax(SyntaxObject::Function);
+
+        let (mut editor, mut buffer) = setup(text, 1, 4);
+        let target = Target::Around(function.clone());
+        let yank = editor.operate(&mut buffer, Operator::Delete, 1, &target);
+        assert_eq!(buffer.contents.to_string(), "fn c() { d }\n");
+        assert!(yank.is_some_and(|yank| yank.linewise));
+
+        let (mut editor, mut buffer) = setup(text, 0, 0);
+        let target = Target::Inner(function.clone());
+        editor.operate(&mut buffer, Operator::Delete, 1, &target);
+        assert_eq!(buffer.contents.to_string(), "fn a() {\n}\nfn c() { d }\n");
+
+        let (mut editor, mut buffer) = setup(text, 3, 0);
+        let target = Target::Inner(function);
+        editor.operate(&mut buffer, Operator::Delete, 1, &target);
+        assert_eq!(buffer.contents.to_string(), "fn a() {\n    b;\n}\nfn c() {}\n");
+    }
+
+    #[test]
+    fn function_motions() {
+        let text = "fn a() {\n    b;\n}\nfn c() { d }\n";
+        let (mut editor, buffer) = setup(text, 1, 4);
+        let next = crate::Motion::Syntax(SyntaxMotion::Next(SyntaxObject::Function));
+        editor.cursor_motion(&buffer, &next, 1);
+        assert_eq!(editor.cursor, Point { line: 3, column: 0 });
+        let previous = crate::Motion::Syntax(SyntaxMotion::Previous(SyntaxObject::Function));
+        editor.cursor_motion(&buffer, &previous, 1);
+        assert_eq!(editor.cursor, Point { line: 0, column: 0 });
+    }
+}
@@ -0,0 +1,359 @@
+use std::borrow::Cow;
+use std::ops::{Range, RangeInclusive};
+
+use anyhow::{anyhow, bail, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::{Buffer, BufferContents, Editor};
+
+/// An ex-style `:[range]s/pattern/replacement/[flags]` command.
+///
+/// The replacement may refer to the whole match with `&` and to capture groups with `\1` to
+/// `\9`. The flags are `g` to replace every match on a line rather than the first, `c` to confirm
+/// each replacement, and `i` or `I` to ignore case or not, which otherwise is ignored unless the
+/// pattern has an uppercase letter.
+#[derive(Debug, Clone)]
+pub struct Substitute {
+    pub lines: RangeInclusive<usize>,
+    pub pattern: String,
+    pub global: bool,
+    pub confirm: bool,
+    regex: Regex,
+    /// The replacement, in the syntax of [`regex::Captures::expand`].
+    replacement: String,
+}
+
+impl Substitute {
+    /// Parses a substitute command. The range defaults to the cursor's line, and an empty pattern
+    /// reuses the last search's.
+    pub fn parse(
+        command: &str,
+        contents: &BufferContents,
+        cursor_line: usize,
+        last_pattern: Option<&str>,
+    ) -> Result<Self> {
+        let (lines, rest) = parse_range(command.trim_start(), contents, cursor_line)?;
+        let rest = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))
+            .ok_or_else(|| anyhow!("not a substitute command: {}", command))?;
+        let mut chars = rest.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+            _ => bail!("substitute: expected a delimiter after s"),
+        };
+        let mut parts = split_unescaped(chars.as_str(), delimiter);
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+
+        let pattern = match (pattern.as_str(), last_pattern) {
+            ("", Some(last)) => last.to_string(),
+            ("", None) => bail!("substitute: no previous search pattern"),
+            _ => pattern,
+        };
+        let mut case_insensitive = !pattern.chars().any(char::is_uppercase);
+        let (mut global, mut confirm) = (false, false);
+        for flag in flags.trim().chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                'i' => case_insensitive = true,
+                'I' => case_insensitive = false,
+                flag => bail!("substitute: unknown flag {}", flag),
+            }
+        }
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        let replacement = expand_syntax(&replacement);
+        Ok(Self { lines, pattern, global, confirm, regex, replacement })
+    }
+
+    /// The matches in the command's lines with their replacements, in document order. Each line
+    /// is searched on its own, without its line ending, so that the whole buffer never has to be
+    /// copied out of the rope.
+    pub fn matches(&self, contents: &BufferContents) -> Vec<Replacement> {
+        let mut replacements = vec![];
+        let last_line = (*self.lines.end()).min(contents.len_lines().saturating_sub(1));
+        for line_idx in *self.lines.start()..=last_line {
+            let line_start = contents.line_to_char(line_idx);
+            let line = contents.line(line_idx);
+            let line = line.slice(..contents.line_len(line_idx));
+            let text: Cow<str> = line.into();
+            let limit = if self.global { usize::MAX } else { 1 };
+            for caps in self.regex.captures_iter(&text).take(limit) {
+                let m = caps.get(0).expect("capture 0 is the whole match");
+                let start = line_start + line.byte_to_char(m.start());
+                let end = line_start + line.byte_to_char(m.end());
+                let mut text = String::new();
+                caps.expand(&self.replacement, &mut text);
+                replacements.push(Replacement { range: start..end, text });
+            }
+        }
+        replacements
+    }
+}
+
+/// A match to replace, as a char range, and what it is replaced with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// A substitution in progress, stepping through its matches so each can be confirmed. All the
+/// replacements it makes undo as one step.
+#[derive(Debug)]
+pub struct Substitution {
+    replacements: Vec<Replacement>,
+    next: usize,
+    /// How many chars the replacements made so far have added, to shift the later matches by.
+    delta: isize,
+    pub replaced: usize,
+}
+
+impl Substitution {
+    pub fn new(buffer: &mut Buffer, substitute: &Substitute) -> Self {
+        let replacements = substitute.matches(&buffer.contents);
+        buffer.begin_transaction();
+        Self { replacements, next: 0, delta: 0, replaced: 0 }
+    }
+
+    /// The match to confirm next, where it is now in the buffer.
+    pub fn current(&self) -> Option<Range<usize>> {
+        let replacement = self.replacements.get(self.next)?;
+        let shift = |offset: usize| offset.saturating_add_signed(self.delta);
+        Some(shift(replacement.range.start)..shift(replacement.range.end))
+    }
+
+    /// Replaces the current match and moves on to the next.
+    pub fn replace(&mut self, buffer: &mut Buffer) {
+        let Some(range) = self.current() else {
+            return;
+        };
+        let text = &self.replacements[self.next].text;
+        buffer.remove(range.clone());
+        buffer.insert(range.start, text);
+        self.delta += text.chars().count() as isize - range.len() as isize;
+        self.replaced += 1;
+        self.next += 1;
+    }
+
+    pub fn skip(&mut self) {
+        self.next += 1;
+    }
+
+    pub fn replace_all(&mut self, buffer: &mut Buffer) {
+        while self.current().is_some() {
+            self.replace(buffer);
+        }
+    }
+
+    /// Commits the replacements, returning how many were made.
+    pub fn finish(self, buffer: &mut Buffer) -> usize {
+        buffer.commit_transaction();
+        self.replaced
+    }
+}
+
+impl Editor {
+    /// Runs a substitute command without confirmation, leaving the cursor at the start of the
+    /// last line changed. Returns how many replacements were made.
+    pub fn substitute(&mut self, buffer: &mut Buffer, substitute: &Substitute) -> usize {
+        let mut substitution = Substitution::new(buffer, substitute);
+        let mut last = None;
+        while let Some(range) = substitution.current() {
+            last = Some(range.start);
+            substitution.replace(buffer);
+        }
+        if let Some(offset) = last {
+            let line = buffer.contents.char_to_line(offset);
+            self.cursor.line = line;
+            self.cursor.column = buffer.contents.first_non_blank(line);
+        }
+        substitution.finish(buffer)
+    }
+}
+
+/// Parses a line range: `%`, or one or two addresses separated by a comma, where an address is a
+/// line number, `.` for the cursor's line or `$` for the last, with an optional `+n` or `-n`.
+/// Returns the zero-based lines and the rest of the command.
+fn parse_range<'a>(
+    command: &'a str,
+    contents: &BufferContents,
+    cursor_line: usize,
+) -> Result<(RangeInclusive<usize>, &'a str)> {
+    let last = contents.last_line();
+    if let Some(rest) = command.strip_prefix('%') {
+        return Ok((0..=last, rest));
+    }
+    let (start, rest) = parse_address(command, cursor_line, last)?;
+    let (end, rest) = match rest.strip_prefix(',') {
+        Some(rest) => {
+            let (end, rest) = parse_address(rest, cursor_line, last)?;
+            (end.ok_or_else(|| anyhow!("substitute: missing address after ,"))?, rest)
+        }
+        None => (start.unwrap_or(cursor_line), rest),
+    };
+    let start = start.unwrap_or(cursor_line);
+    if start > end {
+        bail!("substitute: backwards range");
+    }
+    if start > last {
+        bail!("substitute: range past the end of the buffer");
+    }
+    Ok((start..=end.min(last), rest))
+}
+
+fn parse_address(command: &str, cursor_line: usize, last: usize) -> Result<(Option<usize>, &str)> {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (line, rest) = if let Some(rest) = command.strip_prefix('.') {
+        (Some(cursor_line), rest)
+    } else if let Some(rest) = command.strip_prefix('$') {
+        (Some(last), rest)
+    } else {
+        match digits(command) {
+            0 => (None, command),
+            len => {
+                let line: usize = command[..len].parse()?;
+                (Some(line.saturating_sub(1)), &command[len..])
+            }
+        }
+    };
+    let sign = rest.chars().next().filter(|c| *c == '+' || *c == '-');
+    let Some(sign) = sign else {
+        return Ok((line, rest));
+    };
+    let rest = &rest[1..];
+    let len = digits(rest);
+    let offset: usize = if len == 0 { 1 } else { rest[..len].parse()? };
+    let line = line.unwrap_or(cursor_line);
+    let line = match sign {
+        '+' => line.saturating_add(offset),
+        _ => line.saturating_sub(offset),
+    };
+    Ok((Some(line), &rest[len..]))
+}
+
+/// Splits the pattern, replacement and flags on the delimiter, unescaping a delimiter escaped
+/// with a backslash. Other escapes are kept for the regex or the replacement to interpret.
+fn split_unescaped(text: &str, delimiter: char) -> impl Iterator<Item = String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == delimiter && parts.len() < 3 {
+            parts.push(String::new());
+            continue;
+        }
+        let part = parts.last_mut().expect("there is always a part");
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            c => part.push(c),
+        }
+    }
+    parts.into_iter()
+}
+
+/// Translates a vim-style replacement into the syntax of [`regex::Captures::expand`]. As in vim,
+/// `\r` breaks the line and `\n` puts a NUL.
+fn expand_syntax(replacement: &str) -> String {
+    let mut expanded = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    expanded.push_str("${");
+                    expanded.push(digit);
+                    expanded.push('}');
+                }
+                Some('r') => expanded.push('\n'),
+                Some('n') => expanded.push('\0'),
+                Some('t') => expanded.push('\t'),
+                Some(c) => expanded.push(c),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferId, EditorCommand, EditorId};
+
+    fn setup(text: &str) -> (Editor, Buffer) {
+        let mut buffer = Buffer::empty(BufferId::default());
+        buffer.insert(0, text);
+        let editor = Editor::new(EditorId::default(), buffer.id);
+        (editor, buffer)
+    }
+
+    #[test]
+    fn parses_ranges_and_flags() {
+        let (_, buffer) = setup("a\nb\nc\nd");
+        let contents = &buffer.contents;
+        let sub = Substitute::parse("%s/a/b/gc", contents, 1, None).unwrap();
+        assert_eq!((sub.lines, sub.global, sub.confirm), (0..=3, true, true));
+        let sub = Substitute::parse("2,$s#a\\#b#c#", contents, 0, None).unwrap();
+        assert_eq!((sub.lines, sub.pattern.as_str()), (1..=3, "a#b"));
+        let sub = Substitute::parse(".,.+1s//x/", contents, 1, Some("b")).unwrap();
+        assert_eq!((sub.lines, sub.pattern.as_str()), (1..=2, "b"));
+        assert!(Substitute::parse("3,1s/a/b/", contents, 0, None).is_err());
+        assert!(Substitute::parse("s/a/b/z", contents, 0, None).is_err());
+        // an offset past the end does not overflow.
+        assert!(Substitute::parse(".+18446744073709551615s/a/b/", contents, 1, None).is_err());
+    }
+
+    #[test]
+    fn replaces_with_captures_as_one_undo() {
+        let (mut editor, mut buffer) = setup("foo = 1;\nbar = 22;\nfoo = 3;");
+        let sub = Substitute::parse(r"%s/(\w+) = (\d+)/\2 -> \1 [&]/", &buffer.contents, 0, None);
+        let replaced = editor.substitute(&mut buffer, &sub.unwrap());
+        assert_eq!(replaced, 3);
+        assert_eq!(
+            buffer.contents.to_string(),
+            "1 -> foo [foo = 1];\n22 -> bar [bar = 22];\n3 -> foo [foo = 3];"
+        );
+
+        editor.command(&mut buffer, EditorCommand::Undo);
+        assert_eq!(buffer.contents.to_string(), "foo = 1;\nbar = 22;\nfoo = 3;");
+    }
+
+    #[test]
+    fn replaces_escapes_as_vim_does() {
+        let (mut editor, mut buffer) = setup("a b\tc");
+        let sub = Substitute::parse(r"s/ /\r/", &buffer.contents, 0, None).unwrap();
+        editor.substitute(&mut buffer, &sub);
+        let sub = Substitute::parse(r"s/\t/\n/", &buffer.contents, 1, None).unwrap();
+        editor.substitute(&mut buffer, &sub);
+        assert_eq!(buffer.contents.to_string(), "a\nb\0c");
+    }
+
+    #[test]
+    fn confirms_each_match() {
+        let (_, mut buffer) = setup("aa a\na");
+        let sub = Substitute::parse("%s/a/xyz/g", &buffer.contents, 0, None).unwrap();
+        let mut substitution = Substitution::new(&mut buffer, &sub);
+        assert_eq!(substitution.current(), Some(0..1));
+        substitution.replace(&mut buffer);
+        assert_eq!(substitution.current(), Some(3..4));
+        substitution.skip();
+        substitution.replace(&mut buffer);
+        assert_eq!(substitution.current(), Some(9..10));
+        assert_eq!(substitution.finish(&mut buffer), 2);
+        assert_eq!(buffer.contents.to_string(), "xyza xyz\na");
+    }
+}
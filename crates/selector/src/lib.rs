@@ -47,21 +47,37 @@ impl<Id: Eq + Copy> Selector<Id> {
     }
 
     pub fn insert(&mut self, c: char) {
-        if self.cursor.column == self.query.len() {
-            self.query.push(c);
-        } else {
-            self.query.insert(self.cursor.column, c);
-        }
+        let idx = self.byte_index(self.cursor.column);
+        self.query.insert(idx, c);
         self.cursor.move_next_column();
     }
 
+    pub fn reset(&mut self) {
+        self.query.clear();
+        self.cursor = Point::default();
+        self.focused = None;
+    }
+
     fn delete(&mut self, dir: Direction) {
-        let range = match dir {
-            Direction::Next => self.cursor.column..self.cursor.column + 1,
-            Direction::Prev => self.cursor.column - 1..self.cursor.column,
+        let column = match dir {
+            Direction::Next if self.cursor.column < self.query.chars().count() => {
+                self.cursor.column
+            }
+            Direction::Prev if self.cursor.column > 0 => {
+                self.cursor.move_prev_column();
+                self.cursor.column
+            }
+            _ => return,
         };
-        self.query.drain(range);
-        self.cursor.move_prev_column();
+        self.query.remove(self.byte_index(column));
+    }
+
+    /// The byte index in the query of the char at `column`.
+    fn byte_index(&self, column: usize) -> usize {
+        self.query
+            .char_indices()
+            .nth(column)
+            .map_or(self.query.len(), |(idx, _)| idx)
     }
 
     fn focus(&mut self, direction: Direction) {
@@ -87,7 +103,9 @@ impl<Id: Eq + Copy> Selector<Id> {
         }
     }
 
+    /// Replaces the entries, which are ordered best first for a new query, and focuses the best.
     fn set_entries(&mut self, entries: Vec<Id>) {
+        self.focused = entries.first().copied();
         self.entries = entries;
     }
 }
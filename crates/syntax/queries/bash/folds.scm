@@ -0,0 +1,10 @@
+[
+  (function_definition)
+  (if_statement)
+  (case_statement)
+  (for_statement)
+  (while_statement)
+  (compound_statement)
+] @fold
+
+(comment)+ @fold
@@ -0,0 +1,17 @@
+[
+  (compound_statement)
+  (do_group)
+  (if_statement)
+  (elif_clause)
+  (else_clause)
+  (case_item)
+] @indent
+
+[
+  "}"
+  "done"
+  "fi"
+  "esac"
+  "elif"
+  "else"
+] @outdent
@@ -0,0 +1,11 @@
+(function_definition
+  body: (_) @function.inside) @function.around
+
+(comment) @comment.inside
+
+(comment)+ @comment.around
+
+[
+  (compound_statement)
+  (do_group)
+] @block.around @block.inside
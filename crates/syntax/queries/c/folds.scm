@@ -0,0 +1,12 @@
+[
+  (function_definition)
+  (struct_specifier)
+  (enum_specifier)
+  (union_specifier)
+  (compound_statement)
+  (initializer_list)
+  (preproc_if)
+  (preproc_ifdef)
+  (preproc_else)
+  (comment)
+] @fold
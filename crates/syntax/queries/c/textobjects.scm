@@ -0,0 +1,23 @@
+(function_definition
+  body: (_) @function.inside) @function.around
+
+[
+  (struct_specifier
+    body: (_) @class.inside)
+  (union_specifier
+    body: (_) @class.inside)
+  (enum_specifier
+    body: (_) @class.inside)
+] @class.around
+
+(parameter_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(argument_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(comment) @comment.inside
+
+(comment)+ @comment.around
+
+(compound_statement) @block.around @block.inside
@@ -0,0 +1,9 @@
+; what C++ adds to the C query.
+
+[
+  (class_specifier)
+  (namespace_definition)
+  (field_declaration_list)
+  (template_declaration)
+  (lambda_expression)
+] @fold
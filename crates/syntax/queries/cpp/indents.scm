@@ -0,0 +1,6 @@
+; what C++ adds to the C query.
+
+[
+  (declaration_list)
+  (template_parameter_list)
+] @indent
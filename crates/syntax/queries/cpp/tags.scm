@@ -0,0 +1,17 @@
+; what C++ adds to the C query.
+
+(function_definition
+  declarator: (function_declarator
+    declarator: [
+      (field_identifier)
+      (qualified_identifier)
+      (destructor_name)
+      (operator_name)
+    ] @name)) @definition.function
+
+(class_specifier
+  name: (_) @name
+  body: (_)) @definition.class
+
+(namespace_definition
+  name: (_) @name) @definition.module
@@ -0,0 +1,13 @@
+; what C++ adds to the C query.
+
+(lambda_expression
+  body: (_) @function.inside) @function.around
+
+(class_specifier
+  body: (_) @class.inside) @class.around
+
+(template_parameter_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(template_argument_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
@@ -0,0 +1,13 @@
+[
+  (function_declaration)
+  (method_declaration)
+  (func_literal)
+  (type_declaration)
+  (import_declaration)
+  (const_declaration)
+  (var_declaration)
+  (block)
+  (composite_literal)
+] @fold
+
+(comment)+ @fold
@@ -0,0 +1,21 @@
+[
+  (block)
+  (literal_value)
+  (field_declaration_list)
+  (interface_type)
+  (argument_list)
+  (parameter_list)
+  (import_spec_list)
+  (const_declaration)
+  (var_declaration)
+  (expression_case)
+  (default_case)
+  (type_case)
+  (communication_case)
+] @indent
+
+[
+  "}"
+  "]"
+  ")"
+] @outdent
@@ -0,0 +1,17 @@
+(function_declaration
+  name: (identifier) @name) @definition.function
+
+(method_declaration
+  name: (field_identifier) @name) @definition.method
+
+(type_spec
+  name: (type_identifier) @name
+  type: (struct_type)) @definition.struct
+
+(type_spec
+  name: (type_identifier) @name
+  type: (interface_type)) @definition.interface
+
+; types that are neither, as the patterns before this one take precedence.
+(type_spec
+  name: (type_identifier) @name) @definition.type
@@ -0,0 +1,28 @@
+[
+  (function_declaration
+    body: (_)? @function.inside)
+  (method_declaration
+    body: (_)? @function.inside)
+  (func_literal
+    body: (_) @function.inside)
+] @function.around
+
+(type_declaration
+  (type_spec
+    type: [
+      (struct_type
+        (field_declaration_list) @class.inside)
+      (interface_type)
+    ])) @class.around
+
+(parameter_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(argument_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(comment) @comment.inside
+
+(comment)+ @comment.around
+
+(block) @block.around @block.inside
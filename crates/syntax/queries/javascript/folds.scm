@@ -0,0 +1,11 @@
+[
+  (statement_block)
+  (class_body)
+  (switch_body)
+  (object)
+  (array)
+  (arguments)
+  (template_string)
+  (import_statement)
+  (comment)
+] @fold
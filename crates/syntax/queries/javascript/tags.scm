@@ -0,0 +1,18 @@
+[
+  (function_declaration name: (identifier) @name)
+  (generator_function_declaration name: (identifier) @name)
+] @definition.function
+
+; functions assigned to variables, as in `const f = () => {}`.
+(variable_declarator
+  name: (identifier) @name
+  value: [
+    (arrow_function)
+    (function_expression)
+  ]) @definition.function
+
+(class_declaration
+  name: (_) @name) @definition.class
+
+(method_definition
+  name: (_) @name) @definition.method
@@ -0,0 +1,14 @@
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (try_statement)
+  (with_statement)
+  (dictionary)
+  (list)
+  (string)
+] @fold
+
+(comment)+ @fold
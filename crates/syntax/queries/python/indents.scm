@@ -0,0 +1,30 @@
+; a compound statement's body has no end to mark, so it goes on for as long as lines follow it.
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (elif_clause)
+  (else_clause)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (except_clause)
+  (finally_clause)
+] @indent @extend
+
+[
+  (dictionary)
+  (list)
+  (set)
+  (tuple)
+  (argument_list)
+  (parameters)
+  (parenthesized_expression)
+] @indent
+
+[
+  "}"
+  "]"
+  ")"
+] @outdent
@@ -0,0 +1,23 @@
+(function_definition
+  body: (_) @function.inside) @function.around
+
+(lambda
+  body: (_) @function.inside) @function.around
+
+(class_definition
+  body: (_) @class.inside) @class.around
+
+(parameters
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(lambda_parameters
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(argument_list
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(comment) @comment.inside
+
+(comment)+ @comment.around
+
+(block) @block.around @block.inside
@@ -0,0 +1,21 @@
+[
+  (function_item)
+  (impl_item)
+  (trait_item)
+  (struct_item)
+  (enum_item)
+  (union_item)
+  (mod_item)
+  (macro_definition)
+  (macro_invocation)
+  (match_block)
+  (block)
+  (use_declaration)
+  (field_initializer_list)
+  (array_expression)
+  (arguments)
+  (parameters)
+  (block_comment)
+] @fold
+
+(line_comment)+ @fold
@@ -0,0 +1,20 @@
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (enum_variant_list)
+  (field_initializer_list)
+  (match_block)
+  (arguments)
+  (parameters)
+  (array_expression)
+  (tuple_expression)
+  (use_list)
+  (token_tree)
+] @indent
+
+[
+  "}"
+  "]"
+  ")"
+] @outdent
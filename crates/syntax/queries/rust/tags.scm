@@ -0,0 +1,39 @@
+(function_item
+  name: (identifier) @name) @definition.function
+
+(function_signature_item
+  name: (identifier) @name) @definition.function
+
+[
+  (struct_item name: (type_identifier) @name)
+  (union_item name: (type_identifier) @name)
+] @definition.struct
+
+(enum_item
+  name: (type_identifier) @name) @definition.enum
+
+(type_item
+  name: (type_identifier) @name) @definition.type
+
+(trait_item
+  name: (type_identifier) @name) @definition.trait
+
+; `impl Trait for Type` is named by both.
+(impl_item
+  trait: (_) @name
+  type: (_) @name) @definition.impl
+
+(impl_item
+  type: (_) @name
+  !trait) @definition.impl
+
+(mod_item
+  name: (identifier) @name) @definition.module
+
+(macro_definition
+  name: (identifier) @name) @definition.macro
+
+[
+  (const_item name: (identifier) @name)
+  (static_item name: (identifier) @name)
+] @definition.constant
@@ -0,0 +1,6 @@
+; what TypeScript adds to the JavaScript query.
+
+[
+  (object_type)
+  (enum_body)
+] @fold
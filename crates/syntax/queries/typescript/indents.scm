@@ -0,0 +1,8 @@
+; what TypeScript adds to the JavaScript query.
+
+[
+  (object_type)
+  (enum_body)
+  (type_parameters)
+  (type_arguments)
+] @indent
@@ -0,0 +1,18 @@
+; what TypeScript adds to the JavaScript query.
+
+(abstract_class_declaration
+  name: (type_identifier) @name) @definition.class
+
+(interface_declaration
+  name: (type_identifier) @name) @definition.interface
+
+(type_alias_declaration
+  name: (type_identifier) @name) @definition.type
+
+(enum_declaration
+  name: (identifier) @name) @definition.enum
+
+[
+  (module name: (_) @name)
+  (internal_module name: (_) @name)
+] @definition.module
@@ -0,0 +1,14 @@
+; what TypeScript adds to the JavaScript query.
+
+[
+  (interface_declaration
+    body: (_) @class.inside)
+  (abstract_class_declaration
+    body: (_) @class.inside)
+] @class.around
+
+(type_parameters
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
+
+(type_arguments
+  ((_) @parameter.inside . ","? @parameter.around) @parameter.around)
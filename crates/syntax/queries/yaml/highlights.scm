@@ -0,0 +1,47 @@
+; tree-sitter-yaml ships no highlight query of its own.
+
+(comment) @comment
+
+[
+  (string_scalar)
+  (double_quote_scalar)
+  (single_quote_scalar)
+  (block_scalar)
+] @string
+
+(escape_sequence) @string.escape
+
+[
+  (integer_scalar)
+  (float_scalar)
+] @number
+
+[
+  (boolean_scalar)
+  (null_scalar)
+] @constant.builtin
+
+[
+  (anchor_name)
+  (alias_name)
+] @label
+
+(tag) @type
+
+[
+  (yaml_directive)
+  (tag_directive)
+  (reserved_directive)
+] @keyword
+
+(block_mapping_pair
+  key: (flow_node [(double_quote_scalar) (single_quote_scalar) (plain_scalar (string_scalar))] @property))
+
+(flow_pair
+  key: (flow_node [(double_quote_scalar) (single_quote_scalar) (plain_scalar (string_scalar))] @property))
+
+["[" "]" "{" "}"] @punctuation.bracket
+
+["," "-" ":" "?" ">" "|"] @punctuation.delimiter
+
+["*" "&" "---" "..."] @punctuation.special
@@ -1,33 +1,60 @@
 use anyhow::Result;
 use futures::Stream;
+use std::ops::Range;
 use std::thread;
 use tokio::sync::mpsc;
 use tree_sitter as ts;
 
+use crate::highlighter::Highlighter;
 use crate::BufferContentsTextProvider;
-use crate::{highlighter, Language};
-use editor::{BufferContents, BufferId, Highlights};
+use crate::{Language, LanguageRegistry};
+use editor::{BufferContents, BufferId, HighlightSpans};
 
 #[derive(Debug)]
 pub enum Command {
     Parse {
         buffer_id: BufferId,
+        revision: usize,
         contents: BufferContents,
         language: Language,
     },
+    /// Parses the contents again, reusing the unchanged parts of `tree`, which must have been
+    /// edited to match them.
+    Reparse {
+        buffer_id: BufferId,
+        revision: usize,
+        contents: BufferContents,
+        language: Language,
+        tree: ts::Tree,
+    },
+    /// Highlights the given byte ranges of a parsed tree.
+    Highlight {
+        buffer_id: BufferId,
+        revision: usize,
+        contents: BufferContents,
+        language: Language,
+        tree: ts::Tree,
+        ranges: Vec<Range<usize>>,
+    },
 }
 
+/// Results of syntax commands, tagged with the buffer revision they were computed for.
 #[derive(Debug)]
 pub enum Event {
-    Parsed(BufferId, ts::Tree),
-    Hightlight(BufferId, Highlights),
+    /// A new tree, and the byte ranges whose syntax changed since the tree it was reparsed from.
+    Parsed(BufferId, usize, ts::Tree, Vec<Range<usize>>),
+    Hightlight(BufferId, usize, Vec<Range<usize>>, HighlightSpans),
 }
 
 #[derive(Debug)]
 struct Worker(thread::JoinHandle<Result<()>>);
 
 impl Worker {
-    fn spawn(mut rx: mpsc::Receiver<Command>, tx: mpsc::Sender<Event>) -> Self {
+    fn spawn(
+        mut rx: mpsc::Receiver<Command>,
+        tx: mpsc::UnboundedSender<Event>,
+        languages: LanguageRegistry,
+    ) -> Self {
         // let thread_handle = thread::spawn(move || {
         let thread_handle = thread::Builder::new()
             .name(String::from("syntax"))
@@ -35,26 +62,36 @@ impl Worker {
                 let rt = tokio::runtime::Builder::new_current_thread().build()?;
                 rt.block_on(async {
                     let mut parser = ts::Parser::new();
+                    let mut highlighter = Highlighter::new(languages);
 
                     while let Some(ev) = rx.recv().await {
                         use Command::*;
                         match ev {
-                            Parse { buffer_id, contents, language } => {
-                                let span = tracing::info_span!("parse_ts_tree").entered();
-                                parser.set_language(language.ts)?;
-                                let ts_text = BufferContentsTextProvider(&contents);
-                                let ts_tree =
-                                    parser.parse_with(&mut ts_text.parse_callback(), None);
-                                drop(span);
-                                match ts_tree {
-                                    None => todo!(),
-                                    Some(tree) => {
-                                        tx.send(Event::Parsed(buffer_id, tree.clone())).await?;
-                                        let highlights =
-                                            highlighter::highlight(&contents, language, tree);
-                                        tx.send(Event::Hightlight(buffer_id, highlights)).await?;
-                                    }
-                                }
+                            Parse { buffer_id, revision, contents, language } => {
+                                let Some(tree) = parse(&mut parser, &contents, &language, None)
+                                else {
+                                    continue;
+                                };
+                                tx.send(Event::Parsed(buffer_id, revision, tree, vec![]))?;
+                            }
+                            Reparse { buffer_id, revision, contents, language, tree: old_tree } => {
+                                let old = Some(&old_tree);
+                                let Some(tree) = parse(&mut parser, &contents, &language, old)
+                                else {
+                                    continue;
+                                };
+                                let changed = old_tree
+                                    .changed_ranges(&tree)
+                                    .map(|r| r.start_byte..r.end_byte);
+                                let changed = changed.collect();
+                                tx.send(Event::Parsed(buffer_id, revision, tree, changed))?;
+                            }
+                            Highlight { buffer_id, revision, contents, language, tree, ranges } => {
+                                let highlights =
+                                    highlighter.highlight(&contents, &language, &tree, &ranges);
+                                let highlighted =
+                                    Event::Hightlight(buffer_id, revision, ranges, highlights);
+                                tx.send(highlighted)?;
                             }
                         }
                     }
@@ -62,23 +99,48 @@ impl Worker {
                 })?;
 
                 Ok(())
-            }).expect("failed to spawn syntax worker");
+            })
+            .expect("failed to spawn syntax worker");
         Self(thread_handle)
     }
 }
 
+/// Parses a buffer, or logs why it could not be. The worker goes on either way, as a grammar that
+/// cannot be used only fails its own buffers.
+fn parse(
+    parser: &mut ts::Parser,
+    contents: &BufferContents,
+    language: &Language,
+    old_tree: Option<&ts::Tree>,
+) -> Option<ts::Tree> {
+    let _span = tracing::info_span!("parse_ts_tree").entered();
+    if let Err(e) = parser.set_language(language.ts) {
+        tracing::warn!("cannot parse {}: {}", language.name, e);
+        return None;
+    }
+    let ts_text = BufferContentsTextProvider(contents);
+    let tree = parser.parse_with(&mut ts_text.parse_callback(), old_tree);
+    if tree.is_none() {
+        tracing::warn!("parsing {} was cancelled", language.name);
+    }
+    tree
+}
+
 #[derive(Debug)]
 pub struct Syntax {
     cmd_tx: mpsc::Sender<Command>,
-    event_rx: mpsc::Receiver<Event>,
+    event_rx: mpsc::UnboundedReceiver<Event>,
     worker: Worker,
 }
 
 impl Syntax {
-    pub fn spawn() -> Self {
+    /// Starts the worker. Languages injected into others are looked up in `languages`.
+    pub fn spawn(languages: LanguageRegistry) -> Self {
         let (cmd_tx, cmd_rx) = mpsc::channel(1);
-        let (event_tx, event_rx) = mpsc::channel(1);
-        let worker = Worker::spawn(cmd_rx, event_tx);
+        // events are never waited on, so the worker cannot block on the app while the app
+        // waits on it to take a command.
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let worker = Worker::spawn(cmd_rx, event_tx, languages);
         Syntax { cmd_tx, event_rx, worker }
     }
 
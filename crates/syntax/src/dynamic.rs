@@ -0,0 +1,172 @@
+//! Grammars compiled as shared libraries and loaded at runtime, so that new languages do not
+//! need a rebuild.
+//!
+//! A grammar directory holds `libtree-sitter-<lang>.so` (`.dylib` on macOS), exporting the usual
+//! `tree_sitter_<lang>` function, and its queries in `queries/<lang>/`: `highlights.scm`,
+//! `injections.scm`, `textobjects.scm`, `folds.scm`, `indents.scm` and `tags.scm`. Files with the
+//! `<lang>` extension are detected as the language; others can be set by name. Their lines are
+//! indented as an `indent` file next to the queries says, `tabs` or a number of spaces, and with
+//! four spaces without one. A `highlights.scm` or `injections.scm` that does not compile with
+//! the grammar, as one written for another version of it, is logged and left out.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{bail, Context, Result};
+use tree_sitter as ts;
+
+use editor::Indent;
+
+use crate::{Language, LanguageConfig, Load};
+
+#[derive(Debug)]
+pub struct Grammar {
+    name: &'static str,
+    library: PathBuf,
+    queries: PathBuf,
+    /// The grammar, once its library has been loaded.
+    language: OnceLock<ts::Language>,
+}
+
+impl Grammar {
+    pub(crate) fn load(&self) -> Result<Language> {
+        let ts = match self.language.get() {
+            Some(language) => *language,
+            None => {
+                let language = self.open_library()?;
+                *self.language.get_or_init(|| language)
+            }
+        };
+        let mut language = Language {
+            name: self.name,
+            ts,
+            highlight_query: self.read("highlights.scm")?,
+            injection_query: self.read("injections.scm")?,
+            textobject_query: self.read("textobjects.scm")?,
+            fold_query: self.read("folds.scm")?,
+            indent_query: self.read("indents.scm")?,
+            tag_query: self.read("tags.scm")?,
+            indent: self.read_indent()?,
+        };
+        // queries written for another version of the grammar may not compile, and the language
+        // is still worth having without their highlights.
+        let queries = [
+            ("highlights.scm", &mut language.highlight_query),
+            ("injections.scm", &mut language.injection_query),
+        ];
+        for (filename, query) in queries {
+            if let Err(e) = ts::Query::new(ts, query) {
+                tracing::warn!("{}: {} does not compile: {}", self.name, filename, e);
+                query.clear();
+            }
+        }
+        Ok(language)
+    }
+
+    /// Reads one of the files next to the grammar's queries. A missing file is an empty one.
+    fn read(&self, filename: &str) -> Result<String> {
+        let path = self.queries.join(filename);
+        match std::fs::read_to_string(&path) {
+            Ok(query) => Ok(query),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    fn read_indent(&self) -> Result<Indent> {
+        match self.read("indent")?.trim() {
+            "" => Ok(Indent::default()),
+            indent => indent
+                .parse()
+                .with_context(|| format!("{}: reading its indent", self.name)),
+        }
+    }
+
+    fn open_library(&self) -> Result<ts::Language> {
+        let symbol = format!("tree_sitter_{}", self.name.replace('-', "_"));
+        // SAFETY: loading a library runs its initialisers, and the symbol is trusted to be a
+        // tree-sitter language function as the grammar directory promises.
+        let language = unsafe {
+            let library = libloading::Library::new(&self.library)
+                .with_context(|| format!("loading {}", self.library.display()))?;
+            let function: libloading::Symbol<unsafe extern "C" fn() -> ts::Language> = library
+                .get(symbol.as_bytes())
+                .with_context(|| format!("{} has no {}", self.library.display(), symbol))?;
+            let language = function();
+            // the language points into the library, so it must never be unloaded.
+            std::mem::forget(library);
+            language
+        };
+
+        let version = language.version();
+        if !(ts::MIN_COMPATIBLE_LANGUAGE_VERSION..=ts::LANGUAGE_VERSION).contains(&version) {
+            bail!(
+                "{}: grammar ABI version {} is not supported, it must be between {} and {}",
+                self.library.display(),
+                version,
+                ts::MIN_COMPATIBLE_LANGUAGE_VERSION,
+                ts::LANGUAGE_VERSION
+            );
+        }
+        Ok(language)
+    }
+}
+
+/// The grammars in a grammar directory. Their libraries are only loaded once they are used.
+pub(crate) fn grammars(dir: &Path) -> Result<Vec<LanguageConfig>> {
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+    let mut configs = vec![];
+    let entries = std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))?;
+    for entry in entries {
+        let library = entry?.path();
+        let Some(filename) = library.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(name) = filename
+            .strip_prefix(DLL_PREFIX)
+            .and_then(|name| name.strip_prefix("tree-sitter-"))
+            .and_then(|name| name.strip_suffix(DLL_SUFFIX))
+        else {
+            continue;
+        };
+
+        // names live as long as the loaded libraries do, which is until the process exits.
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let extensions: &'static [&'static str] = Box::leak(Box::new([name]));
+        let queries = dir.join("queries").join(name);
+        let grammar = Grammar { name, library, queries, language: OnceLock::new() };
+        configs.push(LanguageConfig {
+            name,
+            aliases: &[],
+            extensions,
+            filenames: &[],
+            interpreters: &[],
+            load: Load::Dynamic(Arc::new(grammar)),
+        });
+    }
+    configs.sort_by_key(|config| config.name);
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_grammar_libraries() {
+        use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+        let dir = std::env::temp_dir().join(format!("toku-grammars-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let library = format!("{}tree-sitter-dsl{}", DLL_PREFIX, DLL_SUFFIX);
+        std::fs::write(dir.join(library), b"not a library").unwrap();
+        std::fs::write(dir.join("README"), b"").unwrap();
+
+        let configs = grammars(&dir).unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!((configs[0].name, configs[0].extensions), ("dsl", &["dsl"][..]));
+        assert!(configs[0].load().is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,25 +1,470 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
+
 use tree_sitter as ts;
 
-use crate::Language;
-use editor::BufferContents;
+use editor::{paint, BufferContents, HighlightSpans};
+
+use crate::{Language, LanguageRegistry};
+
+/// How deep injections may nest, as in a code block of a Markdown file in a Markdown file.
+const MAX_INJECTION_DEPTH: usize = 4;
+
+/// How many requests an injected tree is kept for after it was last used.
+const KEEP_INJECTED_TREES: usize = 16;
+
+/// Highlights buffers, along with the languages embedded in them as their injection queries
+/// describe.
+pub(crate) struct Highlighter {
+    languages: LanguageRegistry,
+    /// Parses the injected ranges, so that the buffer's own parser is left alone.
+    parser: ts::Parser,
+    /// Compiled queries by language. Compiling them is slow, so they are kept.
+    queries: HashMap<&'static str, Rc<Queries>>,
+    /// The injected languages by the name they were injected as, or `None` if not known.
+    injected: HashMap<String, Option<Language>>,
+    /// The trees of injected text by language and the ranges injected, so that they are only
+    /// parsed again once their text changes.
+    injected_trees: HashMap<(&'static str, Vec<ts::Range>), InjectedTree>,
+    /// How many requests there have been, to drop the injected trees no longer used.
+    requests: usize,
+}
 
-#[tracing::instrument(skip_all)]
-pub fn highlight(
-    buffer: &BufferContents,
-    language: Language,
+struct InjectedTree {
+    /// A hash of the injected text.
+    text: u64,
     tree: ts::Tree,
-) -> editor::Highlights {
-    let query = ts::Query::new(language.ts, &language.highlight_query).expect("invalid query");
+    /// The last request that used the tree.
+    used: usize,
+}
+
+/// The queries of a language, or `None` for those that are empty or do not compile.
+struct Queries {
+    highlight_source: String,
+    injection_source: String,
+    highlights: Option<ts::Query>,
+    injections: Option<ts::Query>,
+}
+
+/// Where a language is embedded in a tree.
+#[derive(Debug)]
+struct Injection {
+    language: String,
+    ranges: Vec<ts::Range>,
+}
+
+impl Highlighter {
+    pub fn new(languages: LanguageRegistry) -> Self {
+        Self {
+            languages,
+            parser: ts::Parser::new(),
+            queries: HashMap::new(),
+            injected: HashMap::new(),
+            injected_trees: HashMap::new(),
+            requests: 0,
+        }
+    }
+
+    /// Runs the highlight query over the given byte ranges of a tree only. Injected languages
+    /// are parsed and highlighted over those ranges as well, their highlights taking precedence
+    /// over those of the language they are embedded in. A language whose queries do not compile
+    /// has no highlights, or no injections.
+    #[tracing::instrument(skip_all)]
+    pub fn highlight(
+        &mut self,
+        contents: &BufferContents,
+        language: &Language,
+        tree: &ts::Tree,
+        ranges: &[Range<usize>],
+    ) -> HighlightSpans {
+        self.requests += 1;
+        let highlights = self.highlight_layer(contents, language, tree, ranges, 0);
+        let requests = self.requests;
+        self.injected_trees
+            .retain(|_, tree| requests - tree.used < KEEP_INJECTED_TREES);
+        highlights
+    }
+
+    fn highlight_layer(
+        &mut self,
+        contents: &BufferContents,
+        language: &Language,
+        tree: &ts::Tree,
+        ranges: &[Range<usize>],
+        depth: usize,
+    ) -> HighlightSpans {
+        let queries = self.queries(language);
+        let mut highlights = match queries.highlights.as_ref() {
+            Some(query) => captures(contents, query, tree, ranges),
+            None => HighlightSpans::new(),
+        };
+        let Some(injection_query) = queries.injections.as_ref() else {
+            return highlights;
+        };
+        if depth >= MAX_INJECTION_DEPTH {
+            return highlights;
+        }
+
+        for injection in injections(contents, injection_query, tree, ranges) {
+            let Some(language) = self.injected_language(&injection.language) else {
+                continue;
+            };
+            let Some(tree) = self.parse_injection(contents, &language, &injection.ranges) else {
+                continue;
+            };
+            let injected = self.highlight_layer(contents, &language, &tree, ranges, depth + 1);
+            overlay(&mut highlights, injected);
+        }
+        highlights
+    }
+
+    /// The compiled queries of a language. Those that do not compile are only reported once.
+    fn queries(&mut self, language: &Language) -> Rc<Queries> {
+        if let Some(queries) = self.queries.get(language.name) {
+            if queries.highlight_source == language.highlight_query
+                && queries.injection_source == language.injection_query
+            {
+                return queries.clone();
+            }
+        }
+        let compile = |kind: &str, source: &str| match source.trim() {
+            "" => None,
+            source => ts::Query::new(language.ts, source)
+                .map_err(|e| tracing::warn!("{} {} query: {}", language.name, kind, e))
+                .ok(),
+        };
+        let highlights = compile("highlights", &language.highlight_query);
+        let injections = compile("injections", &language.injection_query);
+        let queries = Rc::new(Queries {
+            highlight_source: language.highlight_query.clone(),
+            injection_source: language.injection_query.clone(),
+            highlights,
+            injections,
+        });
+        self.queries.insert(language.name, queries.clone());
+        queries
+    }
+
+    fn injected_language(&mut self, name: &str) -> Option<Language> {
+        if let Some(language) = self.injected.get(name) {
+            return language.clone();
+        }
+        let language = self.languages.by_name(name).unwrap_or_else(|e| {
+            tracing::warn!("cannot load injected language {}: {:#}", name, e);
+            None
+        });
+        self.injected.insert(name.to_string(), language.clone());
+        language
+    }
+
+    /// The tree of injected text, parsed again only if the text has changed since it was last.
+    fn parse_injection(
+        &mut self,
+        contents: &BufferContents,
+        language: &Language,
+        ranges: &[ts::Range],
+    ) -> Option<ts::Tree> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for range in ranges {
+            let text = contents.byte_slice(range.start_byte..range.end_byte);
+            text.chunks().for_each(|chunk| chunk.hash(&mut hasher));
+        }
+        let text = hasher.finish();
+        let key = (language.name, ranges.to_vec());
+        if let Some(injected) = self.injected_trees.get_mut(&key) {
+            if injected.text == text {
+                injected.used = self.requests;
+                return Some(injected.tree.clone());
+            }
+        }
+        let tree = self.parse_injected_text(contents, language, ranges)?;
+        let injected = InjectedTree { text, tree: tree.clone(), used: self.requests };
+        self.injected_trees.insert(key, injected);
+        Some(tree)
+    }
+
+    fn parse_injected_text(
+        &mut self,
+        contents: &BufferContents,
+        language: &Language,
+        ranges: &[ts::Range],
+    ) -> Option<ts::Tree> {
+        let _span = tracing::info_span!("parse_injection", language = language.name).entered();
+        self.parser.set_language(language.ts).ok()?;
+        self.parser.set_included_ranges(ranges).ok()?;
+        let text = crate::BufferContentsTextProvider(contents);
+        self.parser.parse_with(&mut text.parse_callback(), None)
+    }
+}
+
+/// The captures of a highlight query over the given byte ranges of a tree, cut to those ranges.
+/// Where captures overlap the innermost one wins, and of captures of the same node the one from
+/// the last pattern in the query.
+fn captures(
+    contents: &BufferContents,
+    query: &ts::Query,
+    tree: &ts::Tree,
+    ranges: &[Range<usize>],
+) -> HighlightSpans {
     let mut cursor = ts::QueryCursor::new();
-    let mut highlights = iset::IntervalMap::new();
-    let captures =
-        cursor.captures(&query, tree.root_node(), crate::BufferContentsTextProvider(buffer));
-    for (query_match, _) in captures {
-        for capture in query_match.captures {
-            let capture_name = &query.capture_names()[capture.index as usize];
-            let capture_range = capture.node.byte_range();
-            highlights.insert(capture_range, capture_name.clone());
+    let mut highlights = HighlightSpans::new();
+    for range in ranges {
+        cursor.set_byte_range(range.clone());
+        let matches =
+            cursor.captures(query, tree.root_node(), crate::BufferContentsTextProvider(contents));
+        let mut captures: Vec<_> = matches
+            .flat_map(|(query_match, _)| {
+                let pattern_index = query_match.pattern_index;
+                query_match
+                    .captures
+                    .iter()
+                    .map(move |capture| (capture.node.byte_range(), pattern_index, capture.index))
+            })
+            .collect();
+        // outer nodes first so that the nodes inside them are painted over them.
+        captures.sort_by_key(|(node, pattern_index, _)| {
+            (node.start, std::cmp::Reverse(node.end), *pattern_index)
+        });
+        for (node, _, capture_index) in captures {
+            let start = node.start.max(range.start);
+            let end = node.end.min(range.end);
+            let name = &query.capture_names()[capture_index as usize];
+            paint(&mut highlights, start..end, name.clone());
         }
     }
     highlights
 }
+
+/// The injections an injection query finds over the given byte ranges of a tree, following the
+/// tree-sitter conventions: the `injection.content` capture is the embedded text and the language
+/// is either the text of the `injection.language` capture or set with `#set! injection.language`.
+/// Named children of the content are left out unless `injection.include-children` is set, and
+/// the matches of a pattern setting `injection.combined` are parsed together as one document.
+fn injections(
+    contents: &BufferContents,
+    query: &ts::Query,
+    tree: &ts::Tree,
+    ranges: &[Range<usize>],
+) -> Vec<Injection> {
+    let capture_index = |name| query.capture_names().iter().position(|n| n == name);
+    let Some(content_capture) = capture_index("injection.content") else {
+        return vec![];
+    };
+    let language_capture = capture_index("injection.language");
+
+    let mut cursor = ts::QueryCursor::new();
+    let mut injections: Vec<Injection> = vec![];
+    // the combined injections by pattern and language, as indices into `injections`.
+    let mut combined: HashMap<(usize, String), usize> = HashMap::new();
+    let mut seen = std::collections::HashSet::new();
+    for range in ranges {
+        cursor.set_byte_range(range.clone());
+        let matches =
+            cursor.matches(query, tree.root_node(), crate::BufferContentsTextProvider(contents));
+        for query_match in matches {
+            let properties = query.property_settings(query_match.pattern_index);
+            let property = |key: &str| properties.iter().find(|p| &*p.key == key);
+            let language = query_match
+                .captures
+                .iter()
+                .find(|capture| Some(capture.index as usize) == language_capture)
+                .map(|capture| contents.byte_slice(capture.node.byte_range()).to_string())
+                .or_else(|| {
+                    property("injection.language")?
+                        .value
+                        .as_deref()
+                        .map(String::from)
+                });
+            let Some(language) = language.map(|language| language.trim().to_string()) else {
+                continue;
+            };
+            let include_children = property("injection.include-children").is_some();
+
+            let nodes = query_match
+                .nodes_for_capture_index(content_capture as u32)
+                // a node found again from an overlapping range is already injected.
+                .filter(|node| seen.insert((query_match.pattern_index, node.id())));
+            let content: Vec<_> = nodes
+                .flat_map(|node| content_ranges(node, include_children))
+                .collect();
+            if content.is_empty() || language.is_empty() {
+                continue;
+            }
+
+            if property("injection.combined").is_some() {
+                let key = (query_match.pattern_index, language.clone());
+                if let Some(&idx) = combined.get(&key) {
+                    injections[idx].ranges.extend(content);
+                    continue;
+                }
+                combined.insert(key, injections.len());
+            }
+            injections.push(Injection { language, ranges: content });
+        }
+    }
+    for injection in injections.iter_mut() {
+        injection.ranges.sort_by_key(|range| range.start_byte);
+    }
+    injections
+}
+
+/// The ranges of a node to parse as injected text, leaving out its named children unless asked
+/// not to.
+fn content_ranges(node: ts::Node, include_children: bool) -> Vec<ts::Range> {
+    let range = node.range();
+    if include_children {
+        return vec![range];
+    }
+    let mut ranges = vec![];
+    let (mut start_byte, mut start_point) = (range.start_byte, range.start_point);
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.start_byte() > start_byte {
+            ranges.push(ts::Range {
+                start_byte,
+                end_byte: child.start_byte(),
+                start_point,
+                end_point: child.start_position(),
+            });
+        }
+        (start_byte, start_point) = (child.end_byte(), child.end_position());
+    }
+    if range.end_byte > start_byte {
+        ranges.push(ts::Range { start_byte, start_point, ..range });
+    }
+    ranges
+}
+
+/// Puts the highlights of an injected language over those of the language it is embedded in,
+/// cutting the latter where the former are.
+fn overlay(highlights: &mut HighlightSpans, injected: HighlightSpans) {
+    for (range, name) in injected.iter(..) {
+        paint(highlights, range, name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{language, parse};
+
+    #[test]
+    fn injections_take_precedence() {
+        let mut highlights = HighlightSpans::new();
+        highlights.insert(0..10, "string".to_string());
+        let mut injected = HighlightSpans::new();
+        injected.insert(2..4, "keyword".to_string());
+        injected.insert(6..8, "number".to_string());
+        overlay(&mut highlights, injected);
+        let ranges: Vec<_> = highlights
+            .iter(..)
+            .map(|(range, name)| (range, name.as_str()))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0..2, "string"),
+                (2..4, "keyword"),
+                (4..6, "string"),
+                (6..8, "number"),
+                (8..10, "string")
+            ]
+        );
+    }
+
+    /// The highlighted text of a snippet over a byte range, with its names.
+    fn highlight(name: &str, text: &str, range: Range<usize>) -> Vec<(String, String)> {
+        highlight_language(&language(name), text, range)
+    }
+
+    fn highlight_language(
+        language: &Language,
+        text: &str,
+        range: Range<usize>,
+    ) -> Vec<(String, String)> {
+        let (tree, buffer) = parse(language, text);
+        let mut highlighter = Highlighter::new(LanguageRegistry::default());
+        let highlights = highlighter.highlight(&buffer.contents, language, &tree, &[range]);
+        highlights
+            .iter(..)
+            .map(|(range, name)| (text[range].to_string(), name.clone()))
+            .collect()
+    }
+
+    fn has(spans: &[(String, String)], text: &str, name: &str) -> bool {
+        spans.iter().any(|span| span.0 == text && span.1 == name)
+    }
+
+    #[test]
+    fn innermost_capture_wins() {
+        let text = r#"fn main() { let s = "a\nb"; }"#;
+        let spans = highlight("rust", text, 0..text.len());
+        assert!(has(&spans, r#""a"#, "string"), "{:?}", spans);
+        assert!(has(&spans, r"\n", "escape"), "{:?}", spans);
+        assert!(has(&spans, r#"b""#, "string"), "{:?}", spans);
+    }
+
+    #[test]
+    fn last_pattern_wins() {
+        // `Foo` is both a type in a path and an uppercase identifier, the later pattern.
+        let text = "fn main() { Foo::bar(); println!(\"hi\"); }";
+        let spans = highlight("rust", text, 0..text.len());
+        assert!(has(&spans, "Foo", "constructor"), "{:?}", spans);
+        assert!(has(&spans, "bar", "function"), "{:?}", spans);
+        assert!(has(&spans, "println", "function.macro"), "{:?}", spans);
+    }
+
+    #[test]
+    fn invalid_queries_have_no_highlights() {
+        let mut language = language("rust");
+        // as a query written for another version of the grammar may be.
+        language.highlight_query = "(no_such_node) @keyword".into();
+        let text = "fn main() {}";
+        assert!(highlight_language(&language, text, 0..text.len()).is_empty());
+    }
+
+    #[test]
+    fn invalid_injections_keep_highlights() {
+        let mut language = language("rust");
+        language.injection_query = "(no_such_node) @injection.content".into();
+        let text = "fn main() {}";
+        let spans = highlight_language(&language, text, 0..text.len());
+        assert!(has(&spans, "fn", "keyword"), "{:?}", spans);
+    }
+
+    #[test]
+    fn reuses_injected_trees() {
+        let language = language("rust");
+        let mut highlighter = Highlighter::new(LanguageRegistry::default());
+        let mut highlight = |text: &str| {
+            let (tree, buffer) = parse(&language, text);
+            let range = 0..text.len();
+            highlighter.highlight(&buffer.contents, &language, &tree, &[range]);
+            let trees: Vec<_> = highlighter.injected_trees.values().collect();
+            assert_eq!(trees.len(), 1);
+            trees[0].tree.root_node().id()
+        };
+        // the text in the macro is injected.
+        let first = highlight("fn main() { m!(a); }");
+        assert_eq!(highlight("fn main() { m!(a); }"), first);
+        assert_ne!(highlight("fn main() { m!(b); }"), first);
+    }
+
+    #[test]
+    fn captures_are_cut_to_the_range() {
+        let text = "// a comment\nfn main() {}\n";
+        let spans = highlight("rust", text, 3..8);
+        assert_eq!(spans, vec![("a com".to_string(), "comment".to_string())]);
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn highlights_code_blocks() {
+        let text = "# Title\n\n```rust\nfn main() {}\n```\n";
+        let spans = highlight("markdown", text, 0..text.len());
+        assert!(has(&spans, "fn", "keyword"), "{:?}", spans);
+        assert!(has(&spans, "main", "function"), "{:?}", spans);
+    }
+}
@@ -1,21 +1,315 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
 use tree_sitter as ts;
 
-use editor::Buffer;
+use editor::{BufferContents, Indent};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Language {
+    pub name: &'static str,
     pub ts: ts::Language,
     pub highlight_query: String,
+    /// Where other languages are embedded, as in `injections.scm`. May be empty.
+    pub injection_query: String,
+    /// The text objects of `textobjects.scm`, see [`editor::SyntaxObject`]. May be empty.
+    pub textobject_query: String,
+    /// The regions that fold, captured as `@fold` in `folds.scm`. May be empty.
+    pub fold_query: String,
+    /// How lines are indented, with `@indent`, `@outdent` and `@extend` captures in
+    /// `indents.scm`. May be empty.
+    pub indent_query: String,
+    /// The definitions listed in a buffer's outline, captured as `@definition.<kind>` with their
+    /// names as `@name` in `tags.scm`. May be empty.
+    pub tag_query: String,
+    /// What the tab key inserts in the language's files, unless set in the registry.
+    pub indent: Indent,
+}
+
+/// How to recognise the files of a language, and how to load it.
+#[derive(Debug, Clone)]
+pub struct LanguageConfig {
+    pub name: &'static str,
+    /// Other names for the language, as modelines may use.
+    pub aliases: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+    /// Whole file names, for files such as `Makefile` that have no telling extension.
+    pub filenames: &'static [&'static str],
+    /// The interpreters a shebang line may name, such as `python3` or `bash`.
+    pub interpreters: &'static [&'static str],
+    pub load: Load,
+}
+
+#[derive(Debug, Clone)]
+pub enum Load {
+    /// A grammar compiled in.
+    Builtin(fn() -> Language),
+    /// A grammar in a shared library, loaded the first time it is needed.
+    Dynamic(Arc<crate::dynamic::Grammar>),
+}
+
+impl LanguageConfig {
+    pub fn load(&self) -> Result<Language> {
+        match &self.load {
+            Load::Builtin(load) => Ok(load()),
+            Load::Dynamic(grammar) => grammar.load(),
+        }
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The languages the editor knows, and the detection of which one a file is in.
+#[derive(Debug, Clone)]
+pub struct LanguageRegistry {
+    configs: Vec<LanguageConfig>,
+    /// The indentation set for languages over their own, by name.
+    indents: HashMap<&'static str, Indent>,
+}
+
+impl Default for LanguageRegistry {
+    /// The languages compiled in.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for config in crate::languages::builtin() {
+            registry.register(config);
+        }
+        registry
+    }
+}
+
+impl LanguageRegistry {
+    pub fn empty() -> Self {
+        Self { configs: vec![], indents: HashMap::new() }
+    }
+
+    /// Adds a language, taking precedence over those registered before it.
+    pub fn register(&mut self, config: LanguageConfig) {
+        self.configs.insert(0, config);
+    }
+
+    /// Adds the grammars in a grammar directory, see [`crate::dynamic`].
+    pub fn load_dir(&mut self, dir: &Path) -> Result<()> {
+        for config in crate::dynamic::grammars(dir)? {
+            self.register(config);
+        }
+        Ok(())
+    }
+
+    /// Sets how the files of a language are indented, over how the language does it.
+    pub fn set_indent(&mut self, name: &str, indent: Indent) -> Result<()> {
+        let config = self
+            .config(name)
+            .with_context(|| format!("no language is named `{}`", name))?;
+        self.indents.insert(config.name, indent);
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.configs.iter().map(|config| config.name)
+    }
+
+    pub fn by_name(&self, name: &str) -> Result<Option<Language>> {
+        self.config(name)
+            .map(|config| self.load(config))
+            .transpose()
+    }
+
+    /// Detects the language of a file from a modeline, its name, its extension or its shebang
+    /// line, in that order. `None` means plain text.
+    pub fn detect(
+        &self,
+        path: Option<&Path>,
+        contents: &BufferContents,
+    ) -> Result<Option<Language>> {
+        if let Some(name) = modeline(contents) {
+            if let Some(config) = self.config(&name) {
+                return self.load(config).map(Some);
+            }
+        }
+
+        let filename = path.and_then(|path| path.file_name()?.to_str());
+        let by_filename = filename.and_then(|filename| {
+            self.configs
+                .iter()
+                .find(|config| config.filenames.contains(&filename))
+        });
+        let extension = path.and_then(|path| path.extension()?.to_str());
+        let by_extension = extension.and_then(|extension| {
+            self.configs
+                .iter()
+                .find(|config| config.extensions.contains(&extension))
+        });
+        let by_shebang = || {
+            let interpreter = shebang(contents)?;
+            self.configs
+                .iter()
+                .find(|config| config.interpreters.iter().any(|i| runs(i, &interpreter)))
+        };
+        let config = by_filename.or(by_extension).or_else(by_shebang);
+        config.map(|config| self.load(config)).transpose()
+    }
+
+    fn load(&self, config: &LanguageConfig) -> Result<Language> {
+        let mut language = config.load()?;
+        if let Some(indent) = self.indents.get(config.name) {
+            language.indent = *indent;
+        }
+        Ok(language)
+    }
+
+    fn config(&self, name: &str) -> Option<&LanguageConfig> {
+        self.configs.iter().find(|config| config.is_named(name))
+    }
 }
 
-impl TryFrom<&Buffer> for Language {
-    type Error = anyhow::Error;
+/// The interpreter a shebang line runs, looking through `env`.
+fn shebang(contents: &BufferContents) -> Option<String> {
+    let line = contents.lines().next()?.to_string();
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    let interpreter = match program {
+        "env" => words.find(|word| !word.starts_with('-'))?,
+        program => program,
+    };
+    Some(interpreter.to_string())
+}
 
-    fn try_from(_: &Buffer) -> Result<Self> {
-        Ok(Language {
-            ts: tree_sitter_rust::language(),
-            highlight_query: tree_sitter_rust::HIGHLIGHT_QUERY.into(),
+/// Whether an interpreter named in a shebang is the given one, allowing a version after it as in
+/// `python3.11`.
+fn runs(name: &str, interpreter: &str) -> bool {
+    interpreter
+        .strip_prefix(name)
+        .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
+/// The language set by a vim modeline in the first or last five lines, as in `vim: ft=rust` or
+/// `vim: set filetype=rust:`, or by an emacs one in the first two, as in `-*- mode: rust -*-`.
+fn modeline(contents: &BufferContents) -> Option<String> {
+    let len = contents.len_lines();
+    let lines = (0..len.min(5)).chain(len.saturating_sub(5).max(5)..len);
+    for line_idx in lines {
+        let line = contents.line(line_idx).to_string();
+        if line_idx < 2 {
+            if let Some(mode) = emacs_mode(&line) {
+                return Some(mode);
+            }
+        }
+        if let Some(filetype) = vim_filetype(&line) {
+            return Some(filetype);
+        }
+    }
+    None
+}
+
+fn vim_filetype(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| {
+            let idx = line.find(marker)?;
+            let preceded_by_space = idx == 0 || line[..idx].ends_with(char::is_whitespace);
+            preceded_by_space.then_some(idx + marker.len())
         })
+        .min()?;
+    let options = line[start..].trim_start();
+    let options = options
+        .strip_prefix("set ")
+        .or_else(|| options.strip_prefix("se "))
+        .map_or(options, |options| options.split(':').next().unwrap_or(""));
+    options
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syntax" | "syn").then(|| value.to_string())
+        })
+}
+
+fn emacs_mode(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+    if !vars.contains(':') {
+        return Some(vars.to_lowercase());
+    }
+    vars.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then(|| value.trim().to_lowercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(text: &str) -> BufferContents {
+        let mut buffer = editor::Buffer::empty(editor::BufferId::default());
+        buffer.insert(0, text);
+        buffer.contents
+    }
+
+    fn registry() -> LanguageRegistry {
+        let mut registry = LanguageRegistry::default();
+        let rust = || Language { name: "script", ..crate::testing::language("rust") };
+        registry.register(LanguageConfig {
+            name: "script",
+            aliases: &["sh"],
+            extensions: &["sh"],
+            filenames: &["Makefile"],
+            interpreters: &["bash", "python"],
+            load: Load::Builtin(rust),
+        });
+        registry
+    }
+
+    fn detect(path: &str, text: &str) -> Option<&'static str> {
+        let path = Some(Path::new(path)).filter(|path| !path.as_os_str().is_empty());
+        let language = registry().detect(path, &contents(text)).unwrap();
+        language.map(|language| language.name)
+    }
+
+    #[test]
+    fn detects_by_name_and_shebang() {
+        assert_eq!(detect("src/main.rs", ""), Some("rust"));
+        assert_eq!(detect("a/Makefile", "all:\n"), Some("script"));
+        assert_eq!(detect("run", "#!/usr/bin/env -S python3.11 -u\n"), Some("script"));
+        assert_eq!(detect("run", "#!/bin/bash\n"), Some("script"));
+        assert_eq!(detect("run", "#!/bin/zsh\n"), None);
+        assert_eq!(detect("notes.txt", "hello"), None);
+    }
+
+    #[test]
+    fn modelines_take_precedence() {
+        assert_eq!(detect("x.sh", "// vim: set ft=rust:\n"), Some("rust"));
+        assert_eq!(
+            detect("x.sh", "a\nb\nc\nd\ne\nf\ng\n# vim: ts=4 filetype=rust\n"),
+            Some("rust")
+        );
+        assert_eq!(
+            detect("x.rs", "#!/bin/sh\n# -*- mode: sh; fill-column: 80 -*-\n"),
+            Some("script")
+        );
+        assert_eq!(detect("", "/* -*- Rust -*- */"), Some("rust"));
+        assert_eq!(detect("x.rs", "// novim: ft=sh"), Some("rust"));
+    }
+
+    #[test]
+    fn sets_indents_by_language() {
+        let mut registry = registry();
+        registry.set_indent("sh", Indent::Tabs).unwrap();
+        assert!(registry.set_indent("cobol", Indent::Tabs).is_err());
+        let indent = |name| registry.by_name(name).unwrap().unwrap().indent;
+        assert_eq!(indent("script"), Indent::Tabs);
+        assert_eq!(indent("rust"), Indent::Spaces(4));
+        assert_eq!("2".parse::<Indent>().unwrap(), Indent::Spaces(2));
+        assert!(" 0".parse::<Indent>().is_err());
     }
 }
@@ -0,0 +1,402 @@
+//! The grammars compiled in, each behind a cargo feature of the same name except for Rust.
+
+use editor::Indent;
+
+use crate::{Language, LanguageConfig, Load};
+
+pub(crate) fn builtin() -> Vec<LanguageConfig> {
+    #[allow(unused_mut)]
+    let mut configs = vec![LanguageConfig {
+        name: "rust",
+        aliases: &["rs"],
+        extensions: &["rs"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "rust",
+            ts: tree_sitter_rust::language(),
+            highlight_query: tree_sitter_rust::HIGHLIGHT_QUERY.into(),
+            injection_query: tree_sitter_rust::INJECTIONS_QUERY.into(),
+            textobject_query: include_str!("../queries/rust/textobjects.scm").into(),
+            fold_query: include_str!("../queries/rust/folds.scm").into(),
+            indent_query: include_str!("../queries/rust/indents.scm").into(),
+            tag_query: include_str!("../queries/rust/tags.scm").into(),
+            indent: Indent::Spaces(4),
+        }),
+    }];
+
+    #[cfg(feature = "bash")]
+    configs.push(LanguageConfig {
+        name: "bash",
+        aliases: &["sh", "shell"],
+        extensions: &["sh", "bash"],
+        filenames: &[".bashrc", ".bash_profile", ".profile", "PKGBUILD"],
+        interpreters: &["bash", "sh"],
+        load: Load::Builtin(|| Language {
+            name: "bash",
+            ts: tree_sitter_bash::language(),
+            highlight_query: tree_sitter_bash::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: include_str!("../queries/bash/textobjects.scm").into(),
+            fold_query: include_str!("../queries/bash/folds.scm").into(),
+            indent_query: include_str!("../queries/bash/indents.scm").into(),
+            tag_query: include_str!("../queries/bash/tags.scm").into(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "c")]
+    configs.push(LanguageConfig {
+        name: "c",
+        aliases: &[],
+        extensions: &["c", "h"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "c",
+            ts: tree_sitter_c::language(),
+            highlight_query: tree_sitter_c::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: include_str!("../queries/c/textobjects.scm").into(),
+            fold_query: include_str!("../queries/c/folds.scm").into(),
+            indent_query: include_str!("../queries/c/indents.scm").into(),
+            tag_query: include_str!("../queries/c/tags.scm").into(),
+            indent: Indent::Spaces(4),
+        }),
+    });
+
+    // the C++ query only covers what C++ adds to C.
+    #[cfg(feature = "cpp")]
+    configs.push(LanguageConfig {
+        name: "cpp",
+        aliases: &["c++"],
+        extensions: &["cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "cpp",
+            ts: tree_sitter_cpp::language(),
+            highlight_query: [
+                tree_sitter_c::HIGHLIGHT_QUERY,
+                tree_sitter_cpp::HIGHLIGHT_QUERY,
+            ]
+            .join("\n"),
+            injection_query: String::new(),
+            textobject_query: [
+                include_str!("../queries/c/textobjects.scm"),
+                include_str!("../queries/cpp/textobjects.scm"),
+            ]
+            .join("\n"),
+            fold_query: [
+                include_str!("../queries/c/folds.scm"),
+                include_str!("../queries/cpp/folds.scm"),
+            ]
+            .join("\n"),
+            indent_query: [
+                include_str!("../queries/c/indents.scm"),
+                include_str!("../queries/cpp/indents.scm"),
+            ]
+            .join("\n"),
+            tag_query: [
+                include_str!("../queries/c/tags.scm"),
+                include_str!("../queries/cpp/tags.scm"),
+            ]
+            .join("\n"),
+            indent: Indent::Spaces(4),
+        }),
+    });
+
+    #[cfg(feature = "go")]
+    configs.push(LanguageConfig {
+        name: "go",
+        aliases: &["golang"],
+        extensions: &["go"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "go",
+            ts: tree_sitter_go::language(),
+            highlight_query: tree_sitter_go::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: include_str!("../queries/go/textobjects.scm").into(),
+            fold_query: include_str!("../queries/go/folds.scm").into(),
+            indent_query: include_str!("../queries/go/indents.scm").into(),
+            tag_query: include_str!("../queries/go/tags.scm").into(),
+            indent: Indent::Tabs,
+        }),
+    });
+
+    #[cfg(feature = "javascript")]
+    configs.push(LanguageConfig {
+        name: "javascript",
+        aliases: &["js"],
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        filenames: &[],
+        interpreters: &["node"],
+        load: Load::Builtin(|| Language {
+            name: "javascript",
+            ts: tree_sitter_javascript::language(),
+            highlight_query: [
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+                tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+            ]
+            .join("\n"),
+            injection_query: tree_sitter_javascript::INJECTION_QUERY.into(),
+            textobject_query: include_str!("../queries/javascript/textobjects.scm").into(),
+            fold_query: include_str!("../queries/javascript/folds.scm").into(),
+            indent_query: include_str!("../queries/javascript/indents.scm").into(),
+            tag_query: include_str!("../queries/javascript/tags.scm").into(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "json")]
+    configs.push(LanguageConfig {
+        name: "json",
+        aliases: &[],
+        extensions: &["json"],
+        filenames: &["flake.lock", ".prettierrc"],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "json",
+            ts: tree_sitter_json::language(),
+            highlight_query: tree_sitter_json::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: String::new(),
+            fold_query: include_str!("../queries/json/folds.scm").into(),
+            indent_query: include_str!("../queries/json/indents.scm").into(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "markdown")]
+    configs.push(LanguageConfig {
+        name: "markdown",
+        aliases: &["md"],
+        extensions: &["md", "markdown"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "markdown",
+            ts: tree_sitter_md::language(),
+            highlight_query: tree_sitter_md::HIGHLIGHT_QUERY_BLOCK.into(),
+            injection_query: tree_sitter_md::INJECTION_QUERY_BLOCK.into(),
+            textobject_query: String::new(),
+            fold_query: String::new(),
+            indent_query: String::new(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    // the inline parts of markdown, injected by the block grammar.
+    #[cfg(feature = "markdown")]
+    configs.push(LanguageConfig {
+        name: "markdown_inline",
+        aliases: &[],
+        extensions: &[],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "markdown_inline",
+            ts: tree_sitter_md::inline_language(),
+            // the upstream query needs the wiki link extension, which is not compiled in.
+            highlight_query: include_str!("../queries/markdown_inline/highlights.scm").into(),
+            injection_query: tree_sitter_md::INJECTION_QUERY_INLINE.into(),
+            textobject_query: String::new(),
+            fold_query: String::new(),
+            indent_query: String::new(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "nix")]
+    configs.push(LanguageConfig {
+        name: "nix",
+        aliases: &[],
+        extensions: &["nix"],
+        filenames: &[],
+        interpreters: &["nix-shell"],
+        load: Load::Builtin(|| Language {
+            name: "nix",
+            ts: tree_sitter_nix::language(),
+            highlight_query: tree_sitter_nix::HIGHLIGHTS_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: String::new(),
+            fold_query: String::new(),
+            indent_query: String::new(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "python")]
+    configs.push(LanguageConfig {
+        name: "python",
+        aliases: &["py"],
+        extensions: &["py", "pyi"],
+        filenames: &[],
+        interpreters: &["python"],
+        load: Load::Builtin(|| Language {
+            name: "python",
+            ts: tree_sitter_python::language(),
+            highlight_query: tree_sitter_python::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: include_str!("../queries/python/textobjects.scm").into(),
+            fold_query: include_str!("../queries/python/folds.scm").into(),
+            indent_query: include_str!("../queries/python/indents.scm").into(),
+            tag_query: include_str!("../queries/python/tags.scm").into(),
+            indent: Indent::Spaces(4),
+        }),
+    });
+
+    #[cfg(feature = "toml")]
+    configs.push(LanguageConfig {
+        name: "toml",
+        aliases: &[],
+        extensions: &["toml"],
+        filenames: &["Cargo.lock", "Pipfile", "poetry.lock"],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "toml",
+            ts: tree_sitter_toml::language(),
+            highlight_query: tree_sitter_toml::HIGHLIGHT_QUERY.into(),
+            injection_query: String::new(),
+            textobject_query: String::new(),
+            fold_query: String::new(),
+            indent_query: String::new(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(4),
+        }),
+    });
+
+    // the TypeScript query only covers what TypeScript adds to JavaScript.
+    #[cfg(feature = "typescript")]
+    configs.push(LanguageConfig {
+        name: "typescript",
+        aliases: &["ts"],
+        extensions: &["ts", "mts", "cts"],
+        filenames: &[],
+        interpreters: &["deno", "ts-node"],
+        load: Load::Builtin(|| Language {
+            name: "typescript",
+            ts: tree_sitter_typescript::language_typescript(),
+            highlight_query: [
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+                tree_sitter_typescript::HIGHLIGHT_QUERY,
+            ]
+            .join("\n"),
+            injection_query: tree_sitter_javascript::INJECTION_QUERY.into(),
+            textobject_query: [
+                include_str!("../queries/javascript/textobjects.scm"),
+                include_str!("../queries/typescript/textobjects.scm"),
+            ]
+            .join("\n"),
+            fold_query: [
+                include_str!("../queries/javascript/folds.scm"),
+                include_str!("../queries/typescript/folds.scm"),
+            ]
+            .join("\n"),
+            indent_query: [
+                include_str!("../queries/javascript/indents.scm"),
+                include_str!("../queries/typescript/indents.scm"),
+            ]
+            .join("\n"),
+            tag_query: [
+                include_str!("../queries/javascript/tags.scm"),
+                include_str!("../queries/typescript/tags.scm"),
+            ]
+            .join("\n"),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "typescript")]
+    configs.push(LanguageConfig {
+        name: "tsx",
+        aliases: &[],
+        extensions: &["tsx"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "tsx",
+            ts: tree_sitter_typescript::language_tsx(),
+            highlight_query: [
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+                tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+                tree_sitter_typescript::HIGHLIGHT_QUERY,
+            ]
+            .join("\n"),
+            injection_query: tree_sitter_javascript::INJECTION_QUERY.into(),
+            textobject_query: [
+                include_str!("../queries/javascript/textobjects.scm"),
+                include_str!("../queries/typescript/textobjects.scm"),
+            ]
+            .join("\n"),
+            fold_query: [
+                include_str!("../queries/javascript/folds.scm"),
+                include_str!("../queries/typescript/folds.scm"),
+            ]
+            .join("\n"),
+            indent_query: [
+                include_str!("../queries/javascript/indents.scm"),
+                include_str!("../queries/typescript/indents.scm"),
+            ]
+            .join("\n"),
+            tag_query: [
+                include_str!("../queries/javascript/tags.scm"),
+                include_str!("../queries/typescript/tags.scm"),
+            ]
+            .join("\n"),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    #[cfg(feature = "yaml")]
+    configs.push(LanguageConfig {
+        name: "yaml",
+        aliases: &["yml"],
+        extensions: &["yaml", "yml"],
+        filenames: &[],
+        interpreters: &[],
+        load: Load::Builtin(|| Language {
+            name: "yaml",
+            ts: tree_sitter_yaml::language(),
+            highlight_query: include_str!("../queries/yaml/highlights.scm").into(),
+            injection_query: String::new(),
+            textobject_query: String::new(),
+            fold_query: String::new(),
+            indent_query: String::new(),
+            tag_query: String::new(),
+            indent: Indent::Spaces(2),
+        }),
+    });
+
+    configs
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter as ts;
+
+    #[test]
+    fn queries_compile() {
+        for config in super::builtin() {
+            let language = config.load().unwrap();
+            let queries = [
+                &language.highlight_query,
+                &language.injection_query,
+                &language.textobject_query,
+                &language.fold_query,
+                &language.indent_query,
+                &language.tag_query,
+            ];
+            for source in queries {
+                let query = ts::Query::new(language.ts, source);
+                assert!(query.is_ok(), "{}: {:?}", config.name, query.err());
+            }
+        }
+    }
+}
@@ -1,14 +1,36 @@
 mod client;
+pub mod dynamic;
 mod highlighter;
 mod language;
+mod languages;
+mod symbols;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+mod tree;
 
 pub use client::{Command, Event, Syntax};
-pub use language::Language;
+pub use language::{Language, LanguageConfig, LanguageRegistry, Load};
+pub use symbols::{symbols, Symbol};
+pub use tree::{QueryCache, Tree};
 
-use editor::BufferContents;
+use editor::{BufferContents, Edit};
 use rope::iter::Chunks;
 use tree_sitter as ts;
 
+/// The tree-sitter form of an edit made to a buffer, to apply to its syntax tree with
+/// [`ts::Tree::edit`] before reparsing.
+pub fn input_edit(edit: &Edit) -> ts::InputEdit {
+    let point = |point: editor::Point| ts::Point { row: point.line, column: point.column };
+    ts::InputEdit {
+        start_byte: edit.start_byte,
+        old_end_byte: edit.old_end_byte,
+        new_end_byte: edit.new_end_byte,
+        start_position: point(edit.start_point),
+        old_end_position: point(edit.old_end_point),
+        new_end_position: point(edit.new_end_point),
+    }
+}
+
 #[derive(Debug)]
 struct BufferContentsTextProvider<'a>(&'a BufferContents);
 
@@ -0,0 +1,91 @@
+use tree_sitter as ts;
+
+use editor::{BufferContents, Point};
+
+use crate::tree::hull;
+use crate::{BufferContentsTextProvider, Language, QueryCache};
+
+/// A definition in a buffer, as its outline lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// What is defined, as the query names it after `@definition.`: `function`, `struct`...
+    pub kind: String,
+    /// Where the definition starts.
+    pub point: Point,
+}
+
+/// The definitions that a language's `tags.scm` finds in a buffer, in the order they start. A
+/// definition that several patterns capture is of the kind the first of them says.
+pub fn symbols(
+    language: &Language,
+    tree: &ts::Tree,
+    contents: &BufferContents,
+    queries: &mut QueryCache,
+) -> Vec<Symbol> {
+    let Some(query) = queries.get(language, "tags", &language.tag_query) else {
+        return vec![];
+    };
+    let Some(name) = query.capture_index_for_name("name") else {
+        return vec![];
+    };
+    let names = query.capture_names();
+
+    let mut cursor = ts::QueryCursor::new();
+    let matches =
+        cursor.matches(query.as_ref(), tree.root_node(), BufferContentsTextProvider(contents));
+    let mut found = vec![];
+    for query_match in matches {
+        let definition = query_match.captures.iter().find_map(|capture| {
+            let kind = names[capture.index as usize].strip_prefix("definition.")?;
+            Some((capture.node, kind))
+        });
+        let nodes: Vec<_> = query_match.nodes_for_capture_index(name).collect();
+        let (Some((node, kind)), Some(range)) = (definition, hull(&nodes)) else {
+            continue;
+        };
+        // a name over several lines, as some generic types are, is shown on one.
+        let text = contents.byte_slice(range).to_string();
+        let name = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let point = contents.char_offset_to_point(contents.byte_to_char(node.start_byte()));
+        let symbol = Symbol { name, kind: kind.to_string(), point };
+        found.push((node.start_byte(), node.id(), query_match.pattern_index, symbol));
+    }
+    found.sort_by_key(|(start, id, pattern, _)| (*start, *id, *pattern));
+    found.dedup_by_key(|(_, id, ..)| *id);
+    found.into_iter().map(|(.., symbol)| symbol).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_rust_symbols() {
+        let text = concat!(
+            "mod m {\n    struct S<T>;\n",
+            "    impl<T> Tr for S<T> {\n        fn f() {}\n    }\n}\n",
+            "fn g() {}\n",
+        );
+        let language = crate::testing::language("rust");
+        let (tree, buffer) = crate::testing::parse(&language, text);
+
+        let symbols: Vec<_> =
+            symbols(&language, &tree, &buffer.contents, &mut QueryCache::default())
+                .into_iter()
+                .map(|symbol| (symbol.kind, symbol.name, symbol.point.line))
+                .collect();
+        let expected = [
+            ("module", "m", 0),
+            ("struct", "S", 1),
+            ("impl", "Tr for S<T>", 2),
+            ("function", "f", 3),
+            ("function", "g", 6),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(kind, name, line)| (kind.to_string(), name.to_string(), line))
+            .collect();
+        assert_eq!(symbols, expected);
+    }
+}
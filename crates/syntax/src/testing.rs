@@ -0,0 +1,22 @@
+//! Helpers for the tests of this crate and the crates that use it, behind the `test-support`
+//! feature.
+
+use tree_sitter as ts;
+
+use crate::{Language, LanguageRegistry};
+
+/// A built-in language by name.
+pub fn language(name: &str) -> Language {
+    let language = LanguageRegistry::default().by_name(name);
+    language.unwrap().expect("language is built in")
+}
+
+/// Parses text in a language, returning its tree and a buffer holding the text.
+pub fn parse(language: &Language, text: &str) -> (ts::Tree, editor::Buffer) {
+    let mut parser = ts::Parser::new();
+    parser.set_language(language.ts).unwrap();
+    let tree = parser.parse(text, None).unwrap();
+    let mut buffer = editor::Buffer::empty(editor::BufferId::default());
+    buffer.insert(0, text);
+    (tree, buffer)
+}
@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use tree_sitter as ts;
+
+use editor::{BufferContents, Edit, ObjectRange, SyntaxObject};
+
+use crate::{BufferContentsTextProvider, Language};
+
+/// A parsed buffer, for the editor to find text objects in and move through.
+#[derive(Debug, Clone)]
+pub struct Tree {
+    tree: ts::Tree,
+    textobjects: Option<Arc<ts::Query>>,
+    folds: Option<Arc<ts::Query>>,
+    indents: Option<Arc<ts::Query>>,
+    tags: Option<Arc<ts::Query>>,
+}
+
+impl Tree {
+    pub fn new(tree: ts::Tree, language: &Language, queries: &mut QueryCache) -> Self {
+        let textobjects = queries.get(language, "textobjects", &language.textobject_query);
+        let folds = queries.get(language, "folds", &language.fold_query);
+        let indents = queries.get(language, "indents", &language.indent_query);
+        let tags = queries.get(language, "tags", &language.tag_query);
+        Self { tree, textobjects, folds, indents, tags }
+    }
+}
+
+/// The tokens that open and close brackets. `<` and `>` are only brackets where they pair, as
+/// around generics, and are operators elsewhere.
+const BRACKETS: [(&str, &str); 4] = [("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")];
+
+/// The bracket that pairs with a bracket token: the nearest of the tokens after or before it in
+/// the same node that closes or opens it, so the brackets in strings, comments and other nodes
+/// are left out.
+fn pair(node: ts::Node) -> Option<ts::Node> {
+    if node.is_named() || node.is_missing() {
+        return None;
+    }
+    let kind = node.kind();
+    let (forward, pair) = BRACKETS.iter().find_map(|&(open, close)| match kind {
+        _ if kind == open => Some((true, close)),
+        _ if kind == close => Some((false, open)),
+        _ => None,
+    })?;
+    let mut sibling = node;
+    loop {
+        sibling = match forward {
+            true => sibling.next_sibling()?,
+            false => sibling.prev_sibling()?,
+        };
+        // a bracket the parser had to make up is not in the text.
+        if sibling.kind() == pair && !sibling.is_named() && !sibling.is_missing() {
+            return Some(sibling);
+        }
+    }
+}
+
+/// The compiled queries of languages, by the name of their file. Compiling is slow and trees are
+/// made on every parse, so whoever makes them keeps the queries of the languages used so far.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    queries: HashMap<(&'static str, &'static str), CachedQuery>,
+}
+
+/// The source of a query, and what it compiled to.
+type CachedQuery = (String, Option<Arc<ts::Query>>);
+
+impl QueryCache {
+    /// A language's query of a kind, compiled from `source` unless it was already, or `None` if it
+    /// is empty or does not compile.
+    pub(crate) fn get(
+        &mut self,
+        language: &Language,
+        kind: &'static str,
+        source: &str,
+    ) -> Option<Arc<ts::Query>> {
+        let key = (language.name, kind);
+        if let Some((cached, query)) = self.queries.get(&key) {
+            if cached == source {
+                return query.clone();
+            }
+        }
+        let query = match source.trim() {
+            "" => None,
+            source => match ts::Query::new(language.ts, source) {
+                Ok(query) => Some(Arc::new(query)),
+                Err(e) => {
+                    tracing::warn!("{} {} query: {}", language.name, kind, e);
+                    None
+                }
+            },
+        };
+        self.queries
+            .insert(key, (source.to_string(), query.clone()));
+        query
+    }
+}
+
+impl editor::SyntaxTree for Tree {
+    fn edit(&mut self, edit: &Edit) {
+        self.tree.edit(&crate::input_edit(edit));
+    }
+
+    fn objects(
+        &self,
+        contents: &BufferContents,
+        object: SyntaxObject,
+        range: Range<usize>,
+    ) -> Vec<ObjectRange> {
+        let Some(query) = self.textobjects.as_deref() else {
+            return vec![];
+        };
+        let around = query.capture_index_for_name(&format!("{}.around", object.name()));
+        let inside = query.capture_index_for_name(&format!("{}.inside", object.name()));
+        if around.is_none() && inside.is_none() {
+            return vec![];
+        }
+
+        let mut cursor = ts::QueryCursor::new();
+        cursor.set_byte_range(range);
+        let matches =
+            cursor.matches(query, self.tree.root_node(), BufferContentsTextProvider(contents));
+        let mut objects = vec![];
+        for query_match in matches {
+            let nodes = |index: Option<u32>| {
+                let nodes = index.map(|index| query_match.nodes_for_capture_index(index));
+                nodes.into_iter().flatten().collect::<Vec<_>>()
+            };
+            let around = hull(&nodes(around));
+            let inside = match nodes(inside).as_slice() {
+                [node] => Some(inside_brackets(*node)),
+                nodes => hull(nodes),
+            };
+            if around.is_some() || inside.is_some() {
+                objects.push(ObjectRange { around, inside });
+            }
+        }
+        objects
+    }
+
+    fn sibling(&self, offset: usize, next: bool) -> Option<usize> {
+        let root = self.tree.root_node();
+        let mut node = root.named_descendant_for_byte_range(offset, offset + 1)?;
+
+        // between the children of a node, the sibling is one of them.
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.named_children(&mut cursor).collect();
+        if node.start_byte() != offset
+            && !children
+                .iter()
+                .any(|child| child.byte_range().contains(&offset))
+        {
+            let child = match next {
+                true => children.iter().find(|child| child.start_byte() > offset),
+                false => children
+                    .iter()
+                    .rev()
+                    .find(|child| child.start_byte() < offset),
+            };
+            if let Some(child) = child {
+                return Some(child.start_byte());
+            }
+        }
+
+        while let Some(parent) = node.parent() {
+            if parent.start_byte() != node.start_byte() || parent.parent().is_none() {
+                break;
+            }
+            node = parent;
+        }
+        let sibling = match next {
+            true => node.next_named_sibling(),
+            false => node.prev_named_sibling(),
+        };
+        sibling.map(|sibling| sibling.start_byte())
+    }
+
+    fn folds(&self, contents: &BufferContents) -> Option<Vec<Range<usize>>> {
+        let query = self.folds.as_deref()?;
+        let mut cursor = ts::QueryCursor::new();
+        let matches =
+            cursor.matches(query, self.tree.root_node(), BufferContentsTextProvider(contents));
+        let mut folds: Vec<_> = matches
+            .filter_map(|query_match| {
+                let nodes: Vec<_> = query_match.captures.iter().map(|c| c.node).collect();
+                let start = nodes.iter().map(|node| node.start_position()).min()?;
+                let end = end_line(nodes.iter().map(|node| node.end_position()).max()?);
+                Some(start.row..end)
+            })
+            .filter(|fold| fold.len() > 1)
+            .collect();
+        folds.sort_by_key(|fold| (fold.start, fold.end));
+        folds.dedup();
+        Some(folds)
+    }
+
+    /// A level for each `@indent` node that started on an earlier line and goes on past the
+    /// split, at most one for the nodes starting on the same line, less one if the line starts
+    /// with an `@outdent` node. An `@extend` node that ends at the split still goes on.
+    fn indent_level(
+        &self,
+        contents: &BufferContents,
+        split: usize,
+        content: usize,
+    ) -> Option<isize> {
+        let query = self.indents.as_deref()?;
+        let names = query.capture_names();
+        let mut cursor = ts::QueryCursor::new();
+        cursor.set_byte_range(split.saturating_sub(1)..content + 1);
+        let mut captured: HashMap<usize, (ts::Node, Vec<&str>)> = HashMap::new();
+        let text = BufferContentsTextProvider(contents);
+        for query_match in cursor.matches(query, self.tree.root_node(), text) {
+            for capture in query_match.captures {
+                let name = names[capture.index as usize].as_str();
+                let (_, names) = captured
+                    .entry(capture.node.id())
+                    .or_insert((capture.node, vec![]));
+                names.push(name);
+            }
+        }
+
+        let mut level = 0;
+        let mut rows = vec![];
+        for (node, names) in captured.values() {
+            let has = |name| names.contains(&name);
+            let goes_on = node.end_byte() > split || (has("extend") && node.end_byte() == split);
+            if has("indent") && node.start_byte() < split && goes_on {
+                let row = node.start_position().row;
+                if !rows.contains(&row) {
+                    rows.push(row);
+                    level += 1;
+                }
+            }
+            if has("outdent") && node.start_byte() == content {
+                level -= 1;
+            }
+        }
+        Some(level)
+    }
+
+    /// The definitions that `tags.scm` captures around the line.
+    fn scopes(&self, contents: &BufferContents, line: usize) -> Vec<Range<usize>> {
+        let Some(query) = self.tags.as_deref() else {
+            return vec![];
+        };
+        let names = query.capture_names();
+        let mut cursor = ts::QueryCursor::new();
+        let (start, end) =
+            (ts::Point { row: line, column: 0 }, ts::Point { row: line + 1, column: 0 });
+        cursor.set_point_range(start..end);
+        let mut scopes = vec![];
+        let text = BufferContentsTextProvider(contents);
+        for query_match in cursor.matches(query, self.tree.root_node(), text) {
+            for capture in query_match.captures {
+                if !names[capture.index as usize].starts_with("definition.") {
+                    continue;
+                }
+                let start = capture.node.start_position();
+                let end = end_line(capture.node.end_position());
+                if start.row <= line && line < end {
+                    scopes.push(start.row..end);
+                }
+            }
+        }
+        scopes.sort_by_key(|scope| (scope.start, std::cmp::Reverse(scope.end)));
+        scopes.dedup();
+        scopes
+    }
+
+    fn matching_bracket(&self, offset: usize) -> Option<usize> {
+        let node = self
+            .tree
+            .root_node()
+            .descendant_for_byte_range(offset, offset + 1)?;
+        if node.start_byte() != offset {
+            return None;
+        }
+        pair(node).map(|pair| pair.start_byte())
+    }
+
+    fn is_literal(&self, offset: usize) -> bool {
+        let root = self.tree.root_node();
+        let mut node = root.descendant_for_byte_range(offset.saturating_sub(1), offset);
+        while let Some(literal) = node {
+            let kind = literal.kind();
+            let (start, end) = (literal.start_byte(), literal.end_byte());
+            // a line comment goes on to the end of its line.
+            if kind.contains("comment") && start < offset && offset <= end {
+                return true;
+            }
+            if (kind.contains("string") || kind == "char_literal") && start < offset && offset < end
+            {
+                return true;
+            }
+            node = literal.parent();
+        }
+        false
+    }
+
+    fn brackets(&self, range: Range<usize>) -> Vec<(usize, usize)> {
+        let mut brackets = vec![];
+        // the nodes overlapping the range, with the depth of the brackets around them. The
+        // brackets of a node pair among its children, and only those that pair count; the text
+        // of strings and comments has no bracket nodes in it.
+        let mut nodes = vec![(self.tree.root_node(), 0usize)];
+        while let Some((node, mut depth)) = nodes.pop() {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                let start = child.start_byte();
+                if start >= range.end {
+                    break;
+                }
+                let opens = BRACKETS.iter().any(|&(open, _)| child.kind() == open);
+                match pair(child) {
+                    Some(_) if opens => {
+                        if range.contains(&start) {
+                            brackets.push((start, depth));
+                        }
+                        depth += 1;
+                    }
+                    Some(_) => {
+                        depth = depth.saturating_sub(1);
+                        if range.contains(&start) {
+                            brackets.push((start, depth));
+                        }
+                    }
+                    None if child.end_byte() > range.start => nodes.push((child, depth)),
+                    None => (),
+                }
+            }
+        }
+        brackets.sort();
+        brackets
+    }
+}
+
+/// The line after the last one a node is on, for the end of a range of lines. A node that ends
+/// with its line ending ends at the start of the next line.
+fn end_line(end: ts::Point) -> usize {
+    match end.column {
+        0 => end.row,
+        _ => end.row + 1,
+    }
+}
+
+/// The byte range from the first node to the last.
+pub(crate) fn hull(nodes: &[ts::Node]) -> Option<Range<usize>> {
+    let start = nodes.iter().map(|node| node.start_byte()).min()?;
+    let end = nodes.iter().map(|node| node.end_byte()).max()?;
+    Some(start..end)
+}
+
+/// The byte range of a node, less the brackets it starts and ends with, as a block's braces.
+fn inside_brackets(node: ts::Node) -> Range<usize> {
+    let count = node.child_count();
+    let (Some(first), Some(last)) = (node.child(0), node.child(count.saturating_sub(1))) else {
+        return node.byte_range();
+    };
+    let bracketed =
+        matches!((first.kind(), last.kind()), ("{", "}") | ("(", ")") | ("[", "]") | ("<", ">"));
+    match bracketed && count > 1 && !first.is_named() && !last.is_named() {
+        true => first.end_byte()..last.start_byte(),
+        false => node.byte_range(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{language, parse};
+    use editor::SyntaxTree;
+
+    fn tree(text: &str) -> (Tree, BufferContents) {
+        let language = language("rust");
+        let (tree, buffer) = parse(&language, text);
+        (Tree::new(tree, &language, &mut QueryCache::default()), buffer.contents)
+    }
+
+    #[test]
+    fn finds_rust_objects() {
+        let text = "fn f(a: u8, b: u8) {\n    g();\n}\n";
+        let (tree, contents) = tree(text);
+        let slice = |range: Option<Range<usize>>| range.map(|range| &text[range]);
+
+        let functions = tree.objects(&contents, SyntaxObject::Function, 0..text.len());
+        assert_eq!(functions.len(), 1);
+        assert_eq!(slice(functions[0].around.clone()), Some(text.trim_end()));
+        assert_eq!(slice(functions[0].inside.clone()), Some("\n    g();\n"));
+
+        let parameters = tree.objects(&contents, SyntaxObject::Parameter, 0..text.len());
+        let parameters: Vec<_> = parameters
+            .into_iter()
+            .map(|parameter| (slice(parameter.inside), slice(parameter.around)))
+            .collect();
+        assert_eq!(
+            parameters,
+            vec![
+                (Some("a: u8"), Some("a: u8,")),
+                (Some("b: u8"), Some("b: u8"))
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_folds() {
+        let text = "// a\n// b\nfn f() {\n    g(\n        1,\n    );\n}\n";
+        let (tree, contents) = tree(text);
+        let folds = tree.folds(&contents).unwrap();
+        assert_eq!(folds, vec![0..2, 2..7, 3..6]);
+    }
+
+    #[test]
+    fn folds_with_predicates() {
+        let text = "fn f() {\n}\nfn g() {\n}\nfn h() {\n}\n";
+        let mut language = language("rust");
+        language.fold_query = r#"((function_item) @fold (#match? @fold "^fn [gh]"))"#.into();
+        let (tree, buffer) = parse(&language, text);
+        let tree = Tree::new(tree, &language, &mut QueryCache::default());
+        // the predicate looks at the text of the functions, and the first one does not match.
+        assert_eq!(tree.folds(&buffer.contents).unwrap(), [2..4, 4..6]);
+    }
+
+    /// The text after breaking a line at a point, as pressing enter does.
+    fn newline(name: &str, text: &str, line: usize, column: usize) -> String {
+        break_line(&language(name), text, line, column)
+    }
+
+    fn break_line(language: &Language, text: &str, line: usize, column: usize) -> String {
+        let (tree, mut buffer) = parse(language, text);
+        buffer.indent = language.indent;
+        let tree = Tree::new(tree, language, &mut QueryCache::default());
+        buffer.syntax = Some(Box::new(tree));
+        let mut editor = editor::Editor::new(editor::EditorId::default(), buffer.id);
+        editor.set_mode(&mut buffer, editor::Mode::Insert);
+        editor.cursor = editor::Point { line, column };
+        editor.insert_newline(&mut buffer);
+        buffer.contents.to_string()
+    }
+
+    #[test]
+    fn indents_new_lines() {
+        let text = "fn f() {\n    g(\n        1,\n    );\n}\n";
+        assert_eq!(newline("rust", text, 0, 8), "fn f() {\n    \n    g(\n        1,\n    );\n}\n");
+        assert_eq!(
+            newline("rust", text, 1, 6),
+            "fn f() {\n    g(\n        \n        1,\n    );\n}\n"
+        );
+        assert_eq!(newline("rust", text, 3, 6), "fn f() {\n    g(\n        1,\n    );\n    \n}\n");
+        assert_eq!(newline("rust", text, 4, 1), "fn f() {\n    g(\n        1,\n    );\n}\n\n");
+        // the closing bracket goes back out to the level of the line that opened it.
+        assert_eq!(newline("rust", "fn f() {}", 0, 8), "fn f() {\n}");
+    }
+
+    #[test]
+    fn indents_with_predicates() {
+        let mut language = language("rust");
+        language.indent_query = r#"((call_expression) @indent (#match? @indent "^g"))"#.into();
+        let text = "fn f() {\n    g(1);\n    h(2);\n}\n";
+        // the predicate looks at the text of the calls, and only the first one indents.
+        let broken = break_line(&language, text, 1, 6);
+        assert_eq!(broken, "fn f() {\n    g(\n        1);\n    h(2);\n}\n");
+        let broken = break_line(&language, text, 2, 6);
+        assert_eq!(broken, "fn f() {\n    g(1);\n    h(\n    2);\n}\n");
+    }
+
+    #[test]
+    #[cfg(feature = "python")]
+    fn indents_python_blocks() {
+        let text = "def f():\n    x\n";
+        assert_eq!(newline("python", text, 1, 5), "def f():\n    x\n    \n");
+    }
+
+    #[test]
+    fn finds_scopes() {
+        let text =
+            "mod m {\n    impl S {\n        fn f() {\n            g();\n        }\n    }\n}\n";
+        let (tree, contents) = tree(text);
+        let scopes = [0..7, 1..6, 2..5];
+        assert_eq!(tree.scopes(&contents, 3), scopes);
+        assert_eq!(tree.scopes(&contents, 5), scopes[..2]);
+        assert!(tree.scopes(&contents, 7).is_empty());
+    }
+
+    #[test]
+    fn finds_scopes_with_predicates() {
+        let text = concat!(
+            "mod m {\n    fn f() {\n        g();\n    }\n",
+            "    fn test_f() {\n        h();\n    }\n}\n",
+        );
+        let mut language = language("rust");
+        language.tag_query = concat!(
+            "(mod_item) @definition.module\n",
+            "((function_item name: (identifier) @name) @definition.function",
+            r#" (#not-match? @name "^test_"))"#,
+        )
+        .into();
+        let (tree, buffer) = parse(&language, text);
+        let tree = Tree::new(tree, &language, &mut QueryCache::default());
+        let scopes = tree.scopes(&buffer.contents, 2);
+        assert_eq!(scopes, [0..8, 1..4]);
+        // the predicate looks at the names, and leaves the test out.
+        assert_eq!(tree.scopes(&buffer.contents, 5), scopes[..1]);
+    }
+
+    #[test]
+    fn pairs_brackets() {
+        let text = "fn f(a: Vec<u8>) {\n    g(\")\", b);\n}\n";
+        let (tree, _) = tree(text);
+        assert_eq!(tree.matching_bracket(4), Some(15));
+        assert_eq!(tree.matching_bracket(11), Some(14));
+        assert_eq!(tree.matching_bracket(17), Some(34));
+        // the bracket in the string is not paired, nor does it pair the call's.
+        assert_eq!(tree.matching_bracket(24), Some(31));
+        assert_eq!(tree.matching_bracket(26), None);
+        assert!(tree.is_literal(26) && !tree.is_literal(25) && !tree.is_literal(28));
+    }
+
+    #[test]
+    fn keeps_the_tree_through_edits() {
+        let text = "fn f() { g(\")\"); }\n";
+        let (tree, contents) = tree(text);
+        let mut buffer = editor::Buffer::empty(editor::BufferId::default());
+        buffer.insert(0, &contents.to_string());
+        buffer.syntax = Some(Box::new(tree));
+        buffer.insert(0, "\n");
+        // the bracket in the string is still told apart, until the buffer is parsed again.
+        assert_eq!(buffer.matching_bracket(11), Some(15));
+    }
+
+    #[test]
+    fn nests_brackets() {
+        let text = "fn f() {\n    g(\"(\", [h(a)]);\n}\n";
+        let (tree, _) = tree(text);
+        let depths = [
+            (4, 0),
+            (5, 0),
+            (7, 0),
+            (14, 1),
+            (20, 2),
+            (22, 3),
+            (24, 3),
+            (25, 2),
+        ];
+        let expected = [&depths[..], &[(26, 1), (29, 0)]].concat();
+        assert_eq!(tree.brackets(0..text.len()), expected);
+        // the depth of a bracket does not depend on where the range starts.
+        assert_eq!(tree.brackets(19..25), [(20, 2), (22, 3), (24, 3)]);
+    }
+
+    #[test]
+    fn nests_angle_brackets_that_pair() {
+        let text = "fn f(a: Vec<u8>) -> bool { a.len() < 2 }\n";
+        let (tree, _) = tree(text);
+        let brackets = tree.brackets(0..text.len());
+        assert!(brackets.contains(&(11, 1)) && brackets.contains(&(14, 1)));
+        // the comparison is not a bracket.
+        assert!(!brackets.iter().any(|(offset, _)| *offset == 35));
+    }
+
+    #[test]
+    fn finds_siblings() {
+        let text = "fn a() {}\n\nstruct B;\n\nfn c() {}\n";
+        let (tree, _) = tree(text);
+        assert_eq!(tree.sibling(0, true), Some(11));
+        assert_eq!(tree.sibling(11, true), Some(22));
+        assert_eq!(tree.sibling(22, false), Some(11));
+        // from the blank line between items.
+        assert_eq!(tree.sibling(10, true), Some(11));
+        assert_eq!(tree.sibling(0, false), None);
+    }
+}
@@ -1,21 +1,25 @@
 use anyhow::Result;
 use crossterm::cursor::{self, SetCursorStyle};
 use crossterm::event::{Event, EventStream, KeyEvent};
-use futures::{Future, FutureExt};
 use ratatui::backend::CrosstermBackend;
 use ratatui::prelude as tui;
 use slotmap::{new_key_type, SecondaryMap, SlotMap};
 use std::io::Stdout;
-use std::pin::Pin;
-use std::sync::Arc;
+use std::ops::Range;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
 use tree_sitter as ts;
 
-use editor::{Buffer, BufferCommand, BufferId, Editor, EditorCommand, EditorId};
-use selector::Selector;
+use editor::{Buffer, BufferCommand, BufferId, Editor, EditorCommand, EditorId, Parsed};
 use syntax::Syntax;
 use tore::CursorPoint;
+use ui::Message;
+
+use crate::commands::{CommandId, CommandRegistry};
+use crate::expand::ExpandHistory;
+use crate::registers::{Clipboard, Registers};
+use crate::search::{Confirm, SearchCommand, SearchPrompt};
+use crate::symbols::SymbolPicker;
 
 type BufferMap = SlotMap<BufferId, Buffer>;
 type EditorMap = SlotMap<EditorId, Editor>;
@@ -31,12 +35,45 @@ pub enum PaneCommand {
 #[derive(Debug, Clone)]
 pub enum Command {
     Quit,
-    FileOpen(Option<EditorId>, std::path::PathBuf),
+    ForceQuit,
+    FileOpen(Option<EditorId>, PathBuf),
+    FileWrite(Option<EditorId>),
+    FileWriteAs(Option<EditorId>, PathBuf),
+    FileWriteQuit(Option<EditorId>),
+    /// Sets the language of the editor's buffer by name, or makes it plain text.
+    SetLanguage(Option<EditorId>, String),
+    /// Grows the editor's selections to the syntax nodes around them.
+    ExpandSelection(Option<EditorId>),
+    /// Shrinks the editor's selections back to what they were before they were expanded.
+    ShrinkSelection(Option<EditorId>),
+    /// Lists the definitions in the editor's buffer, to go to one.
+    PickSymbol(Option<EditorId>),
     Pane(PaneId, PaneCommand),
     Buffer(BufferId, BufferCommand),
     Editor(EditorId, EditorCommand),
+    /// An editor command whose yanked or deleted text goes to a named register.
+    EditorRegister(EditorId, char, EditorCommand),
     FocusedEditor(EditorCommand),
     Commands(selector::Command<CommandId>),
+    Symbols(selector::Command<usize>),
+    Search(SearchCommand),
+    /// An ex-style substitute command, such as `%s/pattern/replacement/g`.
+    Substitute(String),
+    ConfirmSubstitution(Confirm),
+    /// A message from work finished in the background, such as reading the clipboard.
+    Message(Message),
+}
+
+impl Command {
+    /// Fills in the arguments typed after a command's name in the command palette.
+    pub fn with_args(self, args: &str) -> Command {
+        match self {
+            Command::FileWriteAs(editor_id, _) => Command::FileWriteAs(editor_id, args.into()),
+            Command::Substitute(_) => Command::Substitute(format!("s{}", args)),
+            Command::SetLanguage(editor_id, _) => Command::SetLanguage(editor_id, args.into()),
+            command => command,
+        }
+    }
 }
 
 new_key_type! {
@@ -46,6 +83,8 @@ new_key_type! {
 #[derive(Debug, Clone)]
 pub enum Pane {
     Commands(PaneId),
+    Search(PaneId),
+    Symbols(PaneId),
     Editor(PaneId, EditorId),
 }
 
@@ -53,6 +92,8 @@ impl Pane {
     fn id(&self) -> PaneId {
         match self {
             Pane::Commands(id, ..) => *id,
+            Pane::Search(id) => *id,
+            Pane::Symbols(id) => *id,
             Pane::Editor(id, ..) => *id,
         }
     }
@@ -68,129 +109,13 @@ impl Pane {
     fn new_commands(id: PaneId) -> Self {
         Pane::Commands(id)
     }
-}
-
-new_key_type! {
-    pub struct CommandId;
-}
-
-#[derive(Debug)]
-struct Entry {
-    name: &'static str,
-    aliases: Vec<&'static str>,
-    command: Command,
-}
-
-#[derive(Debug)]
-struct CommandRegistry {
-    entries: SlotMap<CommandId, Entry>,
-    selector: Selector<CommandId>,
-    // filtered: Vec<CommandId>,
-}
-
-impl CommandRegistry {
-    fn new() -> Self {
-        let selector = Selector::new(":");
-        let entries = SlotMap::with_key();
-        Self { entries, selector }
-    }
-
-    // fn focused(&self) -> Option<Command> {
-    //     self.selector
-    //         .focused
-    //         .map(|id| self.entries[id].command.clone())
-    // }
 
-    fn register(
-        &mut self,
-        name: &'static str,
-        aliases: Vec<&'static str>,
-        command: Command,
-    ) -> CommandId {
-        self.entries.insert(Entry { name, aliases, command })
-    }
-
-    async fn update(&mut self, query: &str) {
-    }
-
-    // async fn update(&mut self, query: &str) {
-    //     tokio::spawn(async move {
-    //         let mut results = vec![];
-    //         // if self.selector.query.is_empty() {
-    //         // for (id, _) in &self.entries {
-    //         //     // todo: handle max results
-    //         //     if results.len() > 32 {
-    //         //         break;
-    //         //     }
-    //         //     results.push(id);
-    //         // }
-    //         // } else {
-    //         //     let matcher = SkimMatcherV2::default();
-    //         //     for (id, entry) in &self.entries {
-    //         //         let result = matcher.fuzzy_indices(&entry.name, &self.query);
-    //         //         if let Some((score, indices)) = result {
-    //         //             results.push(id)
-    //         //         }
-    //         //     }
-    //         //     // results.sort_by_key(|entry| entry.score);
-    //         // }
-
-    //         // self.selected = results.first().map(|r| r.entry);
-    //         // self.filtered = results;
-    //         results
-    //     });
-    // }
-
-    // fn results(&self) -> Vec<CommandId> {
-    //     let mut results = vec![];
-    //     // if self.selector.query.is_empty() {
-    //     for (id, _) in &self.entries {
-    //         // todo: handle max results
-    //         if results.len() > 32 {
-    //             break;
-    //         }
-    //         results.push(id);
-    //     }
-    //     // } else {
-    //     //     let matcher = SkimMatcherV2::default();
-    //     //     for (id, entry) in &self.entries {
-    //     //         let result = matcher.fuzzy_indices(&entry.name, &self.query);
-    //     //         if let Some((score, indices)) = result {
-    //     //             results.push(id)
-    //     //         }
-    //     //     }
-    //     //     // results.sort_by_key(|entry| entry.score);
-    //     // }
-
-    //     // self.selected = results.first().map(|r| r.entry);
-    //     // self.filtered = results;
-    //     results
-    // }
-
-    fn render(
-        &self,
-        buf: &mut tui::Buffer,
-        area: tui::Rect,
-        theme: &ui::Theme,
-    ) -> (CursorPoint, SetCursorStyle) {
-        let widget = ui::SelectorPane::new(theme, &self.selector);
-        widget.render(buf, area, &self.selector.entries, |area, buf, id| {
-            self.render_result(area, buf, id)
-        })
+    fn new_search(id: PaneId) -> Self {
+        Pane::Search(id)
     }
 
-    fn render_result(&self, area: tui::Rect, buf: &mut tui::Buffer, id: CommandId) {
-        use bstr::ByteSlice;
-        let entry = &self.entries[id];
-        let content = entry.name;
-        let mut graphemes = content.as_bytes().as_bstr().graphemes();
-        for (idx, x) in (area.left()..area.right()).enumerate() {
-            let symbol = graphemes.next().unwrap_or(" ");
-            let style = tui::Style::reset();
-            buf.get_mut(x, area.top())
-                .set_style(style)
-                .set_symbol(symbol);
-        }
+    fn new_symbols(id: PaneId) -> Self {
+        Pane::Symbols(id)
     }
 }
 
@@ -200,7 +125,20 @@ struct State {
 
     buffers: BufferMap,
     editors: EditorMap,
+    languages: syntax::LanguageRegistry,
+    /// The language of each buffer that is not plain text.
+    buffer_languages: SecondaryMap<BufferId, syntax::Language>,
     syntax_trees: SyntaxTreeMap,
+    /// The queries the syntax trees of buffers are searched with.
+    queries: syntax::QueryCache,
+    /// The revision each buffer's syntax tree was parsed at. Edits are applied to the stored tree
+    /// straight away, but it only describes the buffer correctly again once it is reparsed.
+    parsed_revisions: SecondaryMap<BufferId, usize>,
+    /// How each editor's selections were expanded through the syntax tree.
+    expand_histories: SecondaryMap<EditorId, ExpandHistory>,
+    /// The highlights last requested for each buffer and the revision they were requested at,
+    /// so that the same request is not sent again while it is in flight.
+    requested_highlights: SecondaryMap<BufferId, (usize, Vec<Range<usize>>)>,
 
     panes: PaneMap,
     visible_panes: Vec<PaneId>,
@@ -209,10 +147,34 @@ struct State {
     default_editor_id: EditorId,
 
     command_registry: CommandRegistry,
+    commands_pane_id: PaneId,
+
+    search_prompt: SearchPrompt,
+    search_pane_id: PaneId,
+
+    symbol_picker: SymbolPicker,
+    symbols_pane_id: PaneId,
+    /// The last search, whose matches are highlighted while `highlight_search` is set.
+    search: Option<editor::Search>,
+    highlight_search: bool,
+    /// A substitute command waiting for each of its matches to be confirmed.
+    substitution: Option<(EditorId, editor::Substitution)>,
+
+    registers: Registers,
+    message: Option<Message>,
+    /// Where work done in the background sends its results.
+    cmd_tx: mpsc::Sender<Command>,
+    /// What editor panes draw over the text of their buffers.
+    decorations: ui::Decorations,
 }
 
 impl State {
-    fn new() -> Self {
+    fn new(
+        cmd_tx: mpsc::Sender<Command>,
+        clipboard: Clipboard,
+        languages: syntax::LanguageRegistry,
+        decorations: ui::Decorations,
+    ) -> Self {
         let theme = ui::Theme::default();
         let syntax_trees = SecondaryMap::new();
         // let commands = Selector::new(":");
@@ -239,18 +201,37 @@ impl State {
         let mut command_registry = CommandRegistry::new();
         register_commands(&mut command_registry);
         let commands_pane_id = panes.insert_with_key(Pane::new_commands);
+        let search_pane_id = panes.insert_with_key(Pane::new_search);
+        let symbols_pane_id = panes.insert_with_key(Pane::new_symbols);
 
         State {
             theme,
             buffers,
             editors,
+            languages,
+            buffer_languages: SecondaryMap::new(),
             syntax_trees,
+            queries: syntax::QueryCache::default(),
+            parsed_revisions: SecondaryMap::new(),
+            expand_histories: SecondaryMap::new(),
+            requested_highlights: SecondaryMap::new(),
             panes,
             visible_panes,
             focused_pane,
             default_editor_id,
             command_registry,
             commands_pane_id,
+            search_prompt: SearchPrompt::new(),
+            search_pane_id,
+            symbol_picker: SymbolPicker::new(),
+            symbols_pane_id,
+            search: None,
+            highlight_search: false,
+            substitution: None,
+            registers: Registers::new(clipboard),
+            message: None,
+            cmd_tx,
+            decorations,
         }
     }
 
@@ -263,6 +244,102 @@ impl State {
         pane.clone()
     }
 
+    /// The editor that commands without an explicit target apply to: the focused editor pane, or
+    /// the editor pane underneath the focused command palette, search prompt or symbol picker.
+    fn focused_editor_id(&self) -> EditorId {
+        let pane_id = match self.focused_pane() {
+            Pane::Commands(..) | Pane::Search(..) | Pane::Symbols(..) => {
+                if let [.., pane_id, _] = self.visible_panes[..] {
+                    pane_id
+                } else {
+                    unreachable!("no visible panes")
+                }
+            }
+            Pane::Editor(..) => self.focused_pane,
+        };
+        match self.panes[pane_id] {
+            Pane::Commands(..) | Pane::Search(..) | Pane::Symbols(..) => {
+                unreachable!("focused pane is not an editor")
+            }
+            Pane::Editor(_, editor_id) => editor_id,
+        }
+    }
+
+    /// Runs an editor command, keeping any text it yanks or deletes in the registers.
+    fn editor_command(&mut self, editor_id: EditorId, register: Option<char>, cmd: EditorCommand) {
+        let yanked = matches!(
+            cmd,
+            EditorCommand::Operate(editor::Operator::Yank, ..)
+                | EditorCommand::OperateSelection(editor::Operator::Yank)
+        );
+        let editor = &mut self.editors[editor_id];
+        let buffer = &mut self.buffers[editor.buffer_id];
+        let (buffer_id, edits) = (buffer.id, buffer.edits().len());
+        let yank = editor.command(buffer, cmd);
+        self.follow_edits(buffer_id, edits, Some(editor_id));
+        if let Some(yank) = yank {
+            if let Err(e) = self.registers.store(register, yank, yanked) {
+                self.message = Some(Message::Error(format!("clipboard: {:#}", e)));
+            }
+        }
+    }
+
+    /// Moves the folds of the editors on a buffer along with the edits made to it after the
+    /// first `seen`, but for the editor whose command made them, which moved its own.
+    fn follow_edits(&mut self, buffer_id: BufferId, seen: usize, by: Option<EditorId>) {
+        let edits = &self.buffers[buffer_id].edits()[seen..];
+        for (editor_id, editor) in self.editors.iter_mut() {
+            if editor.buffer_id == buffer_id && Some(editor_id) != by {
+                editor.follow_edits(edits);
+            }
+        }
+    }
+
+    /// Expands or shrinks an editor's selections through its buffer's syntax tree.
+    fn resize_selections(&mut self, editor_id: EditorId, expand: bool) {
+        let editor = &mut self.editors[editor_id];
+        let buffer = &mut self.buffers[editor.buffer_id];
+        let Some(tree) = self.syntax_trees.get(buffer.id) else {
+            self.message = Some(Message::Error("no syntax tree for this buffer".into()));
+            return;
+        };
+        if !self.expand_histories.contains_key(editor_id) {
+            self.expand_histories
+                .insert(editor_id, ExpandHistory::default());
+        }
+        let history = &mut self.expand_histories[editor_id];
+        match expand {
+            true => history.expand(editor, buffer, tree),
+            false => history.shrink(editor, buffer, tree),
+        }
+    }
+
+    /// Opens the symbol picker on the definitions in an editor's buffer.
+    fn pick_symbol(&mut self, editor_id: EditorId) {
+        let buffer = &self.buffers[self.editors[editor_id].buffer_id];
+        let language = self.buffer_languages.get(buffer.id);
+        let (Some(language), Some(tree)) = (language, self.syntax_trees.get(buffer.id)) else {
+            self.message = Some(Message::Error("no syntax tree for this buffer".into()));
+            return;
+        };
+        let symbols = syntax::symbols(language, tree, &buffer.contents, &mut self.queries);
+        if symbols.is_empty() {
+            self.message = Some(Message::Error("no symbols in this buffer".into()));
+            return;
+        }
+        self.symbol_picker.open(editor_id, symbols);
+        self.focus_pane(self.symbols_pane_id);
+    }
+
+    async fn write_buffer(&mut self, editor_id: EditorId, path: Option<PathBuf>) -> Result<()> {
+        let buffer = &mut self.buffers[self.editors[editor_id].buffer_id];
+        match path {
+            None => buffer.write().await?,
+            Some(path) => buffer.save_as(path).await?,
+        };
+        Ok(())
+    }
+
     fn close_focused_pane(&mut self) {
         let pane_id = self.visible_panes.pop();
         debug_assert_eq!(pane_id, Some(self.focused_pane));
@@ -287,7 +364,7 @@ impl State {
     fn draw_frame(&self, frame: &mut ratatui::Frame) -> Option<(CursorPoint, SetCursorStyle)> {
         let mut cursor: Option<(CursorPoint, SetCursorStyle)> = None;
 
-        let area = frame.size();
+        let (area, status_area) = ui::StatusLine::split(frame.size());
         let fb = frame.buffer_mut();
         for pane_id in self.visible_panes.iter() {
             let pane = self.panes.get(*pane_id).expect("pane not found");
@@ -299,16 +376,64 @@ impl State {
                 Pane::Editor(pane_id, editor_id) => {
                     let editor = &self.editors[*editor_id];
                     let buffer = &self.buffers[editor.buffer_id];
-                    let widget = ui::EditorPane::new(&self.theme, buffer, editor);
+                    let search = self.search.as_ref().filter(|_| self.highlight_search);
+                    let widget = ui::EditorPane::new(&self.theme, buffer, editor, search)
+                        .decorations(self.decorations);
                     let c = widget.render(fb, area);
                     (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
                 }
+                Pane::Symbols(pane_id) => {
+                    let c = self.symbol_picker.render(fb, area, &self.theme);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Search(pane_id) => {
+                    // the prompt takes the place of the status line.
+                    let prompt_area = status_area.unwrap_or(area);
+                    let widget = ui::Prompt::new(&self.theme, &self.search_prompt.selector);
+                    let c = widget.render(fb, prompt_area);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
             }
         }
 
+        let prompt_open = self.visible_panes.contains(&self.search_pane_id);
+        if let Some(status_area) = status_area.filter(|_| !prompt_open) {
+            let editor = &self.editors[self.focused_editor_id()];
+            let buffer = &self.buffers[editor.buffer_id];
+            let widget = ui::StatusLine::new(&self.theme, buffer, editor, self.message.as_ref());
+            widget.render(fb, status_area);
+        }
+
         cursor
     }
 
+    /// The byte ranges that visible editor panes show without highlights and that have not
+    /// already been requested, by buffer.
+    fn missing_highlights(&mut self, size: tui::Rect) -> Vec<(BufferId, Vec<Range<usize>>)> {
+        let (area, _) = ui::StatusLine::split(size);
+        let mut missing = vec![];
+        for pane_id in self.visible_panes.iter() {
+            let Pane::Editor(_, editor_id) = self.panes[*pane_id] else {
+                continue;
+            };
+            let editor = &self.editors[editor_id];
+            let buffer = &self.buffers[editor.buffer_id];
+            if self.parsed_revisions.get(buffer.id) != Some(&buffer.revision()) {
+                continue;
+            }
+            let ranges = ui::EditorPane::new(&self.theme, buffer, editor, None)
+                .decorations(self.decorations)
+                .missing_highlights(area);
+            let request = (buffer.revision(), ranges);
+            if request.1.is_empty() || self.requested_highlights.get(buffer.id) == Some(&request) {
+                continue;
+            }
+            missing.push((buffer.id, request.1.clone()));
+            self.requested_highlights.insert(buffer.id, request);
+        }
+        missing
+    }
+
     #[tracing::instrument(skip(ev, self))]
     fn process_event(&mut self, ev: Event) -> Option<Command> {
         match ev {
@@ -318,8 +443,8 @@ impl State {
             Event::Mouse(_) => todo!(),
             Event::Resize(_, _) => None,
             Event::Key(key) => {
-                let command = self.process_key(key);
-                command
+                self.message = None;
+                self.process_key(key)
             }
         }
     }
@@ -327,64 +452,160 @@ impl State {
     fn process_key(&mut self, key: KeyEvent) -> Option<Command> {
         use crossterm::event::{KeyCode, KeyModifiers};
 
+        if self.substitution.is_some() {
+            let confirm = match key.code {
+                KeyCode::Esc => Some(Confirm::Quit),
+                KeyCode::Char(c) => Confirm::from_key(c),
+                _ => None,
+            };
+            return confirm.map(Command::ConfirmSubstitution);
+        }
+
         let focused_pane = self
             .panes
             .get_mut(self.focused_pane)
             .expect("focused pane does not exist");
 
         match focused_pane {
-            Pane::Commands(_) => match key.code {
-                KeyCode::Up => {
-                    Some(Command::Commands(selector::Command::Focus(selector::Direction::Prev)))
-                }
-                KeyCode::Down => {
-                    Some(Command::Commands(selector::Command::Focus(selector::Direction::Next)))
+            Pane::Commands(pane_id) => match key.code {
+                KeyCode::Esc => Some(Command::Pane(*pane_id, PaneCommand::Close)),
+                KeyCode::Enter => {
+                    let command = self.command_registry.focused();
+                    self.close_focused_pane();
+                    command
                 }
-                KeyCode::Backspace => {
-                    Some(Command::Commands(selector::Command::Delete(selector::Direction::Prev)))
+                _ => picker_command(key).map(Command::Commands),
+            },
+            Pane::Symbols(pane_id) => match key.code {
+                KeyCode::Esc => Some(Command::Pane(*pane_id, PaneCommand::Close)),
+                KeyCode::Enter => {
+                    let point = self.symbol_picker.focused();
+                    self.close_focused_pane();
+                    let editor_id = self.symbol_picker.editor_id?;
+                    Some(Command::Editor(editor_id, EditorCommand::JumpTo(point?)))
                 }
-                KeyCode::Enter => self.command_registry.focused(),
-                KeyCode::Char(c) => {
-                    let ctrl = key.modifiers == KeyModifiers::CONTROL;
-                    if ctrl && c == 'p' {
-                        Some(Command::Commands(selector::Command::Focus(selector::Direction::Prev)))
-                    } else if ctrl && c == 'n' {
-                        Some(Command::Commands(selector::Command::Focus(selector::Direction::Next)))
-                    } else {
-                        Some(Command::Commands(selector::Command::Insert(c)))
+                _ => picker_command(key).map(Command::Symbols),
+            },
+            Pane::Search(_) => {
+                let edit = |cmd| Some(Command::Search(SearchCommand::Edit(cmd)));
+                match key.code {
+                    KeyCode::Esc => Some(Command::Search(SearchCommand::Cancel)),
+                    KeyCode::Enter => Some(Command::Search(SearchCommand::Commit)),
+                    KeyCode::Backspace if self.search_prompt.selector.query.is_empty() => {
+                        Some(Command::Search(SearchCommand::Cancel))
                     }
+                    KeyCode::Backspace => {
+                        edit(selector::Command::Delete(selector::Direction::Prev))
+                    }
+                    KeyCode::Delete => edit(selector::Command::Delete(selector::Direction::Next)),
+                    KeyCode::Char(c) => edit(selector::Command::Insert(c)),
+                    _ => None,
                 }
-                _ => None,
-            },
+            }
             Pane::Editor(_, editor_id) => {
-                let editor = &mut self.editors[*editor_id];
+                let editor_id = *editor_id;
+                let editor = &mut self.editors[editor_id];
+                let mode = editor.mode.clone();
+                // keys outside the grammar of typed commands, such as arrows, drop the one pending.
+                let typed = matches!(key.code, KeyCode::Char(_))
+                    && !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                if !typed {
+                    editor.pending.reset();
+                }
+                // `v`, `V` and ctrl-v enter a visual mode, or leave it if already in it.
+                let toggle = |visual: editor::Mode| {
+                    let mode = if mode == visual {
+                        editor::Mode::Normal
+                    } else {
+                        visual
+                    };
+                    Some(EditorCommand::SetMode(mode))
+                };
                 let command = match editor.mode {
-                    editor::Mode::Normal => match key.code {
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Up))
-                        }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Down))
+                    editor::Mode::Normal
+                    | editor::Mode::Visual
+                    | editor::Mode::VisualLine
+                    | editor::Mode::VisualBlock => match key.code {
+                        KeyCode::Esc => match mode.is_visual() {
+                            true => Some(EditorCommand::SetMode(editor::Mode::Normal)),
+                            false => Some(EditorCommand::CollapseSelections),
+                        },
+                        KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => {
+                            Some(EditorCommand::AddCursorAtNextMatch)
                         }
-                        KeyCode::Left | KeyCode::Char('h') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Left))
+                        KeyCode::Char('s') if key.modifiers == KeyModifiers::ALT => {
+                            Some(EditorCommand::SplitSelectionLines)
                         }
-                        KeyCode::Right | KeyCode::Char('l') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Right))
+                        KeyCode::Char('o') if key.modifiers == KeyModifiers::ALT => {
+                            return Some(Command::ExpandSelection(Some(editor_id)));
                         }
-                        KeyCode::Char('w') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNextWord))
+                        KeyCode::Char('i') if key.modifiers == KeyModifiers::ALT => {
+                            return Some(Command::ShrinkSelection(Some(editor_id)));
                         }
-                        KeyCode::Char('e') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::EndOfNearestWord))
+                        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            let parsed = match mode.is_visual() {
+                                true => editor.pending.feed_visual(c),
+                                false => editor.pending.feed(c),
+                            };
+                            match parsed {
+                                Parsed::Complete(command, None) => Some(command),
+                                Parsed::Complete(command, Some(register)) => {
+                                    return Some(Command::EditorRegister(
+                                        editor_id, register, command,
+                                    ));
+                                }
+                                Parsed::Put(register, placement, count) => {
+                                    self.put_command(editor_id, register, placement, count)
+                                }
+                                Parsed::Pending | Parsed::Cancelled => None,
+                                Parsed::Refused(keys) => {
+                                    let msg =
+                                        format!("{}: inserts take no count or register", keys);
+                                    self.message = Some(Message::Error(msg));
+                                    None
+                                }
+                                Parsed::Unhandled => match c {
+                                    'i' if !mode.is_visual() => {
+                                        Some(EditorCommand::SetMode(editor::Mode::Insert))
+                                    }
+                                    'u' if !mode.is_visual() => Some(EditorCommand::Undo),
+                                    'o' if mode.is_visual() => Some(EditorCommand::SwapAnchor),
+                                    'o' => Some(EditorCommand::OpenLine(editor::Placement::After)),
+                                    'O' if !mode.is_visual() => {
+                                        Some(EditorCommand::OpenLine(editor::Placement::Before))
+                                    }
+                                    'v' => toggle(editor::Mode::Visual),
+                                    'V' => toggle(editor::Mode::VisualLine),
+                                    ':' => {
+                                        let pane_id = self.commands_pane_id;
+                                        return Some(Command::Pane(pane_id, PaneCommand::Open));
+                                    }
+                                    '/' | '?' => {
+                                        let backward = c == '?';
+                                        let cmd = SearchCommand::Open { backward };
+                                        return Some(Command::Search(cmd));
+                                    }
+                                    'n' | 'N' => {
+                                        let reverse = c == 'N';
+                                        let cmd = SearchCommand::Repeat { reverse };
+                                        return Some(Command::Search(cmd));
+                                    }
+                                    _ => None,
+                                },
+                            }
                         }
-                        KeyCode::Char('b') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNearestWord))
+                        KeyCode::Char('v') if key.modifiers == KeyModifiers::CONTROL => {
+                            toggle(editor::Mode::VisualBlock)
                         }
-                        KeyCode::Char('0') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNearestWord))
+                        KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                            Some(EditorCommand::Redo)
                         }
-                        KeyCode::Char('i') => Some(EditorCommand::SetMode(editor::Mode::Insert)),
+                        KeyCode::Up => Some(EditorCommand::CursorMove(editor::Direction::Up)),
+                        KeyCode::Down => Some(EditorCommand::CursorMove(editor::Direction::Down)),
+                        KeyCode::Left => Some(EditorCommand::CursorMove(editor::Direction::Left)),
+                        KeyCode::Right => Some(EditorCommand::CursorMove(editor::Direction::Right)),
                         _ => None,
                     },
                     editor::Mode::Insert => match key.code {
@@ -393,71 +614,269 @@ impl State {
                         KeyCode::Down => Some(EditorCommand::CursorMove(editor::Direction::Down)),
                         KeyCode::Left => Some(EditorCommand::CursorMove(editor::Direction::Left)),
                         KeyCode::Right => Some(EditorCommand::CursorMove(editor::Direction::Right)),
+                        KeyCode::Enter => Some(EditorCommand::InsertNewline),
+                        KeyCode::Tab => Some(EditorCommand::InsertTab),
+                        KeyCode::Backspace => Some(EditorCommand::DeleteBackward),
+                        KeyCode::Delete => Some(EditorCommand::DeleteForward),
                         KeyCode::Char(c) => Some(EditorCommand::InsertChar(c)),
                         _ => None,
                     },
                 };
-                command
-                    .map(|c| Command::Editor(*editor_id, c))
-                    .or_else(|| match editor.mode {
-                        editor::Mode::Normal => match key.code {
-                            KeyCode::Char(':') => {
-                                Some(Command::Pane(self.commands_pane_id, PaneCommand::Open))
-                            }
-                            _ => None,
-                        },
-                        _ => None,
-                    })
+                command.map(|c| Command::Editor(editor_id, c))
             }
         }
     }
 
-    fn process_syntax(&mut self, ev: syntax::Event) -> Option<Command> {
-        match ev {
-            syntax::Event::Hightlight(buffer_id, hls) => {
-                Some(Command::Buffer(buffer_id, BufferCommand::Highlight(hls)))
-            }
-            syntax::Event::Parsed(buffer_id, tree) => {
-                self.syntax_trees.insert(buffer_id, tree);
+    /// The command to put a register's contents, or `None` with an error shown if it is empty.
+    /// The clipboard is read in the background, and its text put by a command sent once it is.
+    fn put_command(
+        &mut self,
+        editor_id: EditorId,
+        register: Option<char>,
+        placement: editor::Placement,
+        count: usize,
+    ) -> Option<EditorCommand> {
+        if self.registers.is_pasted(register) {
+            let cmd_tx = self.cmd_tx.clone();
+            self.registers.paste(move |yank| {
+                let command = match yank {
+                    Ok(yank) => {
+                        Command::Editor(editor_id, EditorCommand::Put(yank, placement, count))
+                    }
+                    Err(e) => Command::Message(Message::Error(format!("clipboard: {:#}", e))),
+                };
+                // the app has quit if the command cannot be sent.
+                let _ = cmd_tx.blocking_send(command);
+            });
+            return None;
+        }
+        match self.registers.get(register) {
+            Some(yank) => Some(EditorCommand::Put(yank, placement, count)),
+            None => {
+                let msg = format!("register {} is empty", register.unwrap_or('"'));
+                self.message = Some(Message::Error(msg));
                 None
             }
         }
     }
-}
 
-struct BackgroundExecutor(tokio::runtime::Handle);
+    fn search_command(&mut self, cmd: SearchCommand) {
+        let editor_id = self.focused_editor_id();
+        match cmd {
+            SearchCommand::Open { backward } => {
+                let origin = self.editors[editor_id].cursor;
+                self.search_prompt
+                    .open(backward, origin, self.search.clone());
+                self.focus_pane(self.search_pane_id);
+            }
+            SearchCommand::Edit(cmd) => {
+                self.search_prompt.selector.command(cmd);
+                self.incremental_search(editor_id);
+            }
+            SearchCommand::Commit => {
+                self.close_focused_pane();
+                let prompt = &self.search_prompt;
+                // an empty pattern searches for the last one again.
+                let search = match prompt.selector.query.as_str() {
+                    "" => prompt.previous.clone().map(|mut search| {
+                        search.backward = prompt.backward;
+                        search
+                    }),
+                    query => editor::Search::new(query, prompt.backward).ok(),
+                };
+                self.editors[editor_id].cursor = self.search_prompt.origin;
+                match search {
+                    Some(search) => {
+                        self.search = Some(search);
+                        self.highlight_search = true;
+                        self.search_next(editor_id, self.search_prompt.origin, false);
+                    }
+                    None => {
+                        self.search = self.search_prompt.previous.take();
+                        let msg = match self.search_prompt.selector.query.as_str() {
+                            "" => "no previous search pattern".into(),
+                            query => format!("invalid pattern: {}", query),
+                        };
+                        self.message = Some(Message::Error(msg));
+                    }
+                }
+            }
+            SearchCommand::Cancel => {
+                self.close_focused_pane();
+                self.editors[editor_id].cursor = self.search_prompt.origin;
+                self.search = self.search_prompt.previous.take();
+            }
+            SearchCommand::Repeat { reverse } => {
+                self.highlight_search = true;
+                let from = self.editors[editor_id].cursor;
+                self.search_next(editor_id, from, reverse);
+            }
+            SearchCommand::ClearHighlight => self.highlight_search = false,
+        }
+    }
 
-impl BackgroundExecutor {
-    pub fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
-    where
-        F: Future + Send + 'static,
-        F::Output: Send + 'static,
-    {
-        tokio::task::spawn(future)
+    /// Moves the cursor to the first match of the pattern typed so far, as it is typed. An
+    /// unfinished pattern that does not compile yet leaves the cursor where the search started.
+    fn incremental_search(&mut self, editor_id: EditorId) {
+        let prompt = &self.search_prompt;
+        let editor = &mut self.editors[editor_id];
+        let buffer = &self.buffers[editor.buffer_id];
+        editor.cursor = prompt.origin;
+        self.search = match editor::Search::new(&prompt.selector.query, prompt.backward) {
+            Ok(search) if !prompt.selector.query.is_empty() => {
+                editor.search(buffer, &search, prompt.origin, false);
+                self.highlight_search = true;
+                Some(search)
+            }
+            _ => None,
+        };
     }
-}
 
-struct AppContext {
-    background: BackgroundExecutor,
-}
+    /// Moves the cursor to the next match of the last search, telling when it wrapped around.
+    fn search_next(&mut self, editor_id: EditorId, from: editor::Point, reverse: bool) {
+        let Some(search) = &self.search else {
+            self.message = Some(Message::Error("no previous search pattern".into()));
+            return;
+        };
+        let editor = &mut self.editors[editor_id];
+        let buffer = &self.buffers[editor.buffer_id];
+        self.message = match editor.search(buffer, search, from, reverse) {
+            None => Some(Message::Error(format!("Pattern not found: {}", search.pattern))),
+            Some(true) if search.backward != reverse => {
+                Some(Message::Info("search hit TOP, continuing at BOTTOM".into()))
+            }
+            Some(true) => Some(Message::Info("search hit BOTTOM, continuing at TOP".into())),
+            Some(false) => None,
+        };
+    }
+
+    fn substitute(&mut self, command: &str) {
+        let editor_id = self.focused_editor_id();
+        let editor = &mut self.editors[editor_id];
+        let buffer = &mut self.buffers[editor.buffer_id];
+        let last_pattern = self.search.as_ref().map(|search| search.pattern.as_str());
+        let substitute = match editor::Substitute::parse(
+            command,
+            &buffer.contents,
+            editor.cursor.line,
+            last_pattern,
+        ) {
+            Ok(substitute) => substitute,
+            Err(e) => {
+                self.message = Some(Message::Error(format!("{:#}", e)));
+                return;
+            }
+        };
+        // the pattern becomes the one `n` and `N` search for.
+        self.search = editor::Search::new(&substitute.pattern, false).ok();
+        self.highlight_search = true;
+
+        if substitute.confirm {
+            let substitution = editor::Substitution::new(buffer, &substitute);
+            self.substitution = Some((editor_id, substitution));
+            self.confirm_substitution(None);
+        } else {
+            let (buffer_id, edits) = (buffer.id, buffer.edits().len());
+            let replaced = editor.substitute(buffer, &substitute);
+            self.follow_edits(buffer_id, edits, None);
+            self.report_substitution(replaced, &substitute.pattern);
+        }
+    }
+
+    /// Answers the question asked at the current match of a confirmed substitution, then asks it
+    /// at the next match, or finishes the substitution when there is none.
+    fn confirm_substitution(&mut self, answer: Option<Confirm>) {
+        let Some((editor_id, substitution)) = self.substitution.as_mut() else {
+            return;
+        };
+        let editor = &mut self.editors[*editor_id];
+        let buffer = &mut self.buffers[editor.buffer_id];
+        let (buffer_id, edits) = (buffer.id, buffer.edits().len());
+        let done = match answer {
+            None => false,
+            Some(Confirm::Yes) => {
+                substitution.replace(buffer);
+                false
+            }
+            Some(Confirm::No) => {
+                substitution.skip();
+                false
+            }
+            Some(Confirm::All) => {
+                substitution.replace_all(buffer);
+                true
+            }
+            Some(Confirm::Last) => {
+                substitution.replace(buffer);
+                true
+            }
+            Some(Confirm::Quit) => true,
+        };
 
-impl AppContext {
-    pub fn new() -> Result<Self> {
-        let background_rt = tokio::runtime::Builder::new_multi_thread().build()?;
-        let background = BackgroundExecutor(background_rt.handle().clone());
-        Ok(Self { background })
+        match substitution.current().filter(|_| !done) {
+            Some(range) => {
+                let line = buffer.contents.char_to_line(range.start);
+                let column = range.start - buffer.contents.line_to_char(line);
+                editor.cursor = editor::Point { line, column };
+                editor.open_folds_at_cursor();
+                let msg = "replace this match? (y/n/a/q/l)".into();
+                self.message = Some(Message::Info(msg));
+            }
+            None => {
+                let (_, substitution) = self.substitution.take().expect("substitution is pending");
+                let replaced = substitution.finish(buffer);
+                let pattern = self.search.as_ref().map(|search| search.pattern.clone());
+                self.report_substitution(replaced, &pattern.unwrap_or_default());
+            }
+        }
+        self.follow_edits(buffer_id, edits, None);
+    }
+
+    fn report_substitution(&mut self, replaced: usize, pattern: &str) {
+        self.message = match replaced {
+            0 => Some(Message::Error(format!("Pattern not found: {}", pattern))),
+            1 => Some(Message::Info("1 substitution".into())),
+            n => Some(Message::Info(format!("{} substitutions", n))),
+        };
     }
 
-    pub fn background_executor(&self) -> &BackgroundExecutor {
-        &self.background
+    /// Whether a syntax result computed for `revision` still describes the buffer's contents.
+    fn is_current_revision(&self, buffer_id: BufferId, revision: usize) -> bool {
+        self.buffers
+            .get(buffer_id)
+            .is_some_and(|buffer| buffer.revision() == revision)
+    }
+
+    fn process_syntax(&mut self, ev: syntax::Event) -> Option<Command> {
+        match ev {
+            syntax::Event::Hightlight(buffer_id, revision, ranges, spans) => {
+                if !self.is_current_revision(buffer_id, revision) {
+                    return None;
+                }
+                Some(Command::Buffer(buffer_id, BufferCommand::Highlight(ranges, spans)))
+            }
+            syntax::Event::Parsed(buffer_id, revision, tree, changed) => {
+                // a parse started before the buffer's language was changed is outdated too.
+                let language = self.buffer_languages.get(buffer_id);
+                let language = language.filter(|language| language.ts == tree.language());
+                if let Some(language) =
+                    language.filter(|_| self.is_current_revision(buffer_id, revision))
+                {
+                    let syntax = syntax::Tree::new(tree.clone(), language, &mut self.queries);
+                    self.buffers[buffer_id].syntax = Some(Box::new(syntax));
+                    self.syntax_trees.insert(buffer_id, tree);
+                    self.parsed_revisions.insert(buffer_id, revision);
+                    // the syntax around these ranges changed, even where the text did not.
+                    self.buffers[buffer_id].highlights.invalidate(&changed);
+                }
+                None
+            }
+        }
     }
 }
 
-#[derive()]
 pub struct App {
-    ctx: AppContext,
     cmd_rx: mpsc::Receiver<Command>,
-    cmd_tx: mpsc::Sender<Command>,
     term: Terminal,
     events: EventStream,
     syntax: syntax::Syntax,
@@ -465,15 +884,19 @@ pub struct App {
 }
 
 impl App {
-    pub fn spawn(paths: Option<Vec<std::path::PathBuf>>) -> Result<()> {
+    pub fn spawn(
+        paths: Option<Vec<std::path::PathBuf>>,
+        clipboard: Clipboard,
+        languages: syntax::LanguageRegistry,
+        decorations: ui::Decorations,
+    ) -> Result<()> {
         let rt = tokio::runtime::Builder::new_current_thread().build()?;
-        let ctx = AppContext::new()?;
         rt.block_on(async move {
             let stdout = std::io::stdout();
             let term = Terminal::new(CrosstermBackend::new(stdout))?;
 
             let (cmd_tx, cmd_rx) = mpsc::channel(1);
-            let app = Self::new(ctx, term, cmd_tx.clone(), cmd_rx);
+            let app = Self::new(term, cmd_tx.clone(), cmd_rx, clipboard, languages, decorations);
             let app = tokio::spawn(app.run());
             if let Some(paths) = paths {
                 for p in paths.iter() {
@@ -486,24 +909,33 @@ impl App {
         })
     }
 
-    fn new(ctx: AppContext, term: Terminal, cmd_tx: mpsc::Sender<Command>, cmd_rx: mpsc::Receiver<Command>) -> Self {
+    fn new(
+        term: Terminal,
+        cmd_tx: mpsc::Sender<Command>,
+        cmd_rx: mpsc::Receiver<Command>,
+        clipboard: Clipboard,
+        languages: syntax::LanguageRegistry,
+        decorations: ui::Decorations,
+    ) -> Self {
         let events = EventStream::new();
-        let syntax = Syntax::spawn();
-        let state = State::new();
-        Self { ctx, cmd_tx, cmd_rx, term, events, syntax, state }
+        let syntax = Syntax::spawn(languages.clone());
+        let state = State::new(cmd_tx, clipboard, languages, decorations);
+        Self { cmd_rx, term, events, syntax, state }
     }
 
     async fn run(mut self) -> Result<()> {
         'main: loop {
             self.draw_frame()?;
-            let maybe_command = self.select_command().await?;
+            let mut maybe_command = self.select_command().await?;
 
-            if let Some(command) = maybe_command {
-                if let Command::Quit = command {
+            while let Some(command) = maybe_command {
+                if let Command::ForceQuit = command {
                     break 'main;
                 }
-                self.process_command(command).await?;
+                maybe_command = self.process_command(command).await?;
             }
+            self.reparse_edited_buffers().await?;
+            self.request_highlights().await?;
         }
 
         Ok(())
@@ -520,6 +952,9 @@ impl App {
 
         let (cursor, cursor_style) = cursor.expect("cursor must be set");
         let backend = self.term.backend_mut();
+        if let Some(escape) = self.state.registers.take_escape() {
+            backend.queue(crossterm::style::Print(escape))?;
+        }
         backend
             .queue(cursor_style)?
             .queue(cursor::MoveTo(cursor.x, cursor.y))?
@@ -529,7 +964,7 @@ impl App {
     }
 
     async fn select_command(&mut self) -> Result<Option<Command>> {
-        use futures::{future::FutureExt, StreamExt};
+        use futures::{FutureExt, StreamExt};
 
         let maybe_command = tokio::select! {
             maybe_command = self.cmd_rx.recv() => { maybe_command }
@@ -538,33 +973,40 @@ impl App {
                 self.state.process_syntax(syntax)
             },
             maybe_event = self.events.next().fuse() => match maybe_event {
-                None => Some(Command::Quit),
+                None => Some(Command::ForceQuit),
                 Some(event) => self.state.process_event(event?),
             },
         };
         Ok(maybe_command)
     }
 
-    async fn process_command(&mut self, command: Command) -> Result<()> {
+    /// Processes a command, returning a follow-up command to process immediately after.
+    async fn process_command(&mut self, command: Command) -> Result<Option<Command>> {
         match command {
-            Command::Quit => unreachable!("handled in main loop"),
-            Command::Commands(cmd) => match cmd {
-                _ => todo!(),
-                // selector::Command::Select(entry_id) => {
-                //     let entry = self.state.commands.entries.get(entry_id).unwrap();
-                //     self.cmd_tx.send(entry.command.clone()).await?;
-                //     self.state.close_focused_pane();
-                // }
-                // selector::Command::Open => {
-                //     self.state.focus_pane(self.state.commands_pane_id);
-                // }
-                // selector::Command::Close => {
-                //     debug_assert_eq!(self.state.focused_pane, self.state.commands_pane_id);
-                //     self.state.close_focused_pane();
-                // }
-            },
+            Command::Quit => {
+                let dirty = self.state.buffers.values().find(|buffer| buffer.is_dirty());
+                match dirty {
+                    None => return Ok(Some(Command::ForceQuit)),
+                    Some(buffer) => {
+                        let name = buffer.name();
+                        let msg =
+                            format!("\"{}\" has unsaved changes (use quit! to discard)", name);
+                        self.state.message = Some(Message::Error(msg));
+                    }
+                }
+            }
+            Command::ForceQuit => unreachable!("handled in main loop"),
+            Command::Commands(cmd) => self.state.command_registry.command(cmd),
+            Command::Symbols(cmd) => self.state.symbol_picker.command(cmd),
+            Command::Search(cmd) => self.state.search_command(cmd),
+            Command::Substitute(command) => self.state.substitute(&command),
+            Command::ConfirmSubstitution(answer) => self.state.confirm_substitution(Some(answer)),
+            Command::Message(message) => self.state.message = Some(message),
             Command::Pane(pane_id, cmd) => match cmd {
                 PaneCommand::Open => {
+                    if let Pane::Commands(..) = self.state.panes[pane_id] {
+                        self.state.command_registry.reset();
+                    }
                     self.state.focus_pane(pane_id);
                 }
                 PaneCommand::Close => {
@@ -572,10 +1014,9 @@ impl App {
                     self.state.close_focused_pane()
                 }
             },
-            Command::Editor(editor_id, cmd) => {
-                let editor = &mut self.state.editors[editor_id];
-                let buffer = &mut self.state.buffers[editor.buffer_id];
-                editor.command(buffer, cmd);
+            Command::Editor(editor_id, cmd) => self.state.editor_command(editor_id, None, cmd),
+            Command::EditorRegister(editor_id, register, cmd) => {
+                self.state.editor_command(editor_id, Some(register), cmd)
             }
             Command::Buffer(buffer_id, cmd) => {
                 let buffer = &mut self.state.buffers[buffer_id];
@@ -583,63 +1024,230 @@ impl App {
             }
 
             Command::FocusedEditor(cmd) => {
-                let pane_id = match self.state.focused_pane() {
-                    Pane::Commands(..) => {
-                        if let [.., pane_id, _] = self.state.visible_panes[..] {
-                            match self.state.panes[pane_id] {
-                                Pane::Editor(..) => pane_id,
-                                _ => unreachable!("no focused editor"),
-                            }
-                        } else {
-                            unreachable!("no visible panes")
-                        }
-                    }
-                    Pane::Editor(..) => self.state.focused_pane,
-                };
-                let pane = &self.state.panes[pane_id];
-                match pane {
-                    Pane::Commands(..) => {
-                        unreachable!("focused pane is not an editor")
-                    }
-                    Pane::Editor(_, editor_id) => {
-                        let editor = &mut self.state.editors[*editor_id];
-                        let buffer = &mut self.state.buffers[editor.buffer_id];
-                        editor.command(buffer, cmd);
-                    }
-                }
+                let editor_id = self.state.focused_editor_id();
+                self.state.editor_command(editor_id, None, cmd);
             }
 
             Command::FileOpen(maybe_editor_id, path) => {
-                let contents = Buffer::read(&path).await?;
+                let name = format!("\"{}\"", path.display());
+                let contents = match Buffer::read(&path).await {
+                    Ok(contents) => contents,
+                    // a file that does not exist yet is made when the buffer is written.
+                    Err(e) if is_not_found(&e) => {
+                        self.state.message = Some(Message::Info(format!("{} [New]", name)));
+                        editor::BufferContents::default()
+                    }
+                    Err(e) => {
+                        self.state.message = Some(Message::Error(format!("{}: {:#}", name, e)));
+                        return Ok(None);
+                    }
+                };
                 let buffer_id = self
                     .state
                     .buffers
-                    .insert_with_key(|k| Buffer::new(k, contents.clone()));
-
+                    .insert_with_key(|k| Buffer::new(k, Some(path), contents.clone()));
                 let editor_id = maybe_editor_id.unwrap_or(self.state.default_editor_id);
                 let editor = &mut self.state.editors[editor_id];
                 editor.swap_buffer(buffer_id);
 
-                match syntax::Language::try_from(&self.state.buffers[buffer_id]) {
-                    Ok(language) => {
-                        self.syntax
-                            .command(syntax::Command::Parse { buffer_id, contents, language })
-                            .await?;
-                    }
-                    _ => todo!(),
+                let buffer = &self.state.buffers[buffer_id];
+                let language = self
+                    .state
+                    .languages
+                    .detect(buffer.path.as_deref(), &contents);
+                let language = language.unwrap_or_else(|e| {
+                    self.state.message = Some(Message::Error(format!("{:#}", e)));
+                    None
+                });
+                self.set_language(buffer_id, language).await?;
+            }
+            Command::SetLanguage(maybe_editor_id, name) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                let buffer_id = self.state.editors[editor_id].buffer_id;
+                let language = match name.as_str() {
+                    "" | "text" | "plain" => None,
+                    name => match self.state.languages.by_name(name) {
+                        Ok(Some(language)) => Some(language),
+                        Ok(None) => {
+                            let msg = format!("unknown language: {}", name);
+                            self.state.message = Some(Message::Error(msg));
+                            return Ok(None);
+                        }
+                        Err(e) => {
+                            self.state.message = Some(Message::Error(format!("{:#}", e)));
+                            return Ok(None);
+                        }
+                    },
                 };
+                self.set_language(buffer_id, language).await?;
+            }
+            Command::ExpandSelection(maybe_editor_id) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                self.state.resize_selections(editor_id, true);
+            }
+            Command::ShrinkSelection(maybe_editor_id) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                self.state.resize_selections(editor_id, false);
+            }
+            Command::PickSymbol(maybe_editor_id) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                self.state.pick_symbol(editor_id);
+            }
+            Command::FileWrite(maybe_editor_id) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                self.write_buffer(editor_id, None).await;
+            }
+            Command::FileWriteAs(maybe_editor_id, path) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                if path.as_os_str().is_empty() {
+                    self.state.message = Some(Message::Error("write-as: no file name".into()));
+                } else {
+                    self.write_buffer(editor_id, Some(path)).await;
+                }
+            }
+            Command::FileWriteQuit(maybe_editor_id) => {
+                let editor_id = maybe_editor_id.unwrap_or_else(|| self.state.focused_editor_id());
+                if self.write_buffer(editor_id, None).await {
+                    return Ok(Some(Command::Quit));
+                }
             }
         };
 
+        Ok(None)
+    }
+
+    /// Sets the language of a buffer, parsing it from scratch, or with `None` makes it plain text.
+    async fn set_language(
+        &mut self,
+        buffer_id: BufferId,
+        language: Option<syntax::Language>,
+    ) -> Result<()> {
+        let state = &mut self.state;
+        let buffer = &mut state.buffers[buffer_id];
+        buffer.highlights = Default::default();
+        buffer.syntax = None;
+        buffer.take_edits();
+        state.syntax_trees.remove(buffer_id);
+        state.parsed_revisions.remove(buffer_id);
+        state.requested_highlights.remove(buffer_id);
+        buffer.indent = language
+            .as_ref()
+            .map_or_else(Default::default, |language| language.indent);
+        let Some(language) = language else {
+            state.buffer_languages.remove(buffer_id);
+            return Ok(());
+        };
+
+        state.buffer_languages.insert(buffer_id, language.clone());
+        let revision = buffer.revision();
+        let contents = buffer.contents.clone();
+        let command = syntax::Command::Parse { buffer_id, revision, contents, language };
+        self.syntax.command(command).await
+    }
+
+    /// Brings the syntax trees of edited buffers up to date. The stored tree is edited to match
+    /// the buffer so that the worker only reparses what changed.
+    async fn reparse_edited_buffers(&mut self) -> Result<()> {
+        for (buffer_id, buffer) in self.state.buffers.iter_mut() {
+            let edits = buffer.take_edits();
+            if edits.is_empty() {
+                continue;
+            }
+            let Some(language) = self.state.buffer_languages.get(buffer_id).cloned() else {
+                continue;
+            };
+            let revision = buffer.revision();
+            let contents = buffer.contents.clone();
+            let command = match self.state.syntax_trees.get_mut(buffer_id) {
+                Some(tree) => {
+                    for edit in edits.iter() {
+                        tree.edit(&syntax::input_edit(edit));
+                    }
+                    let tree = tree.clone();
+                    syntax::Command::Reparse { buffer_id, revision, contents, language, tree }
+                }
+                // not parsed yet, or the first parse was outdated by these edits.
+                None => syntax::Command::Parse { buffer_id, revision, contents, language },
+            };
+            self.syntax.command(command).await?;
+        }
         Ok(())
     }
+
+    /// Asks the syntax worker to highlight what the visible editor panes show that is not
+    /// highlighted yet, so that only what is looked at gets highlighted.
+    async fn request_highlights(&mut self) -> Result<()> {
+        let size = self.term.size()?;
+        for (buffer_id, ranges) in self.state.missing_highlights(size) {
+            let buffer = &self.state.buffers[buffer_id];
+            let Some(language) = self.state.buffer_languages.get(buffer_id).cloned() else {
+                continue;
+            };
+            let command = syntax::Command::Highlight {
+                buffer_id,
+                revision: buffer.revision(),
+                contents: buffer.contents.clone(),
+                language,
+                tree: self.state.syntax_trees[buffer_id].clone(),
+                ranges,
+            };
+            self.syntax.command(command).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the editor's buffer, reporting the outcome in the status line rather than failing.
+    async fn write_buffer(&mut self, editor_id: EditorId, path: Option<PathBuf>) -> bool {
+        let res = self.state.write_buffer(editor_id, path).await;
+        let buffer = &self.state.buffers[self.state.editors[editor_id].buffer_id];
+        let name = buffer.name();
+        self.state.message = match &res {
+            Ok(()) => {
+                let lines = buffer.contents.len_lines();
+                Some(Message::Info(format!("\"{}\" {}L written", name, lines)))
+            }
+            Err(e) => Some(Message::Error(format!("\"{}\": {:#}", name, e))),
+        };
+        res.is_ok()
+    }
 }
 
-fn register_commands(registry: &mut CommandRegistry) {
+fn is_not_found(e: &anyhow::Error) -> bool {
+    let e = e.downcast_ref::<std::io::Error>();
+    e.is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// The command that a key makes in a picker, such as the command palette: typing filters the
+/// entries, and the arrows or ctrl-p and ctrl-n move through them.
+fn picker_command<T>(key: KeyEvent) -> Option<selector::Command<T>> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    let ctrl = key.modifiers == KeyModifiers::CONTROL;
+    match key.code {
+        KeyCode::Up => Some(selector::Command::Focus(selector::Direction::Prev)),
+        KeyCode::Down => Some(selector::Command::Focus(selector::Direction::Next)),
+        KeyCode::Char('p') if ctrl => Some(selector::Command::Focus(selector::Direction::Prev)),
+        KeyCode::Char('n') if ctrl => Some(selector::Command::Focus(selector::Direction::Next)),
+        KeyCode::Backspace => Some(selector::Command::Delete(selector::Direction::Prev)),
+        KeyCode::Char(c) => Some(selector::Command::Insert(c)),
+        _ => None,
+    }
+}
+
+pub(crate) fn register_commands(registry: &mut CommandRegistry) {
     use editor::EditorCommand::*;
     use editor::{CursorJump, Direction};
 
-    registry.register("quit", vec![], Command::Quit);
+    registry.register("quit", vec!["q"], Command::Quit);
+    registry.register("quit!", vec!["q!"], Command::ForceQuit);
+    registry.register("write", vec!["w"], Command::FileWrite(None));
+    registry.register("write-as", vec!["saveas"], Command::FileWriteAs(None, PathBuf::new()));
+    registry.register("write-quit", vec!["wq", "x"], Command::FileWriteQuit(None));
+    registry.register("substitute", vec!["s"], Command::Substitute(String::new()));
+    registry.register("set-language", vec!["setf"], Command::SetLanguage(None, String::new()));
+    registry.register("selection.expand", vec![], Command::ExpandSelection(None));
+    registry.register("selection.shrink", vec![], Command::ShrinkSelection(None));
+    registry.register("symbols", vec!["outline"], Command::PickSymbol(None));
+    registry.register("nohlsearch", vec!["noh"], Command::Search(SearchCommand::ClearHighlight));
 
     let cmds = [
         ("cursor.up", vec![], CursorMove(Direction::Up)),
@@ -650,10 +1258,13 @@ fn register_commands(registry: &mut CommandRegistry) {
         ("cursor.startOfLastWord", vec![], CursorJump(CursorJump::StartOfLastWord)),
         ("cursor.startOfNearestWord", vec![], CursorJump(CursorJump::StartOfNearestWord)),
         ("cursor.endOfNearestWord", vec![], CursorJump(CursorJump::EndOfNearestWord)),
+        ("cursor.addNextMatch", vec![], AddCursorAtNextMatch),
+        ("selection.splitLines", vec![], SplitSelectionLines),
+        ("selection.collapse", vec![], CollapseSelections),
+        ("undo", vec!["u"], Undo),
+        ("redo", vec![], Redo),
     ];
     for (name, aliases, cmd) in cmds {
         registry.register(name, aliases, Command::FocusedEditor(cmd));
     }
-
-    // commands.reset();
 }
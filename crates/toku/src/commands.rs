@@ -0,0 +1,142 @@
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+use slotmap::{new_key_type, SlotMap};
+
+use selector::Selector;
+use tore::CursorPoint;
+
+use crate::app::Command;
+
+new_key_type! {
+    pub struct CommandId;
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    command: Command,
+}
+
+/// The command palette: the commands that can be run by name, and the prompt that picks one.
+#[derive(Debug)]
+pub struct CommandRegistry {
+    entries: SlotMap<CommandId, Entry>,
+    selector: Selector<CommandId>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        let selector = Selector::new(":");
+        let entries = SlotMap::with_key();
+        Self { entries, selector }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        aliases: Vec<&'static str>,
+        command: Command,
+    ) -> CommandId {
+        self.entries.insert(Entry { name, aliases, command })
+    }
+
+    pub fn command(&mut self, command: selector::Command<CommandId>) {
+        let update = matches!(command, selector::Command::Insert(_) | selector::Command::Delete(_));
+        self.selector.command(command);
+        if update {
+            self.update();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.selector.reset();
+        self.update();
+    }
+
+    /// The focused command, with any arguments typed after its name.
+    pub fn focused(&self) -> Option<Command> {
+        if crate::search::is_substitute(&self.selector.query) {
+            return Some(Command::Substitute(self.selector.query.clone()));
+        }
+        let (_, args) = split_args(&self.selector.query);
+        self.selector
+            .focused
+            .map(|id| self.entries[id].command.clone().with_args(args))
+    }
+
+    fn update(&mut self) {
+        use fuzzy_matcher::skim::SkimMatcherV2;
+        use fuzzy_matcher::FuzzyMatcher;
+
+        let (query, _) = split_args(&self.selector.query);
+        let matcher = SkimMatcherV2::default();
+        let mut results = vec![];
+        for (id, entry) in &self.entries {
+            let names = std::iter::once(&entry.name).chain(entry.aliases.iter());
+            let score = names
+                .filter_map(|name| match *name == query {
+                    true => Some(i64::MAX),
+                    false => matcher.fuzzy_match(name, query),
+                })
+                .max();
+            if let Some(score) = score {
+                results.push((score, id));
+            }
+        }
+        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        let results = results.into_iter().map(|(_, id)| id).collect();
+        self.selector
+            .command(selector::Command::SetEntries(results));
+    }
+
+    pub fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        theme: &ui::Theme,
+    ) -> (CursorPoint, SetCursorStyle) {
+        let widget = ui::SelectorPane::new(theme, &self.selector);
+        widget.render(buf, area, &self.selector.entries, |area, buf, id| {
+            self.render_result(area, buf, id)
+        })
+    }
+
+    fn render_result(&self, area: tui::Rect, buf: &mut tui::Buffer, id: CommandId) {
+        use bstr::ByteSlice;
+        let entry = &self.entries[id];
+        let content = entry.name;
+        let mut graphemes = content.as_bytes().as_bstr().graphemes();
+        for x in area.left()..area.right() {
+            let symbol = graphemes.next().unwrap_or(" ");
+            buf.get_mut(x, area.top()).set_symbol(symbol);
+        }
+    }
+}
+
+/// Splits a command palette query into the command name and its arguments.
+fn split_args(query: &str) -> (&str, &str) {
+    let query = query.trim_start();
+    match query.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (query, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focuses_the_best_match_of_the_query() {
+        let mut registry = CommandRegistry::new();
+        crate::app::register_commands(&mut registry);
+        registry.reset();
+        for c in "q!".chars() {
+            registry.command(selector::Command::Insert(c));
+        }
+        assert!(matches!(registry.focused(), Some(Command::ForceQuit)));
+        registry.command(selector::Command::Delete(selector::Direction::Prev));
+        assert!(matches!(registry.focused(), Some(Command::Quit)));
+    }
+}
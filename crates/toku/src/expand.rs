@@ -0,0 +1,248 @@
+use std::cmp::min;
+use std::ops::Range;
+
+use tree_sitter as ts;
+
+use editor::{Buffer, BufferContents, Editor, Mode, Selection};
+
+/// The selections of an editor before each time they were expanded, so that shrinking them goes
+/// back the way they came.
+#[derive(Debug, Default)]
+pub struct ExpandHistory(Vec<Expansion>);
+
+#[derive(Debug)]
+struct Expansion {
+    mode: Mode,
+    before: (Vec<Selection>, usize),
+    after: (Vec<Selection>, usize),
+}
+
+impl ExpandHistory {
+    /// Grows each selection to the smallest syntax node around it, entering visual mode. A
+    /// cursor grows to the node under it.
+    pub fn expand(&mut self, editor: &mut Editor, buffer: &mut Buffer, tree: &ts::Tree) {
+        let before = editor.selections();
+        if !self.is_current(editor, &before) {
+            self.0.clear();
+        }
+
+        let contents = &buffer.contents;
+        let (selections, primary) = &before;
+        let expanded: Vec<_> = selections
+            .iter()
+            .map(|selection| {
+                let range = char_range(contents, &editor.mode, selection);
+                let range = enclosing_node(tree, contents, range);
+                range.map_or(*selection, |range| to_selection(contents, range, selection))
+            })
+            .collect();
+        if expanded == *selections {
+            return;
+        }
+
+        let mode = editor.mode.clone();
+        editor.set_mode(buffer, Mode::Visual);
+        editor.set_selections(expanded, *primary);
+        let after = editor.selections();
+        self.0.push(Expansion { mode, before, after });
+    }
+
+    /// Takes the selections back to what they were before they were last expanded. Selections
+    /// that were not expanded here shrink to the first node inside them instead.
+    pub fn shrink(&mut self, editor: &mut Editor, buffer: &mut Buffer, tree: &ts::Tree) {
+        let current = editor.selections();
+        if self.is_current(editor, &current) {
+            if let Some(expansion) = self.0.pop() {
+                let (selections, primary) = expansion.before;
+                editor.set_mode(buffer, expansion.mode);
+                editor.set_selections(selections, primary);
+                return;
+            }
+        }
+        self.0.clear();
+        if editor.mode != Mode::Visual {
+            return;
+        }
+
+        let contents = &buffer.contents;
+        let (selections, primary) = current;
+        let shrunk = selections
+            .iter()
+            .map(|selection| {
+                let range = char_range(contents, &editor.mode, selection);
+                let range = inner_node(tree, contents, range);
+                range.map_or(*selection, |range| to_selection(contents, range, selection))
+            })
+            .collect();
+        editor.set_selections(shrunk, primary);
+    }
+
+    /// Whether the selections are the ones the last expansion left.
+    fn is_current(&self, editor: &Editor, selections: &(Vec<Selection>, usize)) -> bool {
+        let last = self.0.last();
+        editor.mode == Mode::Visual && last.is_some_and(|expansion| expansion.after == *selections)
+    }
+}
+
+/// The chars a selection covers in a mode. Outside the visual modes that is the char under the
+/// cursor.
+fn char_range(contents: &BufferContents, mode: &Mode, selection: &Selection) -> Range<usize> {
+    let (start, end) = (selection.start(), selection.end());
+    let range = match mode {
+        Mode::VisualLine => {
+            let end = contents.line_to_char(end.line) + contents.line_len(end.line);
+            contents.line_to_char(start.line)..end
+        }
+        Mode::Visual | Mode::VisualBlock => {
+            contents.point_to_char_offset(start)..contents.point_to_char_offset(end) + 1
+        }
+        Mode::Normal | Mode::Insert => {
+            let head = contents.point_to_char_offset(selection.head);
+            head..head + 1
+        }
+    };
+    let len = contents.len_chars();
+    min(range.start, len)..min(range.end, len)
+}
+
+/// The chars of a node, without the line ending some nodes such as line comments end with.
+fn node_range(contents: &BufferContents, node: ts::Node) -> Range<usize> {
+    let start = contents.byte_to_char(min(node.start_byte(), contents.len_bytes()));
+    let mut end = contents.byte_to_char(min(node.end_byte(), contents.len_bytes()));
+    while end > start && matches!(contents.char(end - 1), '\n' | '\r') {
+        end -= 1;
+    }
+    start..end
+}
+
+/// The named node that spans a range of chars, if there is one.
+fn node_at<'tree>(
+    tree: &'tree ts::Tree,
+    contents: &BufferContents,
+    range: &Range<usize>,
+) -> Option<ts::Node<'tree>> {
+    let (start, end) = (contents.char_to_byte(range.start), contents.char_to_byte(range.end));
+    tree.root_node().named_descendant_for_byte_range(start, end)
+}
+
+/// The chars of the smallest named node that covers a range and more.
+fn enclosing_node(
+    tree: &ts::Tree,
+    contents: &BufferContents,
+    range: Range<usize>,
+) -> Option<Range<usize>> {
+    if range.is_empty() {
+        return None;
+    }
+    let mut node = node_at(tree, contents, &range)?;
+    loop {
+        let node_range = node_range(contents, node);
+        let covers = node_range.start <= range.start && range.end <= node_range.end;
+        if covers && node_range != range {
+            return Some(node_range);
+        }
+        node = node.parent()?;
+    }
+}
+
+/// The chars of the first named node inside the one that spans exactly a range.
+fn inner_node(
+    tree: &ts::Tree,
+    contents: &BufferContents,
+    range: Range<usize>,
+) -> Option<Range<usize>> {
+    let mut node = node_at(tree, contents, &range)?;
+    if node_range(contents, node) != range {
+        return None;
+    }
+    // a node with a single child spans the same chars as it, so look further in.
+    loop {
+        let mut cursor = node.walk();
+        let child = node.named_children(&mut cursor).next()?;
+        let child_range = node_range(contents, child);
+        if !child_range.is_empty() && child_range != range {
+            return Some(child_range);
+        }
+        node = child;
+    }
+}
+
+/// A selection of a non-empty range of chars, facing the same way as `like`.
+fn to_selection(contents: &BufferContents, range: Range<usize>, like: &Selection) -> Selection {
+    let start = contents.char_offset_to_point(range.start);
+    let end = contents.char_offset_to_point(range.end - 1);
+    match like.head < like.anchor {
+        true => Selection { anchor: end, head: start },
+        false => Selection { anchor: start, head: end },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::{EditorId, Point};
+
+    fn setup(text: &str) -> (Editor, Buffer, ts::Tree) {
+        let (tree, buffer) = syntax::testing::parse(&syntax::testing::language("rust"), text);
+        let editor = Editor::new(EditorId::default(), buffer.id);
+        (editor, buffer, tree)
+    }
+
+    fn selected(editor: &Editor, buffer: &Buffer) -> Vec<String> {
+        let contents = &buffer.contents;
+        let (selections, _) = editor.selections();
+        selections
+            .iter()
+            .map(|selection| char_range(contents, &editor.mode, selection))
+            .map(|range| contents.slice(range).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expands_and_shrinks_back() {
+        let (mut editor, mut buffer, tree) = setup("fn f() {\n    g(ab, cd);\n}\n");
+        let mut history = ExpandHistory::default();
+        editor.cursor = Point { line: 1, column: 6 };
+        history.expand(&mut editor, &mut buffer, &tree);
+        assert_eq!(editor.mode, Mode::Visual);
+        assert_eq!(selected(&editor, &buffer), vec!["ab"]);
+        history.expand(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["(ab, cd)"]);
+        history.expand(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["g(ab, cd)"]);
+
+        history.shrink(&mut editor, &mut buffer, &tree);
+        history.shrink(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["ab"]);
+        history.shrink(&mut editor, &mut buffer, &tree);
+        assert_eq!(editor.mode, Mode::Normal);
+        assert_eq!(editor.cursor, Point { line: 1, column: 6 });
+    }
+
+    #[test]
+    fn expands_every_selection() {
+        let (mut editor, mut buffer, tree) = setup("fn f() {\n    g(ab, cd);\n}\n");
+        let mut history = ExpandHistory::default();
+        editor.cursor = Point { line: 1, column: 6 };
+        let cursor = Point { line: 1, column: 10 };
+        editor.secondaries = vec![Selection { anchor: cursor, head: cursor }];
+        history.expand(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["ab", "cd"]);
+        // both grow to the arguments and become one.
+        history.expand(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["(ab, cd)"]);
+        history.shrink(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["ab", "cd"]);
+    }
+
+    #[test]
+    fn shrinks_without_history() {
+        let (mut editor, mut buffer, tree) = setup("fn f() {\n    g(ab, cd);\n}\n");
+        let mut history = ExpandHistory::default();
+        editor.cursor = Point { line: 1, column: 4 };
+        editor.set_mode(&mut buffer, Mode::Visual);
+        editor.cursor = Point { line: 1, column: 13 };
+        history.shrink(&mut editor, &mut buffer, &tree);
+        assert_eq!(selected(&editor, &buffer), vec!["g(ab, cd)"]);
+    }
+}
@@ -3,8 +3,14 @@ use clap::Parser;
 use lazy_static::lazy_static;
 
 mod app;
+mod commands;
+mod expand;
+mod registers;
+mod search;
+mod symbols;
 
 use app::App;
+use registers::Clipboard;
 
 lazy_static! {
     pub(crate) static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_string();
@@ -17,19 +23,57 @@ lazy_static! {
 pub struct Args {
     /// Paths to files to open
     paths: Option<Vec<std::path::PathBuf>>,
+
+    /// Command that copies its stdin to the system clipboard, e.g. "wl-copy"
+    #[arg(long, requires = "paste_command")]
+    copy_command: Option<String>,
+
+    /// Command that prints the system clipboard, e.g. "wl-paste -n"
+    #[arg(long, requires = "copy_command")]
+    paste_command: Option<String>,
+    /// Most lines of the scopes around the top of an editor to pin above it, 0 for none
+    #[arg(long, default_value_t = 3)]
+    context_lines: usize,
+
+    /// Color brackets by how deeply they nest
+    #[arg(long)]
+    rainbow_brackets: bool,
+
+    /// Draw a line down each level of indentation
+    #[arg(long)]
+    indent_guides: bool,
+
+    /// Indent a language's files with tabs or a number of spaces, e.g. "go=tabs" or "python=2"
+    #[arg(long = "indent", value_name = "LANG=INDENT", value_parser = parse_indent)]
+    indents: Vec<(String, editor::Indent)>,
+}
+
+fn parse_indent(arg: &str) -> Result<(String, editor::Indent)> {
+    let (name, indent) = arg.split_once('=').context("expected LANG=INDENT")?;
+    Ok((name.to_string(), indent.parse()?))
 }
 
 fn main() -> Result<()> {
     use crossterm::terminal;
 
     let args = Args::parse();
+    let clipboard = Clipboard::detect(args.copy_command.as_deref(), args.paste_command.as_deref());
     let supports_keyboard_enhancement =
         matches!(terminal::supports_keyboard_enhancement(), Ok(true));
     setup_panic_handler(supports_keyboard_enhancement);
     setup_logging()?;
+    let mut languages = load_languages();
+    for (name, indent) in args.indents {
+        languages.set_indent(&name, indent)?;
+    }
     terminal_enter(supports_keyboard_enhancement)?;
 
-    let res = App::spawn(args.paths);
+    let decorations = ui::Decorations {
+        context_lines: args.context_lines,
+        rainbow_brackets: args.rainbow_brackets,
+        indent_guides: args.indent_guides,
+    };
+    let res = App::spawn(args.paths, clipboard, languages, decorations);
     terminal_exit(supports_keyboard_enhancement)?;
     res
 }
@@ -74,6 +118,24 @@ fn setup_logging() -> Result<()> {
     Ok(())
 }
 
+/// The built-in languages, and the grammars in the `grammars` directories of the XDG data and
+/// config dirs. Those in the config dirs take precedence, the user's own over system-wide ones.
+fn load_languages() -> syntax::LanguageRegistry {
+    let mut languages = syntax::LanguageRegistry::default();
+    let Ok(xdg_dirs) = xdg::BaseDirectories::with_prefix(PROJECT_NAME.clone()) else {
+        return languages;
+    };
+    // most important first, so registered in reverse.
+    let mut dirs = xdg_dirs.find_config_files("grammars").collect::<Vec<_>>();
+    dirs.extend(xdg_dirs.find_data_files("grammars"));
+    for dir in dirs.iter().rev() {
+        if let Err(e) = languages.load_dir(dir) {
+            tracing::warn!("cannot load grammars: {:#}", e);
+        }
+    }
+    languages
+}
+
 fn setup_panic_handler(supports_keyboard_enhancement: bool) {
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use editor::Yank;
+
+/// How long a paste command may run before it is killed.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the `+` and `*` registers reach the system clipboard.
+#[derive(Debug, Clone)]
+pub enum Clipboard {
+    /// Programs that copy text from their stdin and paste it to their stdout.
+    Command {
+        copy: Vec<String>,
+        paste: Vec<String>,
+    },
+    /// OSC 52 escape sequences, which the terminal turns into clipboard writes. Terminals rarely
+    /// allow reading the clipboard this way, so pasting gives back the last text copied.
+    Osc52,
+}
+
+impl Clipboard {
+    /// Picks a clipboard from the given commands, or else from the session's environment.
+    pub fn detect(copy: Option<&str>, paste: Option<&str>) -> Self {
+        let split = |command: &str| command.split_whitespace().map(String::from).collect();
+        let (copy, paste) = match (copy, paste) {
+            (Some(copy), Some(paste)) => (copy, paste),
+            _ if cfg!(target_os = "macos") => ("pbcopy", "pbpaste"),
+            _ if std::env::var_os("WAYLAND_DISPLAY").is_some() => ("wl-copy", "wl-paste -n"),
+            _ if std::env::var_os("DISPLAY").is_some() => {
+                ("xclip -selection clipboard", "xclip -selection clipboard -o")
+            }
+            _ => return Clipboard::Osc52,
+        };
+        Clipboard::Command { copy: split(copy), paste: split(paste) }
+    }
+
+    /// Copies text, returning the escape sequence to write to the terminal if it is the one to
+    /// copy it. A copy command is given the text and waited on in the background, so a slow one
+    /// does not hold up the editor, and it failing is only logged.
+    fn copy(&self, text: &str) -> Result<Option<String>> {
+        match self {
+            Clipboard::Command { copy, .. } => {
+                let (program, args) = copy.split_first().context("empty copy command")?;
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .with_context(|| format!("run {}", program))?;
+                let mut stdin = child.stdin.take().expect("stdin is piped");
+                let (program, text) = (program.clone(), text.to_string());
+                std::thread::spawn(move || {
+                    let written = stdin.write_all(text.as_bytes());
+                    drop(stdin);
+                    match (written, child.wait()) {
+                        (Ok(()), Ok(status)) if status.success() => (),
+                        (Err(e), _) | (_, Err(e)) => tracing::warn!("{}: {}", program, e),
+                        (_, Ok(status)) => tracing::warn!("{} exited with {}", program, status),
+                    }
+                });
+                Ok(None)
+            }
+            Clipboard::Osc52 => {
+                use base64::Engine;
+
+                let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+                Ok(Some(format!("\x1b]52;c;{}\x07", encoded)))
+            }
+        }
+    }
+
+    /// Reads the clipboard with the paste command on a thread of its own, so that a slow one
+    /// does not hold up the editor, and calls `done` with the text.
+    fn paste(&self, done: impl FnOnce(Result<String>) + Send + 'static) {
+        let paste = match self {
+            Clipboard::Command { paste, .. } => paste.clone(),
+            Clipboard::Osc52 => return done(Err(anyhow!("the terminal cannot be pasted from"))),
+        };
+        std::thread::spawn(move || done(run_paste(&paste)));
+    }
+}
+
+/// Runs a paste command, returning what it wrote. A command that has not finished after
+/// [`PASTE_TIMEOUT`] is killed.
+fn run_paste(paste: &[String]) -> Result<String> {
+    let (program, args) = paste.split_first().context("empty paste command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("run {}", program))?;
+    // read as it is written, or the command may wait on a full pipe.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut text = vec![];
+        stdout.read_to_end(&mut text).map(|_| text)
+    });
+    let deadline = Instant::now() + PASTE_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill()?;
+            child.wait()?;
+            bail!("{} did not finish in time", program);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    let text = reader.join().expect("clipboard reader panicked")?;
+    String::from_utf8(text).context("clipboard is not UTF-8")
+}
+
+/// Where yanked and deleted text is kept, named as in vim:
+///
+/// - `"`, the unnamed register, holds the text most recently yanked or deleted.
+/// - `a` to `z` are written only when named; `A` to `Z` append to them.
+/// - `0` holds the last yank that named no register, and `1` to `9` the last deletes, most
+///   recent first.
+/// - `+` and `*` are the system clipboard.
+/// - `_` discards what is written to it.
+#[derive(Debug)]
+pub struct Registers {
+    unnamed: Option<Yank>,
+    named: HashMap<char, Yank>,
+    yanked: Option<Yank>,
+    deleted: VecDeque<Yank>,
+    clipboard: Clipboard,
+    copied: Option<Yank>,
+    /// The escape sequence copying the last text to the clipboard, waiting to be written to the
+    /// terminal.
+    escape: Option<String>,
+}
+
+impl Registers {
+    pub fn new(clipboard: Clipboard) -> Self {
+        Self {
+            unnamed: None,
+            named: HashMap::new(),
+            yanked: None,
+            deleted: VecDeque::new(),
+            clipboard,
+            copied: None,
+            escape: None,
+        }
+    }
+
+    /// Stores text taken by a yank (`yanked`) or a delete, in `register` and the registers that
+    /// track history. `"0` only keeps yanks into the unnamed register.
+    pub fn store(&mut self, register: Option<char>, yank: Yank, yanked: bool) -> Result<()> {
+        let unnamed = matches!(register, None | Some('"'));
+        let yank = match register {
+            Some('_') => return Ok(()),
+            Some(c @ 'A'..='Z') => {
+                let appended = match self.named.remove(&c.to_ascii_lowercase()) {
+                    Some(register) => append(register, yank),
+                    None => yank,
+                };
+                self.named.insert(c.to_ascii_lowercase(), appended.clone());
+                appended
+            }
+            Some(c @ 'a'..='z') => {
+                self.named.insert(c, yank.clone());
+                yank
+            }
+            Some('+' | '*') => {
+                self.escape = self.clipboard.copy(&yank.text)?;
+                self.copied = Some(yank.clone());
+                yank
+            }
+            _ => yank,
+        };
+
+        if yanked && unnamed {
+            self.yanked = Some(yank.clone());
+        } else if !yanked {
+            self.deleted.push_front(yank.clone());
+            self.deleted.truncate(9);
+        }
+        self.unnamed = Some(yank);
+        Ok(())
+    }
+
+    /// The escape sequence to write to the terminal to copy to the clipboard, if there is one.
+    pub fn take_escape(&mut self) -> Option<String> {
+        self.escape.take()
+    }
+
+    /// The contents of `register`, or of the unnamed register if `None`. The clipboard registers
+    /// give back the last text copied, unless they are [pasted](Registers::is_pasted).
+    pub fn get(&self, register: Option<char>) -> Option<Yank> {
+        let yank = match register {
+            None | Some('"') => self.unnamed.as_ref(),
+            Some(c @ ('a'..='z' | 'A'..='Z')) => self.named.get(&c.to_ascii_lowercase()),
+            Some('0') => self.yanked.as_ref(),
+            Some(c @ '1'..='9') => self.deleted.get(c as usize - '1' as usize),
+            Some('+' | '*') => self.copied.as_ref(),
+            _ => None,
+        };
+        yank.cloned()
+    }
+
+    /// Whether `register` is read by running a paste command, with [`Registers::paste`].
+    pub fn is_pasted(&self, register: Option<char>) -> bool {
+        let command = matches!(self.clipboard, Clipboard::Command { .. });
+        command && matches!(register, Some('+' | '*'))
+    }
+
+    /// Reads the clipboard in the background, calling `done` with its text.
+    pub fn paste(&self, done: impl FnOnce(Result<Yank>) + Send + 'static) {
+        self.clipboard.paste(|text| {
+            // text copied elsewhere is taken as lines if it ends with a line break.
+            done(text.map(|text| Yank { linewise: text.ends_with('\n'), text }))
+        });
+    }
+}
+
+/// Text appended to a register, as `"A` to `"Z` do. Lines appended to text, or text to lines,
+/// make lines, with a single line break between the two parts and one at the end.
+fn append(register: Yank, yank: Yank) -> Yank {
+    if !register.linewise && !yank.linewise {
+        return Yank { text: register.text + &yank.text, linewise: false };
+    }
+    let line = |text: &str| text.strip_suffix('\n').unwrap_or(text).to_string();
+    let text = format!("{}\n{}\n", line(&register.text), line(&yank.text));
+    Yank { text, linewise: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Yank {
+        Yank { text: text.into(), linewise: false }
+    }
+
+    #[test]
+    fn named_and_numbered() {
+        let mut registers = Registers::new(Clipboard::Osc52);
+        registers.store(Some('a'), chars("foo"), true).unwrap();
+        registers.store(Some('A'), chars("bar"), true).unwrap();
+        registers.store(None, chars("baz"), true).unwrap();
+        registers.store(None, chars("one"), false).unwrap();
+        registers.store(None, chars("two"), false).unwrap();
+        registers.store(Some('_'), chars("gone"), false).unwrap();
+
+        let get = |register| registers.get(Some(register));
+        assert_eq!(get('a'), Some(chars("foobar")));
+        assert_eq!(get('0'), Some(chars("baz")));
+        assert_eq!(get('1'), Some(chars("two")));
+        assert_eq!(get('2'), Some(chars("one")));
+        assert_eq!(get('"'), Some(chars("two")));
+        assert_eq!(get('b'), None);
+    }
+
+    #[test]
+    fn appends_lines_and_text() {
+        let lines = |text: &str| Yank { text: text.into(), linewise: true };
+        let mut registers = Registers::new(Clipboard::Osc52);
+        registers.store(Some('a'), lines("foo\n"), true).unwrap();
+        registers.store(Some('A'), chars("bar"), true).unwrap();
+        registers.store(Some('b'), chars("foo"), true).unwrap();
+        registers.store(Some('B'), lines("bar\n"), true).unwrap();
+        registers.store(Some('B'), lines("baz\n"), true).unwrap();
+
+        let get = |register| registers.get(Some(register));
+        assert_eq!(get('a'), Some(lines("foo\nbar\n")));
+        assert_eq!(get('b'), Some(lines("foo\nbar\nbaz\n")));
+        // yanks into named registers leave the last yank alone.
+        assert_eq!(get('0'), None);
+    }
+
+    #[test]
+    fn copies_through_the_terminal() {
+        let mut registers = Registers::new(Clipboard::Osc52);
+        registers.store(Some('+'), chars("hi"), true).unwrap();
+        assert_eq!(registers.take_escape().as_deref(), Some("\x1b]52;c;aGk=\x07"));
+        assert_eq!(registers.take_escape(), None);
+        assert_eq!(registers.get(Some('*')), Some(chars("hi")));
+    }
+    #[cfg(unix)]
+    #[test]
+    fn pastes_in_the_background() {
+        let paste = ["printf", "a\\n"].map(String::from).to_vec();
+        let registers = Registers::new(Clipboard::Command { copy: vec!["true".into()], paste });
+        assert!(registers.is_pasted(Some('+')));
+        assert!(!registers.is_pasted(Some('a')));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        registers.paste(move |yank| tx.send(yank.unwrap()).unwrap());
+        let yank = rx.recv_timeout(PASTE_TIMEOUT * 2).unwrap();
+        assert_eq!(yank, Yank { text: "a\n".into(), linewise: true });
+    }
+}
@@ -0,0 +1,86 @@
+use editor::{Point, Search};
+use selector::Selector;
+
+#[derive(Debug, Clone)]
+pub enum SearchCommand {
+    /// Opens the prompt to search forward, or backward with `?`.
+    Open {
+        backward: bool,
+    },
+    Edit(selector::Command<()>),
+    Commit,
+    Cancel,
+    /// Goes to the next match of the last search, or the previous one if `reverse`.
+    Repeat {
+        reverse: bool,
+    },
+    ClearHighlight,
+}
+
+/// The `/` and `?` prompt, which searches as the pattern is typed.
+#[derive(Debug)]
+pub struct SearchPrompt {
+    pub selector: Selector<()>,
+    pub backward: bool,
+    /// Where the cursor was when the prompt opened, to search from and to go back to if the
+    /// prompt is cancelled.
+    pub origin: Point,
+    /// The search before this one, restored if the prompt is cancelled.
+    pub previous: Option<Search>,
+}
+
+impl SearchPrompt {
+    pub fn new() -> Self {
+        let selector = Selector::new("/");
+        Self { selector, backward: false, origin: Point::default(), previous: None }
+    }
+
+    pub fn open(&mut self, backward: bool, origin: Point, previous: Option<Search>) {
+        self.selector.reset();
+        self.selector.query_prefix = if backward { "?" } else { "/" };
+        self.backward = backward;
+        self.origin = origin;
+        self.previous = previous;
+    }
+}
+
+/// An answer to the question asked at each match of a substitute command with the `c` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirm {
+    /// Replaces this match, `y`.
+    Yes,
+    /// Skips this match, `n`.
+    No,
+    /// Replaces this and every remaining match, `a`.
+    All,
+    /// Replaces this match and stops, `l`.
+    Last,
+    /// Stops without replacing this match, `q` or escape.
+    Quit,
+}
+
+impl Confirm {
+    pub fn from_key(c: char) -> Option<Self> {
+        match c {
+            'y' => Some(Confirm::Yes),
+            'n' => Some(Confirm::No),
+            'a' => Some(Confirm::All),
+            'l' => Some(Confirm::Last),
+            'q' => Some(Confirm::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a command palette query is a substitute command, such as `%s/a/b/g`, which unlike
+/// other commands has no space between its name and its arguments.
+pub fn is_substitute(query: &str) -> bool {
+    let rest = query
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit() || ".,$%+-".contains(c));
+    let rest = rest
+        .strip_prefix("substitute")
+        .or_else(|| rest.strip_prefix('s'));
+    rest.and_then(|rest| rest.chars().next())
+        .is_some_and(|c| !c.is_alphanumeric() && !c.is_whitespace() && c != '\\')
+}
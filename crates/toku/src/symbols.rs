@@ -0,0 +1,94 @@
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+
+use editor::{EditorId, Point};
+use selector::Selector;
+use syntax::Symbol;
+use tore::CursorPoint;
+
+/// The definitions in an editor's buffer, to pick one to go to by typing part of its name.
+#[derive(Debug)]
+pub struct SymbolPicker {
+    pub selector: Selector<usize>,
+    /// The editor whose cursor goes to the picked symbol.
+    pub editor_id: Option<EditorId>,
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolPicker {
+    pub fn new() -> Self {
+        let selector = Selector::new("@");
+        Self { selector, editor_id: None, symbols: vec![] }
+    }
+
+    pub fn open(&mut self, editor_id: EditorId, symbols: Vec<Symbol>) {
+        self.editor_id = Some(editor_id);
+        self.symbols = symbols;
+        self.selector.reset();
+        self.update();
+    }
+
+    pub fn command(&mut self, command: selector::Command<usize>) {
+        let update = matches!(command, selector::Command::Insert(_) | selector::Command::Delete(_));
+        self.selector.command(command);
+        if update {
+            self.update();
+        }
+    }
+
+    /// Where the focused symbol starts.
+    pub fn focused(&self) -> Option<Point> {
+        self.selector.focused.map(|index| self.symbols[index].point)
+    }
+
+    /// Filters the symbols by the query, best matches first. With no query they are all listed
+    /// in the order they are defined.
+    fn update(&mut self) {
+        use fuzzy_matcher::skim::SkimMatcherV2;
+        use fuzzy_matcher::FuzzyMatcher;
+
+        let matcher = SkimMatcherV2::default();
+        let query = self.selector.query.trim();
+        let mut results: Vec<_> = self
+            .symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(index, symbol)| {
+                let score = matcher.fuzzy_match(&symbol.name, query)?;
+                Some((score, index))
+            })
+            .collect();
+        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        let results = results.into_iter().map(|(_, index)| index).collect();
+        self.selector
+            .command(selector::Command::SetEntries(results));
+    }
+
+    pub fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        theme: &ui::Theme,
+    ) -> (CursorPoint, SetCursorStyle) {
+        let widget = ui::SelectorPane::new(theme, &self.selector);
+        widget.render(buf, area, &self.selector.entries, |area, buf, index| {
+            self.render_result(area, buf, index)
+        })
+    }
+
+    /// A symbol's name, with its kind and line number at the right.
+    fn render_result(&self, area: tui::Rect, buf: &mut tui::Buffer, index: usize) {
+        use bstr::ByteSlice;
+        let symbol = &self.symbols[index];
+        let details = format!(" {} {}", symbol.kind, symbol.point.line + 1);
+        // the area of an unfocused result starts after a space but is as wide as the results.
+        let width = area.width.saturating_sub(1);
+        let name_width = (width as usize).saturating_sub(details.chars().count());
+        let content = format!("{:name_width$}{}", symbol.name, details);
+        let mut graphemes = content.as_bytes().as_bstr().graphemes();
+        for x in area.left()..area.left() + width {
+            let symbol = graphemes.next().unwrap_or(" ");
+            buf.get_mut(x, area.top()).set_symbol(symbol);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use editor::{Buffer, BufferContents, Indent};
+
+/// What an editor pane draws besides the text of its buffer and its highlights.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decorations {
+    /// How many rows the scopes around the top of the view may take.
+    pub context_lines: usize,
+    /// Whether to color brackets by how deeply they nest.
+    pub rainbow_brackets: bool,
+    /// Whether to draw a line down each level of indentation, brighter for the cursor's block.
+    pub indent_guides: bool,
+}
+
+/// The palette colors of nested brackets, from the outermost, over again past the last.
+pub(crate) const RAINBOW: [&str; 6] = ["yellow", "purple", "blue", "orange", "green", "aqua"];
+
+/// How deeply the brackets in ranges of lines nest, by char offset. Only the brackets in the
+/// syntax tree are counted, so a buffer without one has none.
+pub(crate) fn bracket_depths(buffer: &Buffer, lines: &[Range<usize>]) -> HashMap<usize, usize> {
+    let Some(syntax) = buffer.syntax.as_deref() else {
+        return HashMap::new();
+    };
+    let contents = &buffer.contents;
+    lines
+        .iter()
+        .flat_map(|lines| {
+            syntax.brackets(contents.line_to_byte(lines.start)..contents.line_to_byte(lines.end))
+        })
+        .map(|(offset, depth)| (contents.byte_to_char(offset), depth))
+        .collect()
+}
+
+/// The lines drawn down the indentation of a range of lines, one for each level.
+#[derive(Debug)]
+pub(crate) struct IndentGuides {
+    /// The columns that a level of indentation takes.
+    width: usize,
+    /// The first line of the range.
+    first: usize,
+    /// How deep the guides go on each line of the range.
+    indents: Vec<usize>,
+    /// The column of the guide of the block that the cursor is in, and the lines of the block.
+    current: Option<(usize, Range<usize>)>,
+}
+
+impl IndentGuides {
+    /// The guides on `lines` of a buffer, with the block around the cursor's line among them.
+    pub(crate) fn new(buffer: &Buffer, cursor_line: usize, lines: Range<usize>) -> Self {
+        let contents = &buffer.contents;
+        let width = match buffer.indent {
+            Indent::Tabs => 1,
+            Indent::Spaces(width) => width.max(1),
+        };
+        let lines = lines.start..lines.end.min(contents.len_lines());
+        let indents = guide_indents(contents, lines.clone());
+        let mut guides = Self { width, first: lines.start, indents, current: None };
+        if !lines.contains(&cursor_line) {
+            return guides;
+        }
+
+        let mut indent = guides.indent(cursor_line);
+        let mut start = cursor_line;
+        // on the line that opens a block, the block is the one it opens.
+        let next = (cursor_line + 1..contents.len_lines()).find(|line| !is_blank(contents, *line));
+        if let Some(next) = next.filter(|next| contents.first_non_blank(*next) > indent) {
+            indent = contents.first_non_blank(next);
+            start = cursor_line + 1;
+        }
+        guides.current = (indent > 0).then(|| {
+            let column = (indent - 1) / width * width;
+            let inside = |line: &usize| guides.indent(*line) > column;
+            let first = (lines.start..start).rev().take_while(inside).last();
+            let end = (start..lines.end).find(|line| !inside(line));
+            (column, first.unwrap_or(start)..end.unwrap_or(lines.end))
+        });
+        guides
+    }
+
+    /// The columns of the guides on a line, each with whether it is the current block's.
+    pub(crate) fn columns(&self, line: usize) -> impl Iterator<Item = (usize, bool)> + '_ {
+        (0..self.indent(line))
+            .step_by(self.width)
+            .map(move |column| {
+                let current = self.current.as_ref();
+                let is_current =
+                    current.is_some_and(|(at, lines)| *at == column && lines.contains(&line));
+                (column, is_current)
+            })
+    }
+
+    /// How deep the guides on a line go, or 0 for a line outside the range.
+    fn indent(&self, line: usize) -> usize {
+        let index = line.checked_sub(self.first);
+        index
+            .and_then(|index| self.indents.get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+fn is_blank(contents: &BufferContents, line: usize) -> bool {
+    contents.first_non_blank(line) == contents.line_len(line)
+}
+
+/// How deep the guides on each of a range of lines go: to its text, or for a blank line, as deep
+/// as on the lines with text around it so that they run through it.
+fn guide_indents(contents: &BufferContents, lines: Range<usize>) -> Vec<usize> {
+    let text_indent =
+        |line: usize| (!is_blank(contents, line)).then(|| contents.first_non_blank(line));
+    let texts: Vec<_> = lines.clone().map(text_indent).collect();
+    // the text nearest to the range is only looked for past its ends once.
+    let mut below = (lines.end..contents.len_lines()).find_map(text_indent);
+    let mut indents = vec![0; texts.len()];
+    for (indent, text) in indents.iter_mut().zip(&texts).rev() {
+        below = text.or(below);
+        *indent = below.unwrap_or(0);
+    }
+    let mut above = (0..lines.start).rev().find_map(text_indent);
+    for (indent, text) in indents.iter_mut().zip(&texts) {
+        match text {
+            Some(text) => above = Some(*text),
+            None => *indent = (*indent).max(above.unwrap_or(0)),
+        }
+    }
+    indents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guides_the_cursors_block() {
+        let text = "fn a() {\n    if b {\n        c;\n\n        d;\n    }\n}\n";
+        let mut buffer = Buffer::empty(editor::BufferId::default());
+        buffer.insert(0, text);
+        let contents = &buffer.contents;
+        let lines = 0..contents.len_lines();
+
+        let guides = IndentGuides::new(&buffer, 2, lines.clone());
+        let columns = |line| guides.columns(line).collect::<Vec<_>>();
+        assert_eq!(columns(0), []);
+        assert_eq!(columns(1), [(0, false)]);
+        // the blank line is in the block that it is in the middle of.
+        assert_eq!(columns(3), [(0, false), (4, true)]);
+        assert_eq!(columns(5), [(0, false)]);
+
+        // from the line that opens it.
+        let guides = IndentGuides::new(&buffer, 1, lines.clone());
+        assert_eq!(guides.current, Some((4, 2..5)));
+        let guides = IndentGuides::new(&buffer, 0, lines);
+        assert_eq!(guides.current, Some((0, 1..6)));
+
+        // a blank line at the edge of the range runs the guides of the lines past it.
+        let guides = IndentGuides::new(&buffer, 4, 3..5);
+        assert_eq!(guides.columns(3).collect::<Vec<_>>(), [(0, false), (4, true)]);
+        assert_eq!(guides.current, Some((4, 3..5)));
+    }
+}
@@ -1,40 +1,143 @@
 use crossterm::cursor::SetCursorStyle;
-use editor::{Buffer, Editor};
+use editor::{Buffer, Editor, Search};
 use ratatui::prelude as tui;
 use tore::CursorPoint;
 
-use crate::Theme;
+use crate::decorations::{bracket_depths, IndentGuides, RAINBOW};
+use crate::{Decorations, Theme};
 
 pub struct EditorPane<'a> {
     theme: &'a Theme,
     buffer: &'a Buffer,
     editor: &'a Editor,
+    search: Option<&'a Search>,
+    decorations: Decorations,
 }
 
 impl<'a> EditorPane<'a> {
-    pub fn new(theme: &'a Theme, buffer: &'a Buffer, editor: &'a Editor) -> Self {
-        Self { theme, buffer, editor }
+    pub fn new(
+        theme: &'a Theme,
+        buffer: &'a Buffer,
+        editor: &'a Editor,
+        search: Option<&'a Search>,
+    ) -> Self {
+        Self { theme, buffer, editor, search, decorations: Decorations::default() }
+    }
+
+    /// Draws decorations over the text, such as the first lines of the scopes that the top of
+    /// the view is in, pinned above it.
+    pub fn decorations(mut self, decorations: Decorations) -> Self {
+        self.decorations = decorations;
+        self
     }
 
     fn screen_offset(&self, dims: tui::Rect) -> editor::Point {
         let cursor = self.editor.cursor;
         let width: usize = dims.width.into();
-        let height: usize = dims.height.into();
         let column = if cursor.column >= width {
             cursor.column - width
         } else {
             0
         };
-        let line = if cursor.line >= height {
-            cursor.line - height
-        } else {
-            0
-        };
+        // the first line in view, with the cursor on the last row once it is past the first screen
+        // of lines shown.
+        let folds = &self.editor.folds;
+        let mut line = folds.shown_line(cursor.line);
+        for _ in 1..dims.height {
+            match folds.prev_shown(line) {
+                Some(prev) => line = prev,
+                None => break,
+            }
+        }
         editor::Point { line, column }
     }
 
-    fn offset_cursor(&self, _area: tui::Rect, cursor: tore::Point) -> CursorPoint {
-        CursorPoint { x: cursor.column as u16, y: cursor.line as u16 }
+    /// The lines shown on each row, from the first in view, skipping the lines hidden in folds.
+    fn shown_lines(&self, first: usize) -> impl Iterator<Item = usize> + '_ {
+        let len = self.buffer.contents.len_lines();
+        let lines =
+            std::iter::successors(Some(first), |line| Some(self.editor.folds.next_shown(*line)));
+        lines.take_while(move |line| *line < len)
+    }
+
+    /// The first lines of the scopes around the top of the view, outermost first, and only the
+    /// innermost if there are more than fit. They cover the lines shown on the first rows, so
+    /// the scopes are those around the first line left uncovered, and never the cursor's line.
+    fn context(&self, shown: &[usize]) -> Vec<usize> {
+        let Some(syntax) = self.buffer.syntax.as_deref() else {
+            return vec![];
+        };
+        let cursor_line = self.editor.folds.shown_line(self.editor.cursor.line);
+        let cursor_row = shown.iter().position(|line| *line == cursor_line);
+        let max = self
+            .decorations
+            .context_lines
+            .min(cursor_row.unwrap_or(shown.len()));
+        let mut context = vec![];
+        for _ in 0..=max {
+            let Some(&top) = shown.get(context.len()) else {
+                break;
+            };
+            let mut starts: Vec<_> = syntax
+                .scopes(&self.buffer.contents, top)
+                .into_iter()
+                .map(|scope| scope.start)
+                .filter(|start| *start < top)
+                .collect();
+            starts.dedup();
+            let starts = starts.split_off(starts.len().saturating_sub(max));
+            if starts == context {
+                break;
+            }
+            context = starts;
+        }
+        context
+    }
+
+    /// The lines on each row: the context, then the lines in view that it does not cover.
+    fn rows(&self, dims: tui::Rect) -> Vec<(usize, bool)> {
+        let first = self.screen_offset(dims).line;
+        let shown: Vec<_> = self.shown_lines(first).take(dims.height.into()).collect();
+        let context = self.context(&shown);
+        let uncovered = shown
+            .into_iter()
+            .skip(context.len())
+            .map(|line| (line, false));
+        context
+            .into_iter()
+            .map(|line| (line, true))
+            .chain(uncovered)
+            .collect()
+    }
+
+    /// The parts of the buffer shown in `dims` that have not been highlighted yet, to request from
+    /// the syntax worker as the pane scrolls.
+    pub fn missing_highlights(&self, dims: tui::Rect) -> Vec<std::ops::Range<usize>> {
+        let contents = &self.buffer.contents;
+        let rows = self.rows(dims);
+        let first = self.screen_offset(dims).line.min(contents.len_lines());
+        let last = rows
+            .last()
+            .map_or(first, |(line, _)| line + 1)
+            .min(contents.len_lines());
+        let mut missing = vec![];
+        for (line, _) in rows.iter().take_while(|(_, context)| *context) {
+            let line = contents.line_to_byte(*line)..contents.line_to_byte(line + 1);
+            missing.extend(self.buffer.highlights.missing(line));
+        }
+        let visible = contents.line_to_byte(first)..contents.line_to_byte(last);
+        missing.extend(self.buffer.highlights.missing(visible));
+        missing
+    }
+
+    fn offset_cursor(&self, area: tui::Rect, cursor: tore::Point) -> CursorPoint {
+        let first = self.screen_offset(area).line;
+        let line = self.editor.folds.shown_line(cursor.line);
+        let row = self
+            .shown_lines(first)
+            .take_while(|shown| *shown < line)
+            .count();
+        CursorPoint { x: cursor.column as u16, y: row as u16 }
     }
 
     #[tracing::instrument(skip(self, buf))]
@@ -42,15 +145,84 @@ impl<'a> EditorPane<'a> {
         use bstr::ByteSlice;
 
         let offset = self.screen_offset(dims);
-        let mut lines = self.buffer.contents.lines_at(offset.line);
+        let (selections, _) = self.editor.selections();
+        let visual = self.editor.mode.is_visual();
+        // the terminal shows only the primary cursor, so the others are drawn reversed.
+        let is_secondary_cursor = |point: editor::Point| {
+            self.editor
+                .secondaries
+                .iter()
+                .any(|selection| selection.head == point)
+        };
+        let selection_bg = match self.editor.mode {
+            editor::Mode::VisualLine => self.theme.palette("bg_visual_green"),
+            editor::Mode::VisualBlock => self.theme.palette("bg_visual_yellow"),
+            _ => self.theme.palette("bg_visual_blue"),
+        };
+        let search_style = match (self.theme.palette("bg_yellow"), self.theme.palette("bg0")) {
+            (Some(bg), Some(fg)) => tui::Style::default().bg(bg.into()).fg(fg.into()),
+            _ => tui::Style::default(),
+        };
+        let fold_style = match self.theme.scheme("comment") {
+            Some(color) => tui::Style::default().fg(color.0),
+            None => tui::Style::default(),
+        };
+        let bracket_style = match self.theme.palette("bg4") {
+            Some(bg) => tui::Style::default().bg(bg.into()),
+            None => tui::Style::default().add_modifier(tui::Modifier::UNDERLINED),
+        };
+        let context_style = match self.theme.palette("bg1") {
+            Some(bg) => tui::Style::default().bg(bg.into()),
+            None => tui::Style::default(),
+        };
+        let rows = self.rows(dims);
+        let visible_lines = offset.line..rows.last().map_or(offset.line, |(line, _)| line + 1);
+        let matches = self
+            .search
+            .map(|search| search.matches_in_lines(&self.buffer.contents, visible_lines.clone()))
+            .unwrap_or_default();
+        // the bracket under the cursor and the one it pairs with.
+        let brackets = self
+            .editor
+            .matching_brackets(self.buffer, visible_lines.clone());
+        let rainbow: Vec<_> = RAINBOW
+            .iter()
+            .filter_map(|name| self.theme.palette(name))
+            .filter(|_| self.decorations.rainbow_brackets)
+            .collect();
+        let bracket_depths = match rainbow.is_empty() {
+            true => Default::default(),
+            false => {
+                let context = rows.iter().filter(|(_, context)| *context);
+                let mut lines: Vec<_> = context.map(|(line, _)| *line..line + 1).collect();
+                lines.push(visible_lines.clone());
+                bracket_depths(self.buffer, &lines)
+            }
+        };
+        let guides = self
+            .decorations
+            .indent_guides
+            .then(|| IndentGuides::new(self.buffer, self.editor.cursor.line, visible_lines));
+        let guide_style = |current: bool| {
+            let color = self.theme.palette(if current { "grey1" } else { "bg5" });
+            color.map_or_else(tui::Style::default, |fg| tui::Style::default().fg(fg.into()))
+        };
+        let mut rows = rows.into_iter();
         let x = dims.left();
-        for (yoffset, y) in (dims.top()..dims.bottom()).enumerate() {
-            if let Some(line) = lines.next() {
-                let line_offset = self.buffer.contents.line_to_byte(offset.line + yoffset);
+        for y in dims.top()..dims.bottom() {
+            if let Some((line_idx, context)) = rows.next() {
+                // the context is shown as it is written, without the cursors and selections.
+                if context {
+                    buf.set_style(tui::Rect::new(x, y, dims.width, 1), context_style);
+                }
+                let line = self.buffer.contents.line(line_idx);
+                let line_offset = self.buffer.contents.line_to_byte(line_idx);
                 let mut xoffset = 0;
+                let line_char = self.buffer.contents.line_to_char(line_idx);
+                let mut column = 0;
                 'row_loop: for chunk in line.chunks() {
                     for (start, end, grapheme) in chunk.as_bytes().as_bstr().grapheme_indices() {
-                        if x + xoffset >= dims.width || grapheme == "\n" {
+                        if x + xoffset >= dims.right() || grapheme == "\n" {
                             break 'row_loop;
                         }
 
@@ -62,10 +234,61 @@ impl<'a> EditorPane<'a> {
                             }
                         }
 
+                        let point = editor::Point { line: line_idx, column };
+                        let char_idx = line_char + column;
+                        if let Some(depth) = bracket_depths.get(&char_idx) {
+                            cell.set_fg(rainbow[depth % rainbow.len()].into());
+                        }
+                        if !context {
+                            if matches.iter().any(|m| m.contains(&char_idx)) {
+                                cell.set_style(search_style);
+                            }
+                            if brackets.is_some_and(|(a, b)| point == a || point == b) {
+                                cell.set_style(bracket_style);
+                            }
+                            if let Some(bg) = selection_bg.filter(|_| visual) {
+                                if selections
+                                    .iter()
+                                    .any(|s| s.contains(&self.editor.mode, point))
+                                {
+                                    cell.set_bg(bg.into());
+                                }
+                            }
+                            if is_secondary_cursor(point) {
+                                let reversed =
+                                    tui::Style::default().add_modifier(tui::Modifier::REVERSED);
+                                cell.set_style(reversed);
+                            }
+                        }
+
                         cell.set_symbol(grapheme);
                         xoffset += 1;
+                        column += grapheme.chars().count();
+                    }
+                }
+                // the guides go over the indentation, and past the end of blank lines.
+                if let Some(guides) = guides.as_ref().filter(|_| !context) {
+                    for (column, current) in guides.columns(line_idx) {
+                        if column >= dims.width.into() {
+                            break;
+                        }
+                        let cell = buf.get_mut(x + column as u16, y);
+                        cell.set_symbol("│").set_style(guide_style(current));
                     }
                 }
+                // a cursor past the end of the line, as in insert mode.
+                let point = editor::Point { line: line_idx, column };
+                if !context && is_secondary_cursor(point) && x + xoffset < dims.right() {
+                    let reversed = tui::Style::default().add_modifier(tui::Modifier::REVERSED);
+                    buf.get_mut(x + xoffset, y).set_style(reversed);
+                }
+                // a closed fold shows its first line and how many lines it holds, if there is room.
+                let fold = self.editor.folds.closed_at(line_idx);
+                if let Some(fold) = fold.filter(|_| x + xoffset < dims.right()) {
+                    let placeholder = format!(" ··· {} lines", fold.len());
+                    let width = dims.right() - (x + xoffset);
+                    buf.set_stringn(x + xoffset, y, placeholder, width.into(), fold_style);
+                }
             } else {
                 buf.get_mut(x, y).set_char('~');
             }
@@ -73,9 +296,34 @@ impl<'a> EditorPane<'a> {
 
         let cursor_pos = self.offset_cursor(dims, self.editor.cursor);
         let cursor_style = match self.editor.mode {
-            editor::Mode::Normal => SetCursorStyle::BlinkingBlock,
             editor::Mode::Insert => SetCursorStyle::BlinkingBar,
+            _ => SetCursorStyle::BlinkingBlock,
         };
         (cursor_pos, cursor_style)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_closed_folds_in_the_pane() {
+        let mut buffer = Buffer::empty(editor::BufferId::default());
+        buffer.insert(0, "abcdef\n    g\n");
+        let mut editor = Editor::new(editor::EditorId::default(), buffer.id);
+        editor.fold(&buffer, editor::FoldCommand::Close);
+        let theme = Theme::default();
+        // a pane beside another, with the first line of the fold as wide as it.
+        let area = tui::Rect::new(4, 0, 6, 2);
+        let mut buf = tui::Buffer::empty(tui::Rect::new(0, 0, 10, 2));
+        EditorPane::new(&theme, &buffer, &editor, None).render(&mut buf, area);
+        let row: String = (0..10).map(|x| buf.get(x, 0).symbol.clone()).collect();
+        assert_eq!(row, "    abcdef");
+
+        let area = tui::Rect::new(0, 0, 10, 2);
+        EditorPane::new(&theme, &buffer, &editor, None).render(&mut buf, area);
+        let row: String = (0..10).map(|x| buf.get(x, 0).symbol.clone()).collect();
+        assert_eq!(row, "abcdef ···");
+    }
+}
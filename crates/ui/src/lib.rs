@@ -1,7 +1,13 @@
+mod decorations;
 mod editor_pane;
+mod prompt;
 mod selector_pane;
+mod status_line;
 mod theme;
 
+pub use decorations::Decorations;
 pub use editor_pane::EditorPane;
+pub use prompt::Prompt;
 pub use selector_pane::SelectorPane;
+pub use status_line::{Message, StatusLine};
 pub use theme::Theme;
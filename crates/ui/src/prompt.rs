@@ -0,0 +1,47 @@
+use bstr::ByteSlice;
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+
+use selector::Selector;
+use tore::CursorPoint;
+
+use crate::theme::Color;
+
+#[derive(Debug)]
+struct Theme {
+    bg: Color,
+    fg: Color,
+}
+
+/// A selector's query on a single line, as the search prompt shows in place of the status line.
+pub struct Prompt<'a, Id: Eq + Copy> {
+    theme: Theme,
+    selector: &'a Selector<Id>,
+}
+
+impl<'a, Id: Eq + Copy> Prompt<'a, Id> {
+    pub fn new(theme: &crate::Theme, selector: &'a Selector<Id>) -> Self {
+        let bg = theme.palette("bg0").unwrap();
+        let fg = theme.palette("fg0").unwrap();
+        Self { theme: Theme { bg, fg }, selector }
+    }
+
+    #[tracing::instrument(skip(self, buf))]
+    pub fn render(self, buf: &mut tui::Buffer, area: tui::Rect) -> (CursorPoint, SetCursorStyle) {
+        let style = tui::Style::reset()
+            .fg(self.theme.fg.into())
+            .bg(self.theme.bg.into());
+        let content = format!("{}{}", self.selector.query_prefix, self.selector.query);
+        let mut graphemes = content.as_bytes().as_bstr().graphemes();
+        for x in area.left()..area.right() {
+            let symbol = graphemes.next().unwrap_or(" ");
+            buf.get_mut(x, area.top())
+                .set_style(style)
+                .set_symbol(symbol);
+        }
+
+        let prefix_len = self.selector.query_prefix.chars().count();
+        let x = area.left() + (prefix_len + self.selector.cursor.column) as u16;
+        (CursorPoint { x, y: area.top() }, SetCursorStyle::BlinkingBar)
+    }
+}
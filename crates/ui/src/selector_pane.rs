@@ -12,7 +12,6 @@ pub struct Theme {
     bg: Color,
     fg: Color,
     bg_selected: Color,
-    fg_highlight: Color,
 }
 
 // pub trait Renderer<Id> {
@@ -31,8 +30,7 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
         let bg = theme.palette("bg0").unwrap();
         let bg_selected = theme.palette("bg1").unwrap();
         let fg = theme.palette("fg0").unwrap();
-        let fg_highlight = theme.palette("yellow").unwrap();
-        let theme = Theme { bg, fg, bg_selected, fg_highlight };
+        let theme = Theme { bg, fg, bg_selected };
         Self { theme, selector }
     }
 
@@ -41,11 +39,11 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
         self,
         buf: &mut tui::Buffer,
         area: tui::Rect,
-        results: &Vec<Id>,
+        results: &[Id],
         render: R,
     ) -> (CursorPoint, SetCursorStyle)
     where
-        R: Fn(tui::Rect, &mut tui::Buffer, Id) -> (),
+        R: Fn(tui::Rect, &mut tui::Buffer, Id),
     {
         let area = self.layout(area, results.len());
         let (query_area, results_area) = Self::split_sections(area);
@@ -166,14 +164,9 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
         }
     }
 
-    fn render_results<R>(
-        &self,
-        buf: &mut tui::Buffer,
-        area: tui::Rect,
-        results: &Vec<Id>,
-        render: R,
-    ) where
-        R: Fn(tui::Rect, &mut tui::Buffer, Id) -> (),
+    fn render_results<R>(&self, buf: &mut tui::Buffer, area: tui::Rect, results: &[Id], render: R)
+    where
+        R: Fn(tui::Rect, &mut tui::Buffer, Id),
     {
         let style = tui::Style::reset()
             .fg(self.theme.fg.into())
@@ -184,7 +177,7 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
             return;
         }
 
-        let mut results = results.into_iter();
+        let mut results = results.iter();
         if !has_results {
             let mut graphemes = " No matches".as_bytes().as_bstr().graphemes();
             for x in area.left()..area.right() {
@@ -207,6 +200,10 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
                     }
                     (_, None) => (" ", self.theme.bg),
                 };
+                let style = style.bg(bg.into());
+                for x in area.left()..area.right() {
+                    buf.get_mut(x, y).set_style(style).set_symbol(" ");
+                }
                 if let Some(result) = result {
                     let graphemes = prefix.as_bytes().as_bstr().graphemes();
                     let mut len = 0;
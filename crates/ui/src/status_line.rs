@@ -0,0 +1,97 @@
+use bstr::ByteSlice;
+use editor::{Buffer, Editor};
+use ratatui::prelude as tui;
+
+use crate::theme::Color;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Info(String),
+    Error(String),
+}
+
+/// The colors of the status line, each left to the terminal if the theme has none.
+#[derive(Debug)]
+struct Theme {
+    bg: Option<Color>,
+    fg: Option<Color>,
+    fg_error: Option<Color>,
+}
+
+pub struct StatusLine<'a> {
+    theme: Theme,
+    buffer: &'a Buffer,
+    editor: &'a Editor,
+    message: Option<&'a Message>,
+}
+
+impl<'a> StatusLine<'a> {
+    pub fn new(
+        theme: &crate::Theme,
+        buffer: &'a Buffer,
+        editor: &'a Editor,
+        message: Option<&'a Message>,
+    ) -> Self {
+        let bg = theme.palette("bg_statusline1");
+        let fg = theme.palette("fg0");
+        let fg_error = theme.palette("red");
+        let theme = Theme { bg, fg, fg_error };
+        Self { theme, buffer, editor, message }
+    }
+
+    /// Splits the terminal into the area for panes and the status line's row below it, if there
+    /// is room for both.
+    pub fn split(area: tui::Rect) -> (tui::Rect, Option<tui::Rect>) {
+        match area.height {
+            0 | 1 => (area, None),
+            h => {
+                let status_area = tui::Rect::new(area.x, area.y + h - 1, area.width, 1);
+                (tui::Rect::new(area.x, area.y, area.width, h - 1), Some(status_area))
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self, buf))]
+    pub fn render(self, buf: &mut tui::Buffer, area: tui::Rect) {
+        let color = |color: Option<Color>| color.map_or(tui::Color::Reset, Into::into);
+        let style = tui::Style::reset()
+            .fg(color(self.theme.fg))
+            .bg(color(self.theme.bg));
+
+        let (content, style) = match self.message {
+            Some(Message::Info(msg)) => (msg.clone(), style),
+            Some(Message::Error(msg)) => (msg.clone(), style.fg(color(self.theme.fg_error))),
+            None => {
+                let mode = match self.editor.mode {
+                    editor::Mode::Normal => "NORMAL",
+                    editor::Mode::Insert => "INSERT",
+                    editor::Mode::Visual => "VISUAL",
+                    editor::Mode::VisualLine => "V-LINE",
+                    editor::Mode::VisualBlock => "V-BLOCK",
+                };
+                let path = self.buffer.name();
+                let modified = if self.buffer.is_dirty() { " [+]" } else { "" };
+                let cursors = match self.editor.secondaries.len() {
+                    0 => String::new(),
+                    n => format!(" ({} cursors)", n + 1),
+                };
+                (format!(" {} {}{}{}", mode, path, modified, cursors), style)
+            }
+        };
+
+        let mut graphemes = content.as_bytes().as_bstr().graphemes();
+        for x in area.left()..area.right() {
+            let symbol = graphemes.next().unwrap_or(" ");
+            buf.get_mut(x, area.top())
+                .set_style(style)
+                .set_symbol(symbol);
+        }
+
+        // keys typed towards a normal mode command, right-aligned like vim's showcmd.
+        let keys = self.editor.pending.keys();
+        let width = keys.chars().count() as u16;
+        if self.message.is_none() && width > 0 && width + 1 < area.width {
+            buf.set_string(area.right() - width - 1, area.top(), keys, style);
+        }
+    }
+}
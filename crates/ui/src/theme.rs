@@ -25,8 +25,16 @@ pub struct Theme {
 }
 
 impl Theme {
+    /// The color of a highlight name, falling back to its less specific names: from
+    /// `keyword.control.return` to `keyword.control`, then `keyword`.
     pub(crate) fn scheme(&self, name: &str) -> Option<Color> {
-        self.scheme.get(name).map(|n| self.palette[n])
+        let mut name = name;
+        loop {
+            if let Some(color) = self.scheme.get(name) {
+                return Some(self.palette[color]);
+            }
+            name = &name[..name.rfind('.')?];
+        }
     }
 
     pub(crate) fn palette(&self, name: &str) -> Option<Color> {
@@ -103,3 +111,23 @@ impl Default for Theme {
         Self { palette, scheme }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheme(name: &str) -> Option<ratatui::style::Color> {
+        Theme::default().scheme(name).map(|color| color.0)
+    }
+
+    #[test]
+    fn falls_back_to_less_specific_names() {
+        let palette = |name| Theme::default().palette(name).map(|color| color.0);
+        assert_eq!(scheme("keyword"), palette("red"));
+        assert_eq!(scheme("keyword.control.return"), palette("red"));
+        assert_eq!(scheme("function.macro"), palette("aqua"));
+        assert_eq!(scheme("function.method"), palette("green"));
+        assert_eq!(scheme("none"), None);
+        assert_eq!(scheme(""), None);
+    }
+}